@@ -0,0 +1,146 @@
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::audio::engine::AudioCommand;
+use crate::audio::{chapters, queue, AudioHandle};
+use crate::db::SearchCache;
+use crate::downloads::{find_audio, DownloadManager};
+use crate::error::AppError;
+use crate::models::{Alarm, LocalTime, Track};
+
+/// How often the clock is read; well under a minute so none is missed.
+const TICK: Duration = Duration::from_secs(15);
+/// How long to wait on YouTube before taking the connection to be down.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Whether `alarm` goes off at `now`.
+pub fn is_due(alarm: &Alarm, now: &LocalTime) -> bool {
+    alarm.enabled
+        && alarm.hour == now.hour
+        && alarm.minute == now.minute
+        && (alarm.weekdays == 0 || alarm.weekdays & (1 << now.weekday) != 0)
+}
+
+/// `tracks` with the downloaded ones first when `offline`, so an alarm
+/// sounds even without a connection; as they are otherwise.
+pub fn offline_first(tracks: Vec<Track>, offline: bool, downloaded: impl Fn(&Track) -> bool) -> Vec<Track> {
+    if !offline {
+        return tracks;
+    }
+    let (mut local, remote): (Vec<_>, Vec<_>) = tracks.into_iter().partition(|t| downloaded(t));
+    local.extend(remote);
+    local
+}
+
+/// Whether YouTube can be reached, judged by opening a connection to it.
+fn online() -> bool {
+    ("www.youtube.com", 443)
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .is_some_and(|addr| TcpStream::connect_timeout(&addr, PROBE_TIMEOUT).is_ok())
+}
+
+/// Check the alarms every little while for as long as the app runs.
+pub fn start(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        loop {
+            if let Err(e) = tick(&app) {
+                eprintln!("[sunder] alarm check failed: {e}");
+            }
+            tokio::time::sleep(TICK).await;
+        }
+    });
+}
+
+fn tick(app: &AppHandle) -> Result<(), AppError> {
+    let db = app.state::<SearchCache>();
+    let now = db.local_now()?;
+    for alarm in db.list_alarms()?.iter().filter(|a| is_due(a, &now)) {
+        if db.claim_alarm(alarm.id, &now)? {
+            // Checking the connection blocks, so it's kept off the runtime.
+            let (app, alarm) = (app.clone(), alarm.clone());
+            tauri::async_runtime::spawn_blocking(move || go_off(&app, &alarm));
+        }
+    }
+    Ok(())
+}
+
+/// Replace the queue with the alarm's playlist and start it at the alarm's
+/// volume, rising to it from silence when the alarm ramps up.
+fn go_off(app: &AppHandle, alarm: &Alarm) {
+    let tracks = match app.state::<SearchCache>().get_playlist_tracks(alarm.playlist_id) {
+        Ok(tracks) => tracks,
+        Err(e) => {
+            eprintln!("[sunder] alarm {:?}: {e}", alarm.label);
+            return;
+        }
+    };
+    let dir = DownloadManager::dir_for(app);
+    let tracks = offline_first(tracks, !online(), |t| find_audio(&dir, chapters::split_id(&t.id).0).is_some());
+    if tracks.is_empty() {
+        eprintln!("[sunder] alarm {:?}: playlist is empty", alarm.label);
+        return;
+    }
+    eprintln!("[sunder] alarm {:?} going off", alarm.label);
+
+    let audio = app.state::<AudioHandle>();
+    let volume = alarm.volume.clamp(0.0, 1.0);
+    let ramp = Duration::from_secs(alarm.ramp_secs as u64);
+    audio.send(AudioCommand::SetVolume(volume));
+    if !ramp.is_zero() {
+        audio.send(AudioCommand::RampUp(ramp));
+    }
+    {
+        let mut queue = audio.queue.lock().unwrap();
+        queue.set_tracks(tracks, Some(0));
+        queue::play_current(app, &queue, &|cmd| audio.send(cmd));
+    }
+    let _ = app.emit("alarm", alarm);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::track;
+
+    fn alarm(weekdays: u8) -> Alarm {
+        Alarm {
+            id: 1,
+            label: String::new(),
+            playlist_id: 1,
+            hour: 7,
+            minute: 30,
+            weekdays,
+            ramp_secs: 0,
+            volume: 0.8,
+            enabled: true,
+        }
+    }
+
+    fn at(weekday: u8, hour: u8, minute: u8) -> LocalTime {
+        LocalTime { stamp: String::new(), weekday, hour, minute }
+    }
+
+    #[test]
+    fn goes_off_on_its_days() {
+        let weekdays = 0b0111110;
+        assert!(is_due(&alarm(weekdays), &at(1, 7, 30)));
+        assert!(!is_due(&alarm(weekdays), &at(0, 7, 30)));
+        assert!(!is_due(&alarm(weekdays), &at(1, 7, 31)));
+        assert!(is_due(&alarm(0), &at(0, 7, 30)));
+        assert!(!is_due(&Alarm { enabled: false, ..alarm(0) }, &at(0, 7, 30)));
+    }
+
+    #[test]
+    fn downloaded_tracks_play_first_when_offline() {
+        let ids = |offline| {
+            let ordered = offline_first(vec![track("a"), track("b"), track("c")], offline, |t| t.id == "c");
+            ordered.into_iter().map(|t| t.id).collect::<Vec<_>>()
+        };
+        assert_eq!(ids(true), ["c", "a", "b"]);
+        assert_eq!(ids(false), ["a", "b", "c"]);
+    }
+}
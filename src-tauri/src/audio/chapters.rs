@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::Source;
+
+use crate::models::{Chapter, Track};
+
+/// Separates the upload's id from the range in a chapter track's id.
+const RANGE_MARK: char = '#';
+
+/// Id of the track that plays only `chapter` of the upload `video_id`.
+pub fn track_id(video_id: &str, chapter: &Chapter) -> String {
+    format!("{video_id}{RANGE_MARK}{}-{}", chapter.start_ms, chapter.end_ms)
+}
+
+/// The upload a track's audio comes from, and for a chapter track the
+/// range (ms) it plays.
+pub fn split_id(id: &str) -> (&str, Option<(u64, u64)>) {
+    let Some((video_id, range)) = id.split_once(RANGE_MARK) else {
+        return (id, None);
+    };
+    let parsed = range
+        .split_once('-')
+        .and_then(|(a, b)| Some((a.parse().ok()?, b.parse().ok()?)))
+        .filter(|(a, b)| a < b);
+    match parsed {
+        Some(range) => (video_id, Some(range)),
+        None => (id, None),
+    }
+}
+
+/// One track per chapter of `upload`, titled after the chapter and filed
+/// under the upload as their album.
+pub fn chapter_tracks(upload: &Track, chapters: &[Chapter]) -> Vec<Track> {
+    chapters
+        .iter()
+        .map(|c| Track {
+            id: track_id(&upload.id, c),
+            title: c.title.clone(),
+            artist: upload.artist.clone(),
+            thumbnail: upload.thumbnail.clone(),
+            duration_secs: (c.end_ms - c.start_ms) as f64 / 1000.0,
+            album: upload.title.clone(),
+            stream_url: None,
+        })
+        .collect()
+}
+
+/// Index of the chapter playing at `pos_ms`.
+pub fn chapter_at(chapters: &[Chapter], pos_ms: u64) -> Option<usize> {
+    chapters
+        .iter()
+        .rposition(|c| c.start_ms <= pos_ms && pos_ms < c.end_ms)
+}
+
+/// Start of the first chapter after `pos_ms`.
+pub fn next_start(chapters: &[Chapter], pos_ms: u64) -> Option<u64> {
+    chapters.iter().map(|c| c.start_ms).find(|&start| start > pos_ms)
+}
+
+/// Where "previous chapter" goes: back to the start of the current one once
+/// more than `restart_ms` of it has played, otherwise to the one before.
+/// None from the opening moments of the first chapter.
+pub fn previous_start(chapters: &[Chapter], pos_ms: u64, restart_ms: u64) -> Option<u64> {
+    let current = chapters.iter().rposition(|c| c.start_ms <= pos_ms)?;
+    let start = chapters[current].start_ms;
+    if pos_ms - start > restart_ms {
+        return Some(start);
+    }
+    current.checked_sub(1).map(|i| chapters[i].start_ms)
+}
+
+/// Plays `start..end` of a source as if it were the whole of it: positions,
+/// seeks and length are all counted from the start of the range.
+pub struct Window<S: Source<Item = f32>> {
+    inner: S,
+    channels: u64,
+    start: Duration,
+    /// Samples of the range.
+    len: u64,
+    /// Samples into the range; below zero while the source that couldn't
+    /// seek is read through up to the start.
+    pos: i64,
+}
+
+impl<S: Source<Item = f32>> Window<S> {
+    pub fn new(inner: S, start_ms: u64, end_ms: u64) -> Self {
+        let channels = inner.channels().max(1) as u64;
+        let at = |ms: u64| ms.saturating_mul(inner.sample_rate() as u64) / 1000 * channels;
+        let mut window = Self {
+            start: Duration::from_millis(start_ms),
+            len: at(end_ms) - at(start_ms),
+            pos: -(at(start_ms) as i64),
+            channels,
+            inner,
+        };
+        if window.inner.try_seek(window.start).is_ok() {
+            window.pos = 0;
+        }
+        window
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Window<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        while self.pos < 0 {
+            self.inner.next()?;
+            self.pos += 1;
+        }
+        if self.pos as u64 >= self.len {
+            return None;
+        }
+        self.pos += 1;
+        self.inner.next()
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Window<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let frames = self.len / self.channels;
+        Some(Duration::from_secs_f64(frames as f64 / self.inner.sample_rate().max(1) as f64))
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(self.start + pos)?;
+        let frame = (pos.as_secs_f64() * self.inner.sample_rate() as f64).round() as u64;
+        self.pos = (frame * self.channels).min(self.len) as i64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn chapter(start_ms: u64, end_ms: u64) -> Chapter {
+        Chapter { title: format!("{start_ms}"), start_ms, end_ms }
+    }
+
+    #[test]
+    fn chapter_ids_round_trip() {
+        let c = chapter(95_000, 301_500);
+        let id = track_id("a-b_c", &c);
+        assert_eq!(split_id(&id), ("a-b_c", Some((95_000, 301_500))));
+        assert_eq!(split_id("a-b_c"), ("a-b_c", None));
+        assert_eq!(split_id("odd#9-3"), ("odd#9-3", None));
+    }
+
+    #[test]
+    fn steps_between_chapters() {
+        let chapters = [chapter(0, 60_000), chapter(60_000, 180_000), chapter(180_000, 240_000)];
+        assert_eq!(chapter_at(&chapters, 59_999), Some(0));
+        assert_eq!(chapter_at(&chapters, 60_000), Some(1));
+        assert_eq!(chapter_at(&chapters, 240_000), None);
+
+        assert_eq!(next_start(&chapters, 61_000), Some(180_000));
+        assert_eq!(next_start(&chapters, 200_000), None);
+
+        assert_eq!(previous_start(&chapters, 90_000, 5_000), Some(60_000));
+        assert_eq!(previous_start(&chapters, 62_000, 5_000), Some(0));
+        assert_eq!(previous_start(&chapters, 2_000, 5_000), None);
+    }
+
+    #[test]
+    fn window_counts_from_its_start() {
+        // 3 s of mono at 1 kHz, each sample its own index.
+        let buffer = SamplesBuffer::new(1, 1_000, (0..3_000).map(|i| i as f32).collect::<Vec<_>>());
+        let mut window = Window::new(buffer, 1_000, 2_500);
+        assert_eq!(window.total_duration(), Some(Duration::from_millis(1_500)));
+        assert_eq!(window.next(), Some(1_000.0));
+
+        window.try_seek(Duration::from_millis(200)).unwrap();
+        assert_eq!(window.next(), Some(1_200.0));
+        assert_eq!(window.last(), Some(2_499.0));
+    }
+}
@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::Source;
+
+/// Position of a track, counted from the frames its decoder has handed to
+/// the rest of the chain, and its length once the file tells us.
+pub struct PlayClock {
+    sample_rate: u64,
+    /// Frames from the start of the file.
+    frames: AtomicU64,
+    /// 0 until known.
+    total_ms: AtomicU64,
+}
+
+impl PlayClock {
+    pub fn position_ms(&self) -> u64 {
+        self.frames.load(Ordering::Acquire) * 1000 / self.sample_rate
+    }
+
+    /// From the container when it says, otherwise from where decoding ended.
+    pub fn total_ms(&self) -> Option<u64> {
+        Some(self.total_ms.load(Ordering::Acquire)).filter(|&ms| ms > 0)
+    }
+}
+
+/// Counts what passes through into a [`PlayClock`]. Sits right behind the
+/// decoder, so positions are those of the file whatever speed, trim or
+/// tempo does downstream; the stages after it only hold a few milliseconds.
+pub struct Clocked<S: Source<Item = f32>> {
+    inner: S,
+    clock: Arc<PlayClock>,
+    channels: u16,
+    /// Samples into the current frame.
+    sample: u16,
+}
+
+impl<S: Source<Item = f32>> Clocked<S> {
+    pub fn new(inner: S) -> (Self, Arc<PlayClock>) {
+        let total_ms = inner.total_duration().map_or(0, |d| d.as_millis() as u64);
+        let clock = Arc::new(PlayClock {
+            sample_rate: inner.sample_rate().max(1) as u64,
+            frames: AtomicU64::new(0),
+            total_ms: AtomicU64::new(total_ms),
+        });
+        let channels = inner.channels().max(1);
+        let clocked = Self {
+            inner,
+            clock: clock.clone(),
+            channels,
+            sample: 0,
+        };
+        (clocked, clock)
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Clocked<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let Some(sample) = self.inner.next() else {
+            if self.clock.total_ms().is_none() {
+                let end = self.clock.position_ms();
+                self.clock.total_ms.store(end, Ordering::Release);
+            }
+            return None;
+        };
+        self.sample += 1;
+        if self.sample == self.channels {
+            self.sample = 0;
+            self.clock.frames.fetch_add(1, Ordering::AcqRel);
+        }
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Clocked<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        let pos = match self.clock.total_ms() {
+            Some(total) => pos.min(Duration::from_millis(total)),
+            None => pos,
+        };
+        let frame = (pos.as_secs_f64() * self.clock.sample_rate as f64).round() as u64;
+        self.clock.frames.store(frame, Ordering::Release);
+        self.sample = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn counts_frames_through_seeks() {
+        // 2 s of stereo at 8 kHz.
+        let buffer = SamplesBuffer::new(2, 8_000, vec![0.0f32; 2 * 16_000]);
+        let (mut source, clock) = Clocked::new(buffer);
+        assert_eq!(clock.total_ms(), Some(2_000));
+        source.by_ref().take(2 * 4_000 + 1).for_each(drop);
+        assert_eq!(clock.position_ms(), 500);
+
+        source.try_seek(Duration::from_millis(1_250)).unwrap();
+        assert_eq!(clock.position_ms(), 1_250);
+        source.by_ref().for_each(drop);
+        assert_eq!(clock.position_ms(), 2_000);
+    }
+
+    #[test]
+    fn learns_an_unknown_length_at_the_end() {
+        let tone = rodio::source::SineWave::new(440.0).take_duration(Duration::from_millis(1_500));
+        let (source, clock) = Clocked::new(tone);
+        assert_eq!(clock.total_ms(), None);
+        source.for_each(drop);
+        assert_eq!(clock.total_ms(), Some(1_500));
+    }
+}
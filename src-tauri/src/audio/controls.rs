@@ -0,0 +1,140 @@
+//! OS media controls: our own MPRIS service on Linux, souvlaki elsewhere.
+
+use std::ffi::c_void;
+use std::time::Duration;
+
+use souvlaki::{MediaControlEvent, MediaPlayback};
+
+use super::queue::{QueueSnapshot, RepeatMode};
+use crate::models::Track;
+
+/// A request from the OS media controls.
+pub enum ControlEvent {
+    Media(MediaControlEvent),
+    SetRepeat(RepeatMode),
+    SetShuffle(bool),
+    SetRate(f64),
+    /// Jump to the queue entry at this index.
+    GoTo(usize),
+}
+
+/// The now-playing details shown by the OS.
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub track_id: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub art_url: String,
+    pub length: Duration,
+}
+
+impl From<&Track> for Metadata {
+    fn from(t: &Track) -> Self {
+        Self {
+            track_id: t.id.clone(),
+            title: t.title.clone(),
+            artist: t.artist.clone(),
+            album: t.album.clone(),
+            art_url: t.thumbnail.clone(),
+            length: Duration::from_secs_f64(t.duration_secs.max(0.0)),
+        }
+    }
+}
+
+pub struct MediaControls {
+    #[cfg(target_os = "linux")]
+    inner: super::mpris::Mpris,
+    #[cfg(not(target_os = "linux"))]
+    inner: souvlaki::MediaControls,
+}
+
+impl MediaControls {
+    /// Register with the OS. `hwnd` is the main window, needed on Windows only.
+    pub fn start(
+        hwnd: Option<*mut c_void>,
+        handler: impl Fn(ControlEvent) + Send + Sync + 'static,
+    ) -> Option<Self> {
+        #[cfg(target_os = "linux")]
+        {
+            let _ = hwnd;
+            match super::mpris::Mpris::start("sunder", "Sunder", handler) {
+                Ok(inner) => Some(Self { inner }),
+                Err(e) => {
+                    eprintln!("[sunder] media controls unavailable: {e}");
+                    None
+                }
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let mut inner = souvlaki::MediaControls::new(souvlaki::PlatformConfig {
+                dbus_name: "sunder",
+                display_name: "Sunder",
+                hwnd,
+            })
+            .ok()?;
+            inner.attach(move |event| handler(ControlEvent::Media(event))).ok()?;
+            Some(Self { inner })
+        }
+    }
+
+    pub fn set_metadata(&mut self, metadata: Metadata) {
+        #[cfg(target_os = "linux")]
+        self.inner.set_metadata(metadata);
+        #[cfg(not(target_os = "linux"))]
+        let _ = self.inner.set_metadata(souvlaki::MediaMetadata {
+            title: Some(&metadata.title),
+            artist: Some(&metadata.artist),
+            album: Some(metadata.album.as_str()).filter(|a| !a.is_empty()),
+            cover_url: Some(&metadata.art_url),
+            duration: Some(metadata.length),
+        });
+    }
+
+    pub fn set_playback(&mut self, playback: MediaPlayback) {
+        #[cfg(target_os = "linux")]
+        self.inner.set_playback(playback);
+        #[cfg(not(target_os = "linux"))]
+        let _ = self.inner.set_playback(playback);
+    }
+
+    // The rest is MPRIS-only: other platforms' controls have no place for it.
+
+    pub fn set_volume(&mut self, volume: f64) {
+        #[cfg(target_os = "linux")]
+        self.inner.set_volume(volume);
+        #[cfg(not(target_os = "linux"))]
+        let _ = volume;
+    }
+
+    pub fn set_repeat(&mut self, mode: RepeatMode) {
+        #[cfg(target_os = "linux")]
+        self.inner.set_repeat(mode);
+        #[cfg(not(target_os = "linux"))]
+        let _ = mode;
+    }
+
+    pub fn set_rate(&mut self, rate: f64) {
+        #[cfg(target_os = "linux")]
+        self.inner.set_rate(rate);
+        #[cfg(not(target_os = "linux"))]
+        let _ = rate;
+    }
+
+    /// Publish the queue as the track list, and whether it is shuffled.
+    pub fn set_queue(&mut self, queue: QueueSnapshot) {
+        #[cfg(target_os = "linux")]
+        self.inner.set_queue(queue);
+        #[cfg(not(target_os = "linux"))]
+        let _ = queue;
+    }
+
+    /// Tell clients the position jumped (seek, or a repeat starting over).
+    pub fn seeked(&mut self, position: Duration) {
+        #[cfg(target_os = "linux")]
+        self.inner.seeked(position);
+        #[cfg(not(target_os = "linux"))]
+        let _ = position;
+    }
+}
@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+/// Longest overlap the user can configure.
+pub const MAX_CROSSFADE_SECS: f64 = 12.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CrossfadeCurve {
+    /// Constant perceived loudness through the overlap (sin/cos gains).
+    EqualPower,
+    Linear,
+}
+
+impl CrossfadeCurve {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "linear" => Self::Linear,
+            _ => Self::EqualPower,
+        }
+    }
+
+    /// Gain of the outgoing track at progress `t` in `[0, 1]`.
+    pub fn fade_out(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::EqualPower => (t * std::f32::consts::FRAC_PI_2).cos(),
+            Self::Linear => 1.0 - t,
+        }
+    }
+
+    /// Gain of the incoming track at progress `t` in `[0, 1]`.
+    pub fn fade_in(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Self::EqualPower => (t * std::f32::consts::FRAC_PI_2).sin(),
+            Self::Linear => t,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CrossfadeSettings {
+    pub duration: Duration,
+    pub curve: CrossfadeCurve,
+}
+
+impl CrossfadeSettings {
+    pub fn from_config(config: &crate::config::AppConfig) -> Self {
+        let secs = if config.crossfade_secs.is_finite() {
+            config.crossfade_secs.clamp(0.0, MAX_CROSSFADE_SECS)
+        } else {
+            0.0
+        };
+        Self {
+            duration: Duration::from_secs_f64(secs),
+            curve: CrossfadeCurve::from_name(&config.crossfade_curve),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.duration.is_zero()
+    }
+}
+
+/// Consecutive tracks from the same release are meant to flow into each
+/// other, so they are joined gaplessly instead of being overlapped.
+pub fn same_release(current_album: &str, next_album: &str) -> bool {
+    let a = current_album.trim();
+    !a.is_empty() && a.eq_ignore_ascii_case(next_album.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AppConfig;
+
+    #[test]
+    fn curves_hand_over_from_one_track_to_the_other() {
+        for curve in [CrossfadeCurve::EqualPower, CrossfadeCurve::Linear] {
+            assert_eq!(curve.fade_out(0.0), 1.0);
+            assert_eq!(curve.fade_in(0.0), 0.0);
+            assert!(curve.fade_out(1.0).abs() < 1e-6);
+            assert_eq!(curve.fade_in(1.0), 1.0);
+            assert_eq!(curve.fade_in(1.5), 1.0);
+        }
+        for t in [0.1, 0.25, 0.5, 0.9] {
+            let (out, inc) = (CrossfadeCurve::EqualPower.fade_out(t), CrossfadeCurve::EqualPower.fade_in(t));
+            assert!((out * out + inc * inc - 1.0).abs() < 1e-5, "{t}");
+            let (out, inc) = (CrossfadeCurve::Linear.fade_out(t), CrossfadeCurve::Linear.fade_in(t));
+            assert!((out + inc - 1.0).abs() < 1e-6, "{t}");
+        }
+        assert_eq!(CrossfadeCurve::from_name("linear"), CrossfadeCurve::Linear);
+        assert_eq!(CrossfadeCurve::from_name("bogus"), CrossfadeCurve::EqualPower);
+    }
+
+    #[test]
+    fn settings_are_kept_in_range() {
+        let settings = |secs| CrossfadeSettings::from_config(&AppConfig { crossfade_secs: secs, ..AppConfig::default() });
+        assert!(!settings(0.0).enabled());
+        assert_eq!(settings(4.0).duration, Duration::from_secs(4));
+        assert_eq!(settings(60.0).duration, Duration::from_secs_f64(MAX_CROSSFADE_SECS));
+        assert!(!settings(-3.0).enabled());
+        assert!(!settings(f64::NAN).enabled());
+    }
+
+    #[test]
+    fn tracks_of_one_release_join_gaplessly() {
+        assert!(same_release("Selected Ambient Works", " selected ambient works "));
+        assert!(!same_release("Selected Ambient Works", "Drukqs"));
+        assert!(!same_release("", ""));
+        assert!(!same_release("  ", "  "));
+    }
+}
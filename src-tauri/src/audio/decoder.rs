@@ -0,0 +1,403 @@
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::Source;
+use symphonia::core::audio::{Channels, SampleBuffer, SignalSpec};
+use symphonia::core::codecs::{CodecRegistry, Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::{FormatOptions, FormatReader, SeekMode, SeekTo};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+use symphonia::core::units::{Time, TimeBase};
+
+use crate::error::AppError;
+
+/// A packet that fails to decode is skipped; this many in a row ends the track.
+const MAX_DECODE_RETRIES: usize = 3;
+
+/// Symphonia's codecs plus libopus, so YouTube's native formats play
+/// without transcoding: Opus in WebM, AAC in M4A, and legacy MP3 files.
+fn codecs() -> &'static CodecRegistry {
+    static CODECS: OnceLock<CodecRegistry> = OnceLock::new();
+    CODECS.get_or_init(|| {
+        let mut registry = CodecRegistry::new();
+        symphonia::default::register_enabled_codecs(&mut registry);
+        registry.register_all::<symphonia_adapter_libopus::OpusDecoder>();
+        registry
+    })
+}
+
+/// Decoder for the audio files yt-dlp produces, used in place of rodio's
+/// `Decoder`. rodio hides the stream length from symphonia, which the MP4
+/// and Matroska demuxers need to open a file at all.
+pub struct NativeDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    track_id: u32,
+    spec: SignalSpec,
+    buffer: SampleBuffer<f32>,
+    offset: usize,
+    total_duration: Option<Duration>,
+    time_base: Option<TimeBase>,
+    sample_rate: Option<u32>,
+}
+
+impl NativeDecoder {
+    /// Probe `source` and prepare its first audio track. `extension` is a
+    /// hint for the container (e.g. "webm", "m4a").
+    pub fn new(source: Box<dyn MediaSource>, extension: Option<&str>) -> Result<Self, AppError> {
+        let mut hint = Hint::new();
+        if let Some(ext) = extension {
+            hint.with_extension(ext);
+        }
+        let mss = MediaSourceStream::new(source, Default::default());
+        let format_opts = FormatOptions {
+            enable_gapless: true,
+            ..Default::default()
+        };
+        let probed = symphonia::default::get_probe()
+            .format(&hint, mss, &format_opts, &MetadataOptions::default())
+            .map_err(|e| AppError::Audio(format!("unrecognized audio format: {e}")))?;
+        let format = probed.format;
+
+        let track = format
+            .tracks()
+            .iter()
+            .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+            .ok_or_else(|| AppError::Audio("no audio track".into()))?;
+        let track_id = track.id;
+        let time_base = track.codec_params.time_base;
+        let sample_rate = track.codec_params.sample_rate;
+        let total_duration = track
+            .codec_params
+            .time_base
+            .zip(track.codec_params.n_frames)
+            .map(|(base, frames)| time_to_duration(base.calc_time(frames)));
+        let decoder = codecs()
+            .make(&track.codec_params, &DecoderOptions::default())
+            .map_err(|e| AppError::Audio(format!("unsupported codec: {e}")))?;
+        // Placeholder until the first packet is decoded.
+        let spec = SignalSpec::new(48_000, Channels::FRONT_LEFT);
+
+        let mut this = Self {
+            format,
+            decoder,
+            track_id,
+            spec,
+            buffer: SampleBuffer::new(0, spec),
+            offset: 0,
+            total_duration,
+            time_base,
+            sample_rate,
+        };
+        // Decode the first packet up front so the signal spec is known and
+        // broken files fail here rather than as silence in the sink.
+        if !this.decode_next(0) {
+            return Err(AppError::Audio("no decodable audio".into()));
+        }
+        Ok(this)
+    }
+
+    /// Short name of the codec being decoded ("opus", "aac", "mp3").
+    pub fn codec_name(&self) -> &'static str {
+        codecs()
+            .get_codec(self.decoder.codec_params().codec)
+            .map(|d| d.short_name)
+            .unwrap_or("unknown")
+    }
+
+    /// Frames of audio in `ts` units of the track's time base. Containers
+    /// without a time base count in frames already.
+    fn ts_to_frames(&self, ts: u64) -> u64 {
+        let rate = self.sample_rate.unwrap_or(self.spec.rate) as u128;
+        match self.time_base {
+            Some(tb) if tb.denom > 0 => (ts as u128 * tb.numer as u128 * rate / tb.denom as u128) as u64,
+            _ => ts,
+        }
+    }
+
+    /// Decode packets until one yields audio, skipping the first `skip`
+    /// frames of the stream from the current packet on. Skipped packets are
+    /// still decoded, so codecs with pre-roll (Opus, AAC) are primed when the
+    /// audio starts. Returns false at the end of the stream.
+    fn decode_next(&mut self, mut skip: u64) -> bool {
+        let mut errors = 0;
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(p) => p,
+                Err(_) => return false,
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            match self.decoder.decode(&packet) {
+                Ok(decoded) => {
+                    let frames = decoded.frames() as u64;
+                    if frames == 0 {
+                        continue;
+                    }
+                    self.spec = *decoded.spec();
+                    let channels = self.spec.channels.count().max(1);
+                    if self.buffer.capacity() < decoded.capacity() * channels {
+                        self.buffer = SampleBuffer::new(decoded.capacity() as u64, self.spec);
+                    }
+                    self.buffer.copy_interleaved_ref(decoded);
+                    self.offset = (skip as usize * channels).min(self.buffer.len());
+                    if self.offset < self.buffer.len() {
+                        return true;
+                    }
+                    skip -= frames.min(skip);
+                }
+                Err(Error::DecodeError(e)) => {
+                    errors += 1;
+                    if errors > MAX_DECODE_RETRIES {
+                        eprintln!("[sunder] decode failed: {e}");
+                        return false;
+                    }
+                }
+                Err(Error::ResetRequired) => self.decoder.reset(),
+                Err(e) => {
+                    eprintln!("[sunder] decode failed: {e}");
+                    return false;
+                }
+            }
+        }
+    }
+}
+
+fn time_to_duration(t: Time) -> Duration {
+    Duration::from_secs(t.seconds) + Duration::from_secs_f64(t.frac)
+}
+
+impl Iterator for NativeDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.offset >= self.buffer.len() {
+            return None;
+        }
+        let sample = self.buffer.samples()[self.offset];
+        self.offset += 1;
+        // Decode ahead, so the buffer only runs dry at the end of the stream
+        // and `current_frame_len` never reports an end that isn't there.
+        if self.offset == self.buffer.len() {
+            self.decode_next(0);
+        }
+        Some(sample)
+    }
+}
+
+impl Source for NativeDecoder {
+    /// Samples left of the decoded packet; zero only once the stream is over.
+    fn current_frame_len(&self) -> Option<usize> {
+        Some(self.buffer.len() - self.offset)
+    }
+
+    fn channels(&self) -> u16 {
+        self.spec.channels.count().max(1) as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.spec.rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        // Stay just short of the end; some demuxers reject seeking to it.
+        let pos = match self.total_duration {
+            Some(total) if pos + Duration::from_millis(1) >= total => {
+                total.saturating_sub(Duration::from_millis(1))
+            }
+            _ => pos,
+        };
+        let seeked = self
+            .format
+            .seek(
+                SeekMode::Accurate,
+                SeekTo::Time {
+                    time: pos.as_secs_f64().into(),
+                    track_id: Some(self.track_id),
+                },
+            )
+            .map_err(|e| SeekError::Other(Box::new(e)))?;
+        self.decoder.reset();
+        // Demuxers land on a packet boundary at or before the target;
+        // decode forward and drop the difference.
+        let skip = self.ts_to_frames(seeked.required_ts.saturating_sub(seeked.actual_ts));
+        if !self.decode_next(skip) {
+            self.offset = self.buffer.len();
+        }
+        Ok(())
+    }
+}
+
+/// Codec and average bitrate (kbps) of an audio file, for the downloads
+/// table. The bitrate comes from the file size when the container doesn't
+/// state a duration.
+pub fn describe(path: &std::path::Path, fallback_secs: f64) -> Option<(String, u32)> {
+    let file = std::fs::File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+    let ext = path.extension().and_then(|e| e.to_str());
+    let decoder = NativeDecoder::new(Box::new(file), ext).ok()?;
+    let secs = decoder
+        .total_duration
+        .map(|d| d.as_secs_f64())
+        .filter(|s| *s > 0.0)
+        .unwrap_or(fallback_secs);
+    let kbps = if secs > 0.0 {
+        (size as f64 * 8.0 / secs / 1000.0).round() as u32
+    } else {
+        0
+    };
+    Some((decoder.codec_name().to_string(), kbps))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use symphonia::core::checksum::{Crc16Ansi, Crc8Ccitt};
+    use symphonia::core::io::Monitor;
+
+    const RATE: u32 = 8_000;
+    const BLOCK_MS: u64 = 20;
+    const BLOCK_FRAMES: u64 = RATE as u64 * BLOCK_MS / 1000;
+
+    fn element(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut out = id.to_vec();
+        out.push(0x01);
+        out.extend_from_slice(&(data.len() as u64).to_be_bytes()[1..]);
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn uint(id: &[u8], v: u64) -> Vec<u8> {
+        element(id, &v.to_be_bytes())
+    }
+
+    fn float(id: &[u8], v: f64) -> Vec<u8> {
+        element(id, &v.to_be_bytes())
+    }
+
+    /// An uncompressed 16-bit mono FLAC frame holding `samples`.
+    fn flac_frame(number: u64, samples: impl Iterator<Item = i16>) -> Vec<u8> {
+        let mut frame = vec![0xFF, 0xF8, 0x64, 0x08];
+        match number {
+            0..0x80 => frame.push(number as u8),
+            _ => frame.extend([0xC0 | (number >> 6) as u8, 0x80 | (number & 0x3F) as u8]),
+        }
+        frame.push(BLOCK_FRAMES as u8 - 1);
+        let mut crc8 = Crc8Ccitt::new(0);
+        crc8.process_buf_bytes(&frame);
+        frame.push(crc8.crc());
+        frame.push(0x02);
+        for sample in samples {
+            frame.extend_from_slice(&sample.to_be_bytes());
+        }
+        let mut crc16 = Crc16Ansi::new(0);
+        crc16.process_buf_bytes(&frame);
+        frame.extend_from_slice(&crc16.crc().to_be_bytes());
+        frame
+    }
+
+    /// A WebM whose every sample holds its own frame index, in 20 ms blocks
+    /// timed in milliseconds and indexed ahead of the audio like YouTube's
+    /// files.
+    pub(crate) fn webm(secs: u64) -> Vec<u8> {
+        let header = [
+            uint(&[0x42, 0x86], 1),
+            uint(&[0x42, 0xF7], 1),
+            uint(&[0x42, 0xF2], 4),
+            uint(&[0x42, 0xF3], 8),
+            element(&[0x42, 0x82], b"webm"),
+            uint(&[0x42, 0x87], 4),
+            uint(&[0x42, 0x85], 2),
+        ]
+        .concat();
+        let info = [uint(&[0x2A, 0xD7, 0xB1], 1_000_000), float(&[0x44, 0x89], (secs * 1000) as f64)].concat();
+
+        let total = secs * RATE as u64;
+        let mut stream_info = [BLOCK_FRAMES as u16, BLOCK_FRAMES as u16].map(u16::to_be_bytes).concat();
+        stream_info.extend([0; 6]);
+        stream_info.extend_from_slice(&((RATE as u64) << 44 | 15 << 36 | total).to_be_bytes());
+        stream_info.extend([0; 16]);
+        let private = [b"fLaC".as_slice(), &[0x80, 0, 0, stream_info.len() as u8], &stream_info].concat();
+
+        let audio = [float(&[0xB5], RATE as f64), uint(&[0x9F], 1), uint(&[0x62, 0x64], 16)].concat();
+        let track = [
+            uint(&[0xD7], 1),
+            uint(&[0x73, 0xC5], 1),
+            uint(&[0x83], 2),
+            element(&[0x86], b"A_FLAC"),
+            element(&[0x63, 0xA2], &private),
+            uint(&[0x23, 0xE3, 0x83], BLOCK_MS * 1_000_000),
+            element(&[0xE1], &audio),
+        ]
+        .concat();
+        let head = [element(&[0x15, 0x49, 0xA9, 0x66], &info), element(&[0x16, 0x54, 0xAE, 0x6B], &element(&[0xAE], &track))].concat();
+
+        let mut clusters = Vec::new();
+        for second in 0..secs {
+            let mut cluster = uint(&[0xE7], second * 1000);
+            for block in 0..1000 / BLOCK_MS {
+                let number = second * 1000 / BLOCK_MS + block;
+                let first = number * BLOCK_FRAMES;
+                let mut data = vec![0x81];
+                data.extend_from_slice(&((block * BLOCK_MS) as i16).to_be_bytes());
+                data.push(0x80);
+                data.extend(flac_frame(number, (first..first + BLOCK_FRAMES).map(|f| f as i16)));
+                cluster.extend(element(&[0xA3], &data));
+            }
+            clusters.push(element(&[0x1F, 0x43, 0xB6, 0x75], &cluster));
+        }
+        // One cue per cluster; every element has a fixed-width size, so the
+        // index is as long whatever positions it holds.
+        let cues = |first: u64| {
+            let mut pos = first;
+            let points = clusters.iter().enumerate().map(|(second, cluster)| {
+                let at = [uint(&[0xF7], 1), uint(&[0xF1], pos)].concat();
+                pos += cluster.len() as u64;
+                element(&[0xBB], &[uint(&[0xB3], second as u64 * 1000), element(&[0xB7], &at)].concat())
+            });
+            element(&[0x1C, 0x53, 0xBB, 0x6B], &points.collect::<Vec<_>>().concat())
+        };
+        let first = (head.len() + cues(0).len()) as u64;
+        let segment = [head, cues(first), clusters.concat()].concat();
+        [element(&[0x1A, 0x45, 0xDF, 0xA3], &header), element(&[0x18, 0x53, 0x80, 0x67], &segment)].concat()
+    }
+
+    pub(crate) fn frame_index(sample: f32) -> i64 {
+        (sample * 32_768.0).round() as i64
+    }
+
+    #[test]
+    fn seeks_to_the_frame_in_millisecond_timed_webm() {
+        let file = std::io::Cursor::new(webm(3));
+        let mut decoder = NativeDecoder::new(Box::new(file), Some("webm")).unwrap();
+        assert_eq!(decoder.sample_rate(), RATE);
+        assert_eq!(decoder.total_duration(), Some(Duration::from_secs(3)));
+
+        decoder.next();
+        assert_eq!(decoder.current_frame_len(), Some(BLOCK_FRAMES as usize - 1));
+        // Finishing a packet moves straight on to the next one.
+        decoder.by_ref().take(BLOCK_FRAMES as usize - 1).for_each(drop);
+        assert_eq!(decoder.current_frame_len(), Some(BLOCK_FRAMES as usize));
+        assert_eq!(frame_index(decoder.next().unwrap()), BLOCK_FRAMES as i64);
+
+        decoder.try_seek(Duration::from_millis(1_234)).unwrap();
+        assert_eq!(frame_index(decoder.next().unwrap()), 1_234 * RATE as i64 / 1000);
+        decoder.try_seek(Duration::from_millis(2_500)).unwrap();
+        assert_eq!(frame_index(decoder.next().unwrap()), 2_500 * RATE as i64 / 1000);
+        decoder.try_seek(Duration::from_millis(321)).unwrap();
+        assert_eq!(frame_index(decoder.next().unwrap()), 321 * RATE as i64 / 1000);
+
+        // Played out to the end, and only then is the frame empty.
+        decoder.try_seek(Duration::from_millis(2_990)).unwrap();
+        assert_eq!(decoder.by_ref().count(), 10 * RATE as usize / 1000);
+        assert_eq!(decoder.current_frame_len(), Some(0));
+    }
+}
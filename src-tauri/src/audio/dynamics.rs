@@ -0,0 +1,291 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+/// How far ahead the limiter sees peaks coming.
+const LOOKAHEAD_MS: f32 = 5.0;
+const LIMITER_RELEASE_MS: f32 = 150.0;
+pub const MIN_CEILING_DB: f32 = -12.0;
+
+/// Night mode: everything above the threshold is squashed by the ratio and
+/// the result lifted by the makeup gain, so quiet passages come up and loud
+/// ones stay put.
+const NIGHT_THRESHOLD_DB: f32 = -30.0;
+const NIGHT_RATIO: f32 = 4.0;
+const NIGHT_MAKEUP_DB: f32 = 9.0;
+const NIGHT_ATTACK_MS: f32 = 10.0;
+const NIGHT_RELEASE_MS: f32 = 300.0;
+/// Fade time when night mode is switched, so the makeup doesn't jump.
+const NIGHT_SWITCH_MS: f32 = 50.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DynamicsSettings {
+    pub limiter: bool,
+    /// Highest level the limiter lets through, in dBFS.
+    pub ceiling_db: f32,
+    pub night_mode: bool,
+}
+
+impl Default for DynamicsSettings {
+    fn default() -> Self {
+        Self {
+            limiter: true,
+            ceiling_db: -1.0,
+            night_mode: false,
+        }
+    }
+}
+
+/// One-pole smoothing coefficient for a time constant of `ms`.
+fn coeff(ms: f32, sample_rate: u32) -> f32 {
+    (-1.0 / (ms / 1000.0 * sample_rate as f32).max(1.0)).exp()
+}
+
+fn from_db(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Night-mode compressor followed by a look-ahead limiter. Audio runs a few
+/// milliseconds late so the limiter can turn down ahead of a peak instead
+/// of clipping it; whatever still goes over full scale is clamped.
+pub struct Dynamics<S: Source<Item = f32>> {
+    inner: S,
+    settings: Arc<RwLock<DynamicsSettings>>,
+    channels: usize,
+    sample_rate: u32,
+    lookahead: usize,
+    /// Compressed frames waiting for the limiter, interleaved.
+    delay: VecDeque<f32>,
+    /// Gain each delayed frame needs to stay under the ceiling.
+    needs: VecDeque<f32>,
+    /// Sliding minimum of `needs`, as (frame number, gain), rising front
+    /// to back.
+    needed: VecDeque<(u64, f32)>,
+    frames_in: u64,
+    frames_out: u64,
+    gain: f32,
+    attack: f32,
+    release: f32,
+    envelope: f32,
+    night_gain: f32,
+    night_attack: f32,
+    night_release: f32,
+    night_switch: f32,
+    frame: Vec<f32>,
+    out: Vec<f32>,
+    out_pos: usize,
+    exhausted: bool,
+}
+
+impl<S: Source<Item = f32>> Dynamics<S> {
+    pub fn new(inner: S, settings: Arc<RwLock<DynamicsSettings>>) -> Self {
+        let channels = inner.channels().max(1) as usize;
+        let sample_rate = inner.sample_rate();
+        let lookahead = ((LOOKAHEAD_MS / 1000.0 * sample_rate as f32) as usize).max(1);
+        let night_gain = if settings.read().unwrap().night_mode {
+            from_db(NIGHT_MAKEUP_DB)
+        } else {
+            1.0
+        };
+        Self {
+            inner,
+            settings,
+            channels,
+            sample_rate,
+            lookahead,
+            delay: VecDeque::with_capacity((lookahead + 1) * channels),
+            needs: VecDeque::with_capacity(lookahead + 1),
+            needed: VecDeque::new(),
+            frames_in: 0,
+            frames_out: 0,
+            gain: 1.0,
+            // Within about 1% of the target by the time the peak arrives.
+            attack: coeff(LOOKAHEAD_MS / 4.5, sample_rate),
+            release: coeff(LIMITER_RELEASE_MS, sample_rate),
+            envelope: 0.0,
+            night_gain,
+            night_attack: coeff(NIGHT_ATTACK_MS, sample_rate),
+            night_release: coeff(NIGHT_RELEASE_MS, sample_rate),
+            night_switch: coeff(NIGHT_SWITCH_MS, sample_rate),
+            frame: Vec::with_capacity(channels),
+            out: Vec::with_capacity(channels),
+            out_pos: 0,
+            exhausted: false,
+        }
+    }
+
+    /// Compress the frame in `self.frame` and queue it for the limiter.
+    fn push_frame(&mut self, settings: &DynamicsSettings) {
+        let level = self.frame.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+        let k = if level > self.envelope { self.night_attack } else { self.night_release };
+        self.envelope = level + (self.envelope - level) * k;
+
+        let target = if settings.night_mode {
+            let over = (20.0 * self.envelope.max(1e-9).log10() - NIGHT_THRESHOLD_DB).max(0.0);
+            from_db(NIGHT_MAKEUP_DB - over * (1.0 - 1.0 / NIGHT_RATIO))
+        } else {
+            1.0
+        };
+        self.night_gain = target + (self.night_gain - target) * self.night_switch;
+
+        let mut peak = 0.0f32;
+        for s in &mut self.frame {
+            *s *= self.night_gain;
+            peak = peak.max(s.abs());
+        }
+        self.delay.extend(&self.frame);
+
+        let ceiling = from_db(settings.ceiling_db.clamp(MIN_CEILING_DB, 0.0));
+        let need = if settings.limiter && peak > ceiling { ceiling / peak } else { 1.0 };
+        while self.needed.back().is_some_and(|&(_, g)| g >= need) {
+            self.needed.pop_back();
+        }
+        self.needs.push_back(need);
+        self.needed.push_back((self.frames_in, need));
+        self.frames_in += 1;
+    }
+
+    /// Take the oldest delayed frame into `self.out`, limited.
+    fn pop_frame(&mut self) {
+        let no = self.frames_out;
+        self.frames_out += 1;
+        // Frames still ahead in the look-ahead window count; older ones don't.
+        while self.needed.front().is_some_and(|&(n, _)| n < no) {
+            self.needed.pop_front();
+        }
+        let target = self.needed.front().map_or(1.0, |&(_, g)| g);
+        let k = if target < self.gain { self.attack } else { self.release };
+        self.gain = target + (self.gain - target) * k;
+        // A peak with less warning than the look-ahead (the very start of a
+        // track) is cut to size on the spot.
+        let now = self.needs.pop_front().unwrap_or(1.0);
+        self.gain = self.gain.min(now);
+
+        self.out.clear();
+        for _ in 0..self.channels {
+            let s = self.delay.pop_front().unwrap_or(0.0);
+            self.out.push((s * self.gain).clamp(-1.0, 1.0));
+        }
+        self.out_pos = 0;
+    }
+
+    fn reset(&mut self) {
+        self.delay.clear();
+        self.needs.clear();
+        self.needed.clear();
+        self.frames_in = 0;
+        self.frames_out = 0;
+        self.gain = 1.0;
+        self.envelope = 0.0;
+        self.out.clear();
+        self.out_pos = 0;
+        self.exhausted = false;
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Dynamics<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.out_pos >= self.out.len() {
+            let settings = *self.settings.read().unwrap();
+            while !self.exhausted && self.frames_in - self.frames_out <= self.lookahead as u64 {
+                self.frame.clear();
+                self.frame.extend(self.inner.by_ref().take(self.channels));
+                if self.frame.len() < self.channels {
+                    self.exhausted = true;
+                } else {
+                    self.push_frame(&settings);
+                }
+            }
+            if self.frames_out == self.frames_in {
+                return None;
+            }
+            self.pop_frame();
+        }
+        let sample = self.out[self.out_pos];
+        self.out_pos += 1;
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Dynamics<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.reset();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    /// Stereo 440 Hz tone whose amplitude follows `level` over time.
+    fn tone(rate: u32, secs: f32, level: impl Fn(f32) -> f32) -> SamplesBuffer<f32> {
+        let samples = (0..(rate as f32 * secs) as usize)
+            .flat_map(|i| {
+                let t = i as f32 / rate as f32;
+                let v = (2.0 * std::f32::consts::PI * 440.0 * t).sin() * level(t);
+                [v, v]
+            })
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(2, rate, samples)
+    }
+
+    fn run(settings: DynamicsSettings, input: SamplesBuffer<f32>) -> Vec<f32> {
+        Dynamics::new(input, Arc::new(RwLock::new(settings))).collect()
+    }
+
+    fn peak(samples: &[f32]) -> f32 {
+        samples.iter().fold(0.0, |m, s| m.max(s.abs()))
+    }
+
+    #[test]
+    fn limiter_holds_the_ceiling_without_losing_samples() {
+        let out = run(DynamicsSettings::default(), tone(44_100, 0.5, |_| 2.0));
+        assert_eq!(out.len(), tone(44_100, 0.5, |_| 2.0).count());
+        let ceiling = from_db(-1.0);
+        assert!(peak(&out) <= ceiling + 1e-6, "{}", peak(&out));
+        // Limited, not clipped: the tone keeps most of its level.
+        assert!(peak(&out[out.len() / 2..]) > ceiling * 0.95);
+
+        // Below the ceiling nothing changes; the delay only adds latency.
+        let quiet = run(DynamicsSettings::default(), tone(44_100, 0.1, |_| 0.5));
+        assert!(quiet.into_iter().eq(tone(44_100, 0.1, |_| 0.5)));
+    }
+
+    #[test]
+    fn night_mode_narrows_the_range() {
+        // One second quiet, one second loud.
+        let level = |t: f32| if t < 1.0 { 0.01 } else { 0.5 };
+        let settings = DynamicsSettings { night_mode: true, ..Default::default() };
+        let out = run(settings, tone(44_100, 2.0, level));
+        let half = out.len() / 2;
+        let quiet = peak(&out[half / 2..half]);
+        let loud = peak(&out[half + half / 2..]);
+        assert!(quiet > 0.01 * 2.0, "{quiet}");
+        assert!(loud / quiet < 0.5 / 0.01 / 4.0, "{loud} / {quiet}");
+    }
+}
@@ -1,12 +1,12 @@
 use std::ffi::c_void;
 use std::io::{self, BufRead, Read};
 use std::process::{Command, Stdio};
-use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::{Duration, Instant};
 
-use rodio::{Decoder, OutputStream, Sink, Source};
-use souvlaki::{MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, PlatformConfig};
+use rodio::{Sink, Source};
+use souvlaki::{MediaControlEvent, MediaPlayback, SeekDirection};
 use tauri::{Emitter, Manager};
 
 /// Wrapper to send a raw HWND pointer across threads.
@@ -14,21 +14,212 @@ use tauri::{Emitter, Manager};
 struct RawHwnd(*mut c_void);
 unsafe impl Send for RawHwnd {}
 
+use super::chapters::{self, Window};
+use super::segments;
+use super::sleep::{self, SleepStatus, SleepTimer};
+use super::clock::{Clocked, PlayClock};
+use super::controls::{ControlEvent, MediaControls, Metadata};
+use super::decoder::NativeDecoder;
+use super::crossfade::{same_release, CrossfadeCurve, CrossfadeSettings};
+use super::dynamics::{Dynamics, DynamicsSettings};
 use super::equalizer::{EqSettings, EqSource};
+use super::spectrum::Spectrum;
+use super::tempo::{SpeedMode, TempoSettings, TimeStretch};
+use super::loudness::{self, Loudness};
+use super::output::{self, Output};
+use super::progressive::Progressive;
+use super::queue::{self, PlayQueue, RepeatMode};
+use super::repeat::RepeatOne;
+use super::silence::{self, AudibleSpan, Trim};
 use super::state::PlaybackState;
+use super::stereo::{StereoSettings, StereoSource};
+use crate::models::{Chapter, CuePoints, ResumePoint, Segment};
 
 const FADE_STEPS: u32 = 10;
 const FADE_STEP_MS: u64 = 10;
 
+/// Audio that must be on disk before a streamed track starts, and before
+/// playback resumes after catching up with the download.
+const REBUFFER_MS: u64 = 3000;
+/// How often to check whether the preferred output device is back, or any
+/// output when there is none.
+const DEVICE_PROBE: Duration = Duration::from_secs(3);
+
+/// Pause a streamed track when it gets this close to the downloaded end.
+/// Buffered time is estimated from download progress, and Opus is VBR, so
+/// leave some slack.
+const UNDERRUN_MARGIN_MS: u64 = 1500;
+
+/// Past this point "previous" restarts the current track instead.
+const RESTART_THRESHOLD_MS: u64 = 5000;
+
+/// A long track left this early, or this close to its end, starts from the
+/// top next time.
+const RESUME_MIN_PROGRESS_MS: u64 = 10_000;
+const RESUME_END_MARGIN_MS: u64 = 30_000;
+
+/// Chapters are looked up for tracks at least this long; shorter ones are
+/// songs.
+const CHAPTER_LOOKUP_MIN_MS: u64 = 480_000;
+
+/// Step for a bare "seek forward/backward" from the media controls.
+const SEEK_STEP: Duration = Duration::from_secs(5);
+
+/// Playback speed range, also advertised as the MPRIS rate range.
+pub(crate) const MIN_SPEED: f32 = 0.25;
+pub(crate) const MAX_SPEED: f32 = 3.0;
+/// Pitch shift range either way, in semitones.
+pub(crate) const MAX_PITCH: f32 = 12.0;
+
+/// yt-dlp format selection for full downloads. Opus and AAC are decoded
+/// natively, so the original stream is kept as-is.
+pub(crate) const AUDIO_FORMAT: &str = "bestaudio[acodec=opus]/bestaudio[acodec^=mp4a]/bestaudio";
+
+/// Playback held back until a progressive download has buffered enough.
+struct Rebuffer {
+    /// Position to seek to once the audio is there.
+    seek_ms: Option<u64>,
+    /// Whether to play afterwards or stay paused.
+    resume: bool,
+}
+
+/// A fully prepared track (decoder + EQ) ready to be appended to a sink.
+pub type TrackSource = Box<dyn Source<Item = f32> + Send>;
+
+/// A freshly opened track and the clock counting its position.
+type Opened = (TrackSource, Arc<PlayClock>);
+
+/// The preloaded track that follows the current one.
+struct NextUp {
+    video_id: String,
+    duration_ms: u64,
+    /// Where the track starts and stops once silence is skipped.
+    span: AudibleSpan,
+    clock: Arc<PlayClock>,
+    album: String,
+    queued: Queued,
+}
+
+enum Queued {
+    /// Appended behind the current source in the active sink (gapless).
+    /// The flag is set when the preload goes stale; the source then ends the
+    /// moment it is reached and playback finishes like a normal track end.
+    Appended(Arc<AtomicBool>),
+    /// Kept aside until the current track nears its end, then started in its
+    /// own sink so both overlap for the crossfade.
+    Held {
+        source: TrackSource,
+        crossfade: CrossfadeSettings,
+    },
+}
+
+impl NextUp {
+    fn discard(self) {
+        if let Queued::Appended(cancel) = self.queued {
+            cancel.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A sink ramping down to silence on its own schedule, stopped once silent.
+/// Used for track changes, stop and the outgoing side of a crossfade so the
+/// audio thread never sleeps while commands are waiting.
+struct FadeOut {
+    sink: Sink,
+    from: f32,
+    started: Instant,
+    duration: Duration,
+    curve: CrossfadeCurve,
+}
+
+impl FadeOut {
+    fn new(sink: Sink, duration: Duration, curve: CrossfadeCurve) -> Self {
+        Self {
+            from: sink.volume(),
+            sink,
+            started: Instant::now(),
+            duration,
+            curve,
+        }
+    }
+
+    /// The short fade used when playback is cut off by the user.
+    fn quick(sink: Sink) -> Self {
+        Self::new(
+            sink,
+            Duration::from_millis(FADE_STEPS as u64 * FADE_STEP_MS),
+            CrossfadeCurve::Linear,
+        )
+    }
+
+    /// Apply the gain for the current instant. Returns false once finished.
+    fn step(&self) -> bool {
+        let t = fade_progress(self.started, self.duration);
+        self.sink.set_volume(self.from * self.curve.fade_out(t));
+        if t >= 1.0 {
+            self.sink.stop();
+            false
+        } else {
+            true
+        }
+    }
+}
+
+/// Ramp applied to the incoming sink while a crossfade is running.
+struct CrossfadeIn {
+    started: Instant,
+    duration: Duration,
+    curve: CrossfadeCurve,
+}
+
+/// Where a relative seek from `pos_ms` lands, never before the start.
+fn seek_target(pos_ms: u64, dir: SeekDirection, by: Duration) -> Duration {
+    let pos = Duration::from_millis(pos_ms);
+    match dir {
+        SeekDirection::Forward => pos + by,
+        SeekDirection::Backward => pos.saturating_sub(by),
+    }
+}
+
+fn fade_progress(started: Instant, duration: Duration) -> f32 {
+    if duration.is_zero() {
+        return 1.0;
+    }
+    (started.elapsed().as_secs_f32() / duration.as_secs_f32()).min(1.0)
+}
+
 pub enum AudioCommand {
     Play {
         video_id: String,
         duration_ms: u64,
+        album: String,
+        /// Where a long track was left last time.
+        resume: Option<ResumePoint>,
     },
     Prepared {
         session_id: usize,
-        sink: rodio::Sink,
+        source: TrackSource,
+        clock: Arc<PlayClock>,
+        duration_ms: u64,
+        span: AudibleSpan,
+        /// Set when the track plays while it is still being downloaded.
+        download: Option<Arc<Progressive>>,
+    },
+    /// Decode the track that follows the current one ahead of time so it can
+    /// be appended to the active sink and start on the exact boundary sample.
+    Preload {
+        video_id: String,
+        duration_ms: u64,
+        album: String,
+    },
+    Preloaded {
+        session_id: usize,
+        video_id: String,
+        source: TrackSource,
+        clock: Arc<PlayClock>,
         duration_ms: u64,
+        span: AudibleSpan,
+        album: String,
     },
     LoadFailed {
         session_id: usize,
@@ -38,6 +229,14 @@ pub enum AudioCommand {
     Pause,
     Resume,
     Stop,
+    /// Skip to the next track in the queue.
+    Next,
+    /// Restart the current track, or step back if it has only just begun.
+    Previous,
+    /// Forget the preloaded track; nothing follows the current one anymore.
+    ClearPreload,
+    /// The queue was edited; republish it to the media controls.
+    QueueChanged,
     SetVolume(f32),
     Seek(f64),
     UpdateMetadata {
@@ -46,8 +245,43 @@ pub enum AudioCommand {
         thumbnail: String,
         track_id: String,
     },
-    SetRepeat(String),
+    SetRepeat(RepeatMode),
     SetSpeed(f32),
+    /// Play the current track at this speed rather than the global one
+    /// until the track changes; `None` goes back to the global speed.
+    SetTrackSpeed(Option<f32>),
+    /// Resample or time-stretch to reach the playback speed.
+    SetSpeedMode(SpeedMode),
+    /// Shift pitch by this many semitones, independent of speed.
+    SetPitch(f32),
+    /// Move playback to the named output device ("" for the system default).
+    SetOutputDevice(String),
+    /// Whether the output device `name` was listed, once the device probe
+    /// has looked.
+    DeviceProbed {
+        name: String,
+        present: bool,
+    },
+    /// Loop the current track between two positions (ms), or stop looping.
+    SetAbLoop(Option<(u64, u64)>),
+    /// Start the sleep timer, or cancel it.
+    SetSleepTimer(Option<SleepTimer>),
+    /// Bring playback up from silence to the volume over this long, once it
+    /// is playing, without touching the volume setting itself.
+    RampUp(Duration),
+    NextChapter,
+    /// Back to the start of the chapter, or the one before it early on.
+    PreviousChapter,
+    /// Chapters of `video_id`, once looked up.
+    Chapters {
+        video_id: String,
+        chapters: Vec<Chapter>,
+    },
+    /// SponsorBlock segments of `video_id` to skip, once looked up.
+    Segments {
+        video_id: String,
+        segments: Vec<Segment>,
+    },
 }
 
 pub struct AudioHandle {
@@ -57,9 +291,8 @@ pub struct AudioHandle {
     pub duration_ms: Arc<AtomicU64>,
     pub volume: Arc<RwLock<f32>>,
     pub speed: Arc<RwLock<f32>>,
-    pub eq_settings: Arc<RwLock<EqSettings>>,
-    #[allow(dead_code)]
-    pub current_session: Arc<AtomicUsize>,
+    pub effects: Effects,
+    pub queue: Arc<Mutex<PlayQueue>>,
 }
 
 impl AudioHandle {
@@ -70,7 +303,14 @@ impl AudioHandle {
         let duration_ms = Arc::new(AtomicU64::new(0));
         let volume = Arc::new(RwLock::new(0.8_f32));
         let speed = Arc::new(RwLock::new(1.0_f32));
-        let eq_settings = Arc::new(RwLock::new(EqSettings::default()));
+        let effects = Effects {
+            eq: Default::default(),
+            stereo: Default::default(),
+            dynamics: Default::default(),
+            tempo: Default::default(),
+            spectrum: Spectrum::start(app.clone()),
+        };
+        let queue = Arc::new(Mutex::new(PlayQueue::default()));
         let current_session = Arc::new(AtomicUsize::new(0));
 
         let handle = Self {
@@ -80,14 +320,13 @@ impl AudioHandle {
             duration_ms: duration_ms.clone(),
             volume: volume.clone(),
             speed: speed.clone(),
-            eq_settings: eq_settings.clone(),
-            current_session: current_session.clone(),
+            effects: effects.clone(),
+            queue: queue.clone(),
         };
 
         let hwnd = Self::extract_hwnd(&app);
 
         let app_handle = app.app_handle().clone();
-        let current_session_clone = current_session.clone();
         let tx_clone = tx.clone();
 
         std::thread::Builder::new()
@@ -101,9 +340,10 @@ impl AudioHandle {
                     duration_ms,
                     volume,
                     speed,
-                    eq_settings,
+                    effects,
+                    queue,
                     app_handle,
-                    current_session_clone,
+                    current_session,
                     hwnd,
                 );
             })
@@ -133,7 +373,7 @@ impl AudioHandle {
     }
 }
 
-fn ytdlp_bin() -> String {
+pub(super) fn ytdlp_bin() -> String {
     std::env::var("SUNDER_YTDLP_PATH").unwrap_or_else(|_| "yt-dlp".into())
 }
 
@@ -146,85 +386,158 @@ fn audio_thread(
     duration_ms: Arc<AtomicU64>,
     volume: Arc<RwLock<f32>>,
     speed: Arc<RwLock<f32>>,
-    eq_settings: Arc<RwLock<EqSettings>>,
+    effects: Effects,
+    queue: Arc<Mutex<PlayQueue>>,
     app: tauri::AppHandle,
     current_session: Arc<AtomicUsize>,
     hwnd: Option<RawHwnd>,
 ) {
-    let (_stream, stream_handle) = match OutputStream::try_default() {
-        Ok(s) => s,
+    // Without an output, tracks fail to load until a device turns up.
+    let config = app.state::<crate::config::ConfigManager>().get();
+    let backend = output::Backend::selected(&config.output_backend);
+    let mut preferred_device = config.output_device;
+    let mut output = match Output::open(&backend, &preferred_device) {
+        Ok(o) => Some(o),
         Err(e) => {
-            eprintln!("[sunder] FATAL: no audio output device: {e}");
-            return;
+            eprintln!("[sunder] {e}");
+            None
         }
     };
-    eprintln!("[sunder] audio thread started, output device ready");
+    // Rate the sink resamples by; the tempo stage does the rest of the speed.
+    let sink_rate = || effects.tempo.read().unwrap().sink_rate();
+    // Device to move playback to at the end of this iteration.
+    let mut switch_to: Option<String> = None;
+    let mut last_probe = Instant::now();
+    let mut probing = false;
+    // Set while the output is lost: whether to play once it is back.
+    let mut reconnecting: Option<bool> = None;
+    eprintln!("[sunder] audio thread started");
     let mut active_id: Option<String> = None;
+    let mut active_album = String::new();
+    // The current track's audible span; seeks and restarts go no earlier
+    // than its start, and a sleep timer fades out towards its end.
+    let mut active_span = AudibleSpan::WHOLE;
     let mut sink: Option<Sink> = None;
+    let mut next_up: Option<NextUp> = None;
+    // Preload requested while the current track was still loading.
+    let mut pending_preload: Option<(String, u64, String)> = None;
+    // Most recent preload request; older in-flight loads are dropped.
+    let mut wanted_preload: Option<String> = None;
 
-    let mut controls = match MediaControls::new(PlatformConfig {
-        dbus_name: "sunder",
-        display_name: "Sunder",
-        hwnd: hwnd.map(|h| h.0),
-    }) {
-        Ok(mut c) => {
-            let tx_clone = tx.clone();
-            let app_clone = app.clone();
-            let _ = c.attach(move |event| {
-                match event {
-                    MediaControlEvent::Pause => {
-                        let _ = tx_clone.send(AudioCommand::Pause);
-                    }
-                    MediaControlEvent::Play => {
-                        let _ = tx_clone.send(AudioCommand::Resume);
-                    }
-                    MediaControlEvent::Toggle => {
-                        // Toggle routes through the frontend so it can update UI state
-                        let _ = app_clone.emit("media-toggle", ());
-                    }
-                    MediaControlEvent::Next => {
-                        let _ = app_clone.emit("media-next", ());
-                    }
-                    MediaControlEvent::Previous => {
-                        let _ = app_clone.emit("media-previous", ());
-                    }
-                    MediaControlEvent::Stop => {
-                        let _ = tx_clone.send(AudioCommand::Stop);
-                    }
-                    MediaControlEvent::Seek(_) => {}
-                    MediaControlEvent::SeekBy(_, _) => {}
-                    MediaControlEvent::SetPosition(pos) => {
-                        let _ = tx_clone.send(AudioCommand::Seek(pos.0.as_secs_f64()));
-                    }
-                    MediaControlEvent::SetVolume(v) => {
-                        let _ = tx_clone.send(AudioCommand::SetVolume(v as f32));
+    let mut controls = {
+        let tx_clone = tx.clone();
+        let app_clone = app.clone();
+        let position_ms = position_ms.clone();
+        let duration_ms = duration_ms.clone();
+        let queue = queue.clone();
+        MediaControls::start(hwnd.map(|h| h.0), move |event| match event {
+            ControlEvent::Media(event) => match event {
+                MediaControlEvent::Pause => {
+                    let _ = tx_clone.send(AudioCommand::Pause);
+                }
+                MediaControlEvent::Play => {
+                    let _ = tx_clone.send(AudioCommand::Resume);
+                }
+                MediaControlEvent::Toggle => {
+                    // Toggle routes through the frontend so it can update UI state
+                    let _ = app_clone.emit("media-toggle", ());
+                }
+                MediaControlEvent::Next => {
+                    let _ = tx_clone.send(AudioCommand::Next);
+                }
+                MediaControlEvent::Previous => {
+                    let _ = tx_clone.send(AudioCommand::Previous);
+                }
+                MediaControlEvent::Stop => {
+                    let _ = tx_clone.send(AudioCommand::Stop);
+                }
+                MediaControlEvent::Seek(dir) => {
+                    let target = seek_target(position_ms.load(Ordering::Acquire), dir, SEEK_STEP);
+                    let _ = tx_clone.send(AudioCommand::Seek(target.as_secs_f64()));
+                }
+                MediaControlEvent::SeekBy(dir, by) => {
+                    let target = seek_target(position_ms.load(Ordering::Acquire), dir, by);
+                    let dur = duration_ms.load(Ordering::Acquire);
+                    // MPRIS: seeking past the end behaves like Next.
+                    if dur > 0 && target.as_millis() as u64 >= dur {
+                        let _ = tx_clone.send(AudioCommand::Next);
+                    } else {
+                        let _ = tx_clone.send(AudioCommand::Seek(target.as_secs_f64()));
                     }
-                    _ => {}
                 }
-            });
-            #[cfg(target_os = "linux")]
-            let _ = c.set_volume(*volume.read().unwrap() as f64);
-            Some(c)
-        }
-        Err(_) => None,
+                MediaControlEvent::SetPosition(pos) => {
+                    let _ = tx_clone.send(AudioCommand::Seek(pos.0.as_secs_f64()));
+                }
+                MediaControlEvent::SetVolume(v) => {
+                    let _ = tx_clone.send(AudioCommand::SetVolume(v as f32));
+                }
+                _ => {}
+            },
+            ControlEvent::SetRepeat(mode) => {
+                let _ = tx_clone.send(AudioCommand::SetRepeat(mode));
+            }
+            ControlEvent::SetRate(rate) => {
+                let _ = tx_clone.send(AudioCommand::SetSpeed(rate as f32));
+            }
+            ControlEvent::SetShuffle(on) => {
+                let mut q = queue.lock().unwrap();
+                if on {
+                    q.shuffle();
+                } else {
+                    q.unshuffle();
+                }
+                queue::changed(&app_clone, &q, &|cmd| {
+                    let _ = tx_clone.send(cmd);
+                });
+            }
+            ControlEvent::GoTo(index) => {
+                let mut q = queue.lock().unwrap();
+                if q.select(index).is_some() {
+                    queue::play_current(&app_clone, &q, &|cmd| {
+                        let _ = tx_clone.send(cmd);
+                    });
+                }
+            }
+        })
     };
+    if let Some(ref mut c) = controls {
+        c.set_volume(*volume.read().unwrap() as f64);
+        c.set_rate(*speed.read().unwrap() as f64);
+    }
+    // Repeat-one loops the playing source in place instead of reloading it.
+    let repeat_one = RepeatOne::default();
+    let mut seen_restarts = 0;
 
     let mut last_mpris_state: Option<PlaybackState> = None;
     let mut last_mpris_pos: u64 = 0;
     let mut last_emit_state: Option<PlaybackState> = None;
     let mut last_emit_pos: u64 = 0;
     let mut last_emit_vol: f32 = 0.0;
-    // Epoch-based position tracking. We derive source position entirely
-    // from wall-clock time, avoiding rodio's get_pos() which mixes
-    // wall-clock and source-time domains depending on speed.
-    //
-    // source_pos = epoch_source_ms + elapsed_since_epoch * epoch_speed
-    //
-    // A new epoch starts on: play, seek, speed change, or resume.
-    let mut epoch_start: Option<Instant> = None;
-    let mut epoch_source_ms: u64 = 0;
-    let mut epoch_speed: f32 = 1.0;
-    let mut paused_pos_ms: Option<u64> = None;
+    // Position is read off the clock of the playing source, which counts
+    // the frames its decoder has given out, so it holds through pauses,
+    // speed changes and seeks without any bookkeeping here. While a seek
+    // waits for the download or for an output, its target stands in.
+    let mut active_clock: Option<Arc<PlayClock>> = None;
+    let mut held_ms: Option<u64> = None;
+    // Last metadata published, to republish once the real length is known.
+    let mut now_playing: Option<Metadata> = None;
+    // A-B loop over the current track; dropped when the track changes.
+    let mut ab_loop: Option<(u64, u64)> = None;
+    // Sleep timer and what was last reported of it.
+    let mut sleep: Option<SleepTimer> = None;
+    let mut sleep_status: Option<SleepStatus> = None;
+    // Alarm ramp: how long it takes, and when it started playing.
+    let mut ramp: Option<(Duration, Option<Instant>)> = None;
+    // Saved place to pick the loading track up from.
+    let mut pending_resume: Option<ResumePoint> = None;
+    // Chapters of the current track and the one playing, which the media
+    // controls show in place of the track title.
+    let mut active_chapters: Vec<Chapter> = Vec::new();
+    let mut current_chapter: Option<usize> = None;
+    // SponsorBlock segments of the current track, and where playback was
+    // last seen so only those played into are skipped.
+    let mut active_segments: Vec<Segment> = Vec::new();
+    let mut segment_mark: Option<u64> = None;
     // Fade structures for inline processing
     enum FadeAction {
         Pause,
@@ -240,9 +553,26 @@ fn audio_thread(
     }
 
     let mut active_fade: Option<ActiveFade> = None;
+    let mut fade_outs: Vec<FadeOut> = Vec::new();
+    let mut crossfade_in: Option<CrossfadeIn> = None;
+    // Download feeding the current track while it plays, and the pending
+    // resume/seek while playback waits for it to catch up.
+    let mut streaming: Option<Arc<Progressive>> = None;
+    let mut waiting: Option<Rebuffer> = None;
+    // Speed the current track resumed at, in place of the global `speed`.
+    let mut track_speed: Option<f32> = None;
+    let send = |cmd: AudioCommand| {
+        let _ = tx.send(cmd);
+    };
 
     loop {
-        let timeout = if active_fade.is_some() { FADE_STEP_MS } else { 50 };
+        let fading = active_fade.is_some()
+            || crossfade_in.is_some()
+            || !fade_outs.is_empty()
+            || ramp.is_some()
+            || sleep_status.as_ref().is_some_and(|s| s.fading);
+        // A loop wakes up often too, so it turns at B rather than past it.
+        let timeout = if fading || ab_loop.is_some() { FADE_STEP_MS } else { 50 };
         let first = rx.recv_timeout(Duration::from_millis(timeout));
 
         let mut cmds: Vec<AudioCommand> = Vec::new();
@@ -259,30 +589,53 @@ fn audio_thread(
 
         for cmd in cmds {
             match cmd {
-                AudioCommand::Play { video_id, duration_ms: dur } => {
+                AudioCommand::Play { video_id, duration_ms: dur, album, resume } => {
+                    // Skipping away from a long track keeps its place.
+                    if let (Some(id), true) = (&active_id, sink.is_some()) {
+                        let pos = play_pos(held_ms, active_clock.as_deref());
+                        save_resume_point(&app, id, pos, duration_ms.load(Ordering::Relaxed), effects.tempo.read().unwrap().speed);
+                    }
+                    pending_resume = resume;
                     active_fade = None;
+                    crossfade_in = None;
+                    reconnecting = None;
+                    pending_preload = None;
+                    wanted_preload = None;
+                    if let Some(n) = next_up.take() {
+                        n.discard();
+                    }
+                    if let Some(dl) = streaming.take() {
+                        dl.abandon();
+                    }
+                    waiting = None;
                     if let Some(s) = sink.take() {
-                        let start_vol = s.volume();
-                        for i in (0..FADE_STEPS).rev() {
-                            s.set_volume(start_vol * (i as f32 / FADE_STEPS as f32));
-                            std::thread::sleep(Duration::from_millis(FADE_STEP_MS));
-                        }
-                        s.stop();
+                        fade_outs.push(FadeOut::quick(s));
+                    }
+                    if track_speed.take().is_some() {
+                        apply_speed(&effects, &mut controls, None, *speed.read().unwrap());
                     }
                     let session_id = current_session.fetch_add(1, Ordering::SeqCst) + 1;
                     *state.write().unwrap() = PlaybackState::Loading;
                     duration_ms.store(dur, Ordering::Release);
                     position_ms.store(0, Ordering::Release);
-                    epoch_start = None;
-                    epoch_source_ms = 0;
-                    paused_pos_ms = None;
+                    active_clock = None;
+                    held_ms = None;
+                    active_span = AudibleSpan::WHOLE;
+                    if ab_loop.take().is_some() {
+                        emit_ab_loop(&app, None);
+                    }
                     active_id = Some(video_id.clone());
-                    emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed);
+                    active_album = album;
+                    clear_chapters(&app, &mut active_chapters);
+                    look_up_chapters(&app, &video_id, dur, &tx);
+                    active_segments.clear();
+                    segment_mark = None;
+                    look_up_segments(&app, &video_id, &tx);
+                    emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
 
                     let app_clone = app.clone();
                     let state_clone = state.clone();
-                    let stream_handle_clone = stream_handle.clone();
-                    let eq_settings_clone = eq_settings.clone();
+                    let effects = effects.clone();
                     let tx_clone = tx.clone();
                     let video_id_clone = video_id.clone();
                     let session_clone = current_session.clone();
@@ -295,17 +648,20 @@ fn audio_thread(
                         match start_streaming(
                             &video_id_clone,
                             &state_clone,
-                            &stream_handle_clone,
-                            &eq_settings_clone,
+                            &effects,
                             &app_clone,
                             &session_clone,
                             session_id,
+                            dur,
                         ) {
-                            Ok(new_sink) => {
+                            Ok(((source, clock), span, download)) => {
                                 let _ = tx_clone.send(AudioCommand::Prepared {
                                     session_id,
-                                    sink: new_sink,
+                                    source,
+                                    clock,
                                     duration_ms: dur,
+                                    span,
+                                    download,
                                 });
                             }
                             Err(e) => {
@@ -320,25 +676,44 @@ fn audio_thread(
                 }
                 AudioCommand::Prepared {
                     session_id,
-                    sink: new_sink,
+                    source,
+                    clock,
                     duration_ms: dur,
+                    span,
+                    download,
                 } => {
                     if session_id == current_session.load(Ordering::SeqCst) {
                         if let Some(s) = sink.take() {
                             s.stop();
                         }
+                        let new_sink = match open_sink(output.as_ref()) {
+                            Ok(s) => s,
+                            Err(e) => {
+                                if let Some(dl) = download {
+                                    dl.abandon();
+                                }
+                                let _ = tx.send(AudioCommand::LoadFailed {
+                                    session_id,
+                                    video_id: active_id.clone().unwrap_or_default(),
+                                    error: e.to_string(),
+                                });
+                                continue;
+                            }
+                        };
+                        streaming = download;
+                        waiting = None;
 
                         new_sink.set_volume(0.0);
+                        new_sink.append(repeat_one.wrap(source));
 
                         duration_ms.store(dur, Ordering::Release);
-                        position_ms.store(0, Ordering::Release);
-                        epoch_speed = *speed.read().unwrap();
-                        new_sink.set_speed(epoch_speed);
+                        position_ms.store(clock.position_ms(), Ordering::Release);
+                        new_sink.set_speed(sink_rate());
                         sink = Some(new_sink);
                         *state.write().unwrap() = PlaybackState::Playing;
-                        epoch_source_ms = 0;
-                        epoch_start = Some(Instant::now());
-                        paused_pos_ms = None;
+                        active_span = span;
+                        active_clock = Some(clock);
+                        held_ms = None;
 
                         active_fade = Some(ActiveFade {
                             start_vol: 0.0,
@@ -348,7 +723,139 @@ fn audio_thread(
                             action: FadeAction::SetVolume,
                         });
 
-                        emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed);
+                        emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
+
+                        if let Some(r) = pending_resume.take() {
+                            eprintln!("[sunder] resuming at {}ms", r.position_ms);
+                            if r.speed != *speed.read().unwrap() {
+                                send(AudioCommand::SetTrackSpeed(Some(r.speed)));
+                            }
+                            send(AudioCommand::Seek(r.position_ms as f64 / 1000.0));
+                        }
+
+                        if let Some((video_id, dur, album)) = pending_preload.take() {
+                            let _ = tx.send(AudioCommand::Preload {
+                                video_id,
+                                duration_ms: dur,
+                                album,
+                            });
+                        }
+                    } else if let Some(dl) = download {
+                        dl.abandon();
+                    }
+                }
+                AudioCommand::Preload {
+                    video_id,
+                    duration_ms: dur,
+                    album,
+                } => {
+                    if sink.is_none() {
+                        if active_id.is_some() {
+                            pending_preload = Some((video_id, dur, album));
+                        }
+                        continue;
+                    }
+                    if wanted_preload.as_ref() == Some(&video_id) {
+                        continue;
+                    }
+                    if let Some(n) = next_up.take() {
+                        n.discard();
+                    }
+                    wanted_preload = Some(video_id.clone());
+
+                    let session_id = current_session.load(Ordering::SeqCst);
+                    let app_clone = app.clone();
+                    let effects = effects.clone();
+                    let tx_clone = tx.clone();
+                    let session_clone = current_session.clone();
+
+                    std::thread::spawn(move || {
+                        let (file_id, window) = chapters::split_id(&video_id);
+                        let prepared = fetch_audio(file_id, &app_clone, false).and_then(|path| {
+                            let gain = normalization_gain(&app_clone, file_id, Some(&path), true);
+                            let span = play_span(&app_clone, &video_id, Some(&path), true);
+                            // Played on from the end of the current track, so
+                            // without a fade-in.
+                            let (source, clock) = open_file(&path, gain, span, window, false, &effects)?;
+                            Ok((source, clock, span.unwrap_or(AudibleSpan::WHOLE)))
+                        });
+                        if session_clone.load(Ordering::SeqCst) != session_id {
+                            return;
+                        }
+                        match prepared {
+                            Ok((source, clock, span)) => {
+                                let _ = tx_clone.send(AudioCommand::Preloaded {
+                                    session_id,
+                                    video_id,
+                                    source,
+                                    clock,
+                                    duration_ms: dur,
+                                    span,
+                                    album,
+                                });
+                            }
+                            Err(e) => eprintln!("[sunder] preload of {video_id} failed: {e}"),
+                        }
+                    });
+                }
+                AudioCommand::Preloaded {
+                    session_id,
+                    video_id,
+                    source,
+                    clock,
+                    duration_ms: dur,
+                    span,
+                    album,
+                } => {
+                    if session_id != current_session.load(Ordering::SeqCst)
+                        || wanted_preload.as_ref() != Some(&video_id)
+                    {
+                        continue;
+                    }
+                    // Only one track may sit behind the current one, and
+                    // none behind the one the sleep timer stops after.
+                    if next_up.is_some() || sleep.as_ref().is_some_and(SleepTimer::on_last_track) {
+                        continue;
+                    }
+                    let crossfade = CrossfadeSettings::from_config(
+                        &app.state::<crate::config::ConfigManager>().get(),
+                    );
+                    // Overlapping needs to know where the current track ends;
+                    // without that, or within one release, join gaplessly.
+                    let ends = active_id.as_deref().and_then(|id| {
+                        sleep::track_end(duration_ms.load(Ordering::Relaxed), chapters::split_id(id).1, active_span)
+                    });
+                    let overlap = crossfade.enabled() && ends.is_some() && !same_release(&active_album, &album);
+                    if overlap {
+                        eprintln!("[sunder] preloaded {video_id} for crossfade");
+                        next_up = Some(NextUp {
+                            video_id,
+                            duration_ms: dur,
+                            span,
+                            clock,
+                            album,
+                            queued: Queued::Held { source, crossfade },
+                        });
+                    } else if let Some(ref s) = sink {
+                        let cancel = Arc::new(AtomicBool::new(false));
+                        let cancel_clone = cancel.clone();
+                        s.append(repeat_one.wrap(source).stoppable().periodic_access(
+                            Duration::from_millis(5),
+                            move |src| {
+                                if cancel_clone.load(Ordering::Relaxed) {
+                                    src.stop();
+                                }
+                            },
+                        ));
+                        eprintln!("[sunder] preloaded {video_id} for gapless playback");
+                        next_up = Some(NextUp {
+                            video_id,
+                            duration_ms: dur,
+                            span,
+                            clock,
+                            album,
+                            queued: Queued::Appended(cancel),
+                        });
                     }
                 }
                 AudioCommand::LoadFailed {
@@ -359,7 +866,8 @@ fn audio_thread(
                     if session_id == current_session.load(Ordering::SeqCst) {
                         *state.write().unwrap() = PlaybackState::Idle;
                         active_id = None;
-                        emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed);
+                        pending_preload = None;
+                        emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
                         let _ = app.emit(
                             "playback-error",
                             serde_json::json!({
@@ -370,13 +878,27 @@ fn audio_thread(
                     }
                 }
                 AudioCommand::Pause => {
+                    if let Some(ref mut r) = reconnecting {
+                        *r = false;
+                        continue;
+                    }
+                    if let Some(ref mut w) = waiting {
+                        // Already silent while buffering; just don't resume.
+                        w.resume = false;
+                        *state.write().unwrap() = PlaybackState::Paused;
+                        emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
+                        continue;
+                    }
+                    // A crossfade in progress completes instantly on pause.
+                    for f in fade_outs.drain(..) {
+                        f.sink.stop();
+                    }
+                    crossfade_in = None;
                     if let Some(ref s) = sink {
-                        // Snapshot source position before pausing
-                        if let Some(start) = epoch_start {
-                            let wall_ms = start.elapsed().as_millis() as u64;
-                            paused_pos_ms = Some(epoch_source_ms + (wall_ms as f64 * epoch_speed as f64) as u64);
+                        if let Some(ref id) = active_id {
+                            let pos = play_pos(held_ms, active_clock.as_deref());
+                            save_resume_point(&app, id, pos, duration_ms.load(Ordering::Relaxed), effects.tempo.read().unwrap().speed);
                         }
-                        epoch_start = None;
                         active_fade = Some(ActiveFade {
                             start_vol: s.volume(),
                             target_vol: 0.0,
@@ -387,15 +909,20 @@ fn audio_thread(
                     }
                 }
                 AudioCommand::Resume => {
+                    if let Some(ref mut r) = reconnecting {
+                        *r = true;
+                        continue;
+                    }
+                    if let Some(ref mut w) = waiting {
+                        w.resume = true;
+                        *state.write().unwrap() = PlaybackState::Buffering;
+                        emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
+                        continue;
+                    }
                     if let Some(ref s) = sink {
                         s.play();
                         *state.write().unwrap() = PlaybackState::Playing;
-                        // Start new epoch from paused position
-                        epoch_source_ms = paused_pos_ms.unwrap_or(0);
-                        epoch_speed = *speed.read().unwrap();
-                        epoch_start = Some(Instant::now());
-                        paused_pos_ms = None;
-                        emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed);
+                        emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
 
                         active_fade = Some(ActiveFade {
                             start_vol: s.volume(),
@@ -407,63 +934,147 @@ fn audio_thread(
                     }
                 }
                 AudioCommand::Stop => {
+                    if let (Some(id), true) = (&active_id, sink.is_some()) {
+                        let pos = play_pos(held_ms, active_clock.as_deref());
+                        save_resume_point(&app, id, pos, duration_ms.load(Ordering::Relaxed), effects.tempo.read().unwrap().speed);
+                    }
+                    pending_resume = None;
+                    ramp = None;
                     current_session.fetch_add(1, Ordering::SeqCst);
                     active_fade = None;
+                    reconnecting = None;
+                    pending_preload = None;
+                    wanted_preload = None;
+                    if let Some(n) = next_up.take() {
+                        n.discard();
+                    }
 
+                    crossfade_in = None;
+                    if let Some(dl) = streaming.take() {
+                        dl.abandon();
+                    }
+                    waiting = None;
                     if let Some(s) = sink.take() {
-                        let start_vol = s.volume();
-                        for i in (0..FADE_STEPS).rev() {
-                            s.set_volume(start_vol * (i as f32 / FADE_STEPS as f32));
-                            std::thread::sleep(Duration::from_millis(FADE_STEP_MS));
-                        }
-                        s.stop();
+                        fade_outs.push(FadeOut::quick(s));
                     }
                     *state.write().unwrap() = PlaybackState::Stopped;
                     active_id = None;
-                    epoch_start = None;
-                    epoch_source_ms = 0;
-                    paused_pos_ms = None;
+                    clear_chapters(&app, &mut active_chapters);
+                    active_segments.clear();
+                    active_clock = None;
+                    held_ms = None;
+                    if ab_loop.take().is_some() {
+                        emit_ab_loop(&app, None);
+                    }
                     position_ms.store(0, Ordering::Release);
-                    emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed);
+                    emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
+                }
+                AudioCommand::Next => {
+                    let mut q = queue.lock().unwrap();
+                    if q.advance(true).is_some() {
+                        queue::play_current(&app, &q, &send);
+                    }
+                }
+                AudioCommand::Previous => {
+                    let pos = play_pos(held_ms, active_clock.as_deref());
+                    if active_id.is_some() && restarts_on_previous(pos) {
+                        send(AudioCommand::Seek(0.0));
+                        continue;
+                    }
+                    let mut q = queue.lock().unwrap();
+                    if q.retreat(true).is_some() {
+                        queue::play_current(&app, &q, &send);
+                    }
+                }
+                AudioCommand::QueueChanged => {
+                    if let Some(ref mut c) = controls {
+                        c.set_queue(queue.lock().unwrap().snapshot());
+                    }
+                }
+                AudioCommand::ClearPreload => {
+                    pending_preload = None;
+                    wanted_preload = None;
+                    if let Some(n) = next_up.take() {
+                        n.discard();
+                    }
                 }
                 AudioCommand::SetVolume(v) => {
                     *volume.write().unwrap() = v;
+                    // Whoever sets the volume wants to hear it now.
+                    ramp = None;
                     if let Some(ref s) = sink {
                         // Skip direct update if paused to allow Resume to ramp from 0.0 or current
                         let st = state.read().unwrap().clone();
                         if st != PlaybackState::Paused {
                             if let Some(ref mut f) = active_fade {
                                 f.target_vol = v;
-                            } else {
+                            } else if crossfade_in.is_none() {
                                 s.set_volume(v);
                             }
                         }
                     }
-                    #[cfg(target_os = "linux")]
                     if let Some(ref mut c) = controls {
-                        let _ = c.set_volume(v as f64);
+                        c.set_volume(v as f64);
                     }
-                    emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed);
+                    emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
                 }
                 AudioCommand::Seek(secs) => {
+                    // Leading silence that was trimmed can't be seeked into.
+                    let secs = secs.max(active_span.start_ms as f64 / 1000.0);
+                    let target = (secs * 1000.0).round() as u64;
+                    segment_mark = Some(target);
+                    if let Some(ref mut c) = controls {
+                        c.seeked(Duration::from_millis(target));
+                    }
+                    // A dead stream never answers a seek; keep the target for
+                    // when the track is reopened on a working output.
+                    if sink.is_some()
+                        && (reconnecting.is_some() || output.as_ref().is_some_and(Output::stalled))
+                    {
+                        if let Some(ref mut w) = waiting {
+                            w.seek_ms = Some(target);
+                        }
+                        held_ms = Some(target);
+                        position_ms.store(target, Ordering::Release);
+                        continue;
+                    }
+                    let unbuffered = streaming.as_ref().is_some_and(|dl| {
+                        !dl.is_done() && target + REBUFFER_MS > dl.buffered_ms()
+                    });
+                    if let (true, Some(s)) = (unbuffered || waiting.is_some(), &sink) {
+                        // Seeking into audio that hasn't arrived yet: hold
+                        // the sink paused until the download catches up.
+                        let resume = match waiting {
+                            Some(ref w) => w.resume,
+                            None => *state.read().unwrap() == PlaybackState::Playing,
+                        };
+                        active_fade = None;
+                        s.pause();
+                        s.set_volume(*volume.read().unwrap());
+                        held_ms = Some(target);
+                        position_ms.store(target, Ordering::Release);
+                        *state.write().unwrap() = if resume {
+                            PlaybackState::Buffering
+                        } else {
+                            PlaybackState::Paused
+                        };
+                        waiting = Some(Rebuffer {
+                            seek_ms: Some(target),
+                            resume,
+                        });
+                        emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
+                        continue;
+                    }
                     if let Some(ref s) = sink {
-                        // rodio's Speed::try_seek multiplies pos by the speed factor,
+                        // rodio's Speed::try_seek multiplies pos by the sink's rate,
                         // so we pre-divide to get the correct source-time seek position.
                         let source_secs = secs.max(0.0);
-                        let seek_secs = source_secs / epoch_speed as f64;
+                        let seek_secs = source_secs / sink_rate() as f64;
                         let d = Duration::from_secs_f64(seek_secs);
                         if let Err(e) = s.try_seek(d) {
                             eprintln!("[sunder] seek failed: {e}");
                         } else {
-                            let target = (source_secs * 1000.0) as u64;
-                            if paused_pos_ms.is_some() {
-                                paused_pos_ms = Some(target);
-                            } else {
-                                epoch_source_ms = target;
-                                epoch_speed = *speed.read().unwrap();
-                                epoch_start = Some(Instant::now());
-                            }
-                            position_ms.store(target, Ordering::Release);
+                            position_ms.store(play_pos(held_ms, active_clock.as_deref()), Ordering::Release);
                         }
                     }
                 }
@@ -472,41 +1083,263 @@ fn audio_thread(
                         continue;
                     }
 
+                    let metadata = Metadata {
+                        track_id,
+                        title: title.clone(),
+                        artist: artist.clone(),
+                        album: active_album.clone(),
+                        art_url: thumbnail,
+                        length: Duration::from_millis(duration_ms.load(Ordering::Relaxed)),
+                    };
                     if let Some(ref mut c) = controls {
-                        let metadata = MediaMetadata {
-                            title: Some(&title),
-                            artist: Some(&artist),
-                            album: None,
-                            cover_url: Some(&thumbnail),
-                            duration: Some(Duration::from_millis(duration_ms.load(Ordering::Relaxed))),
-                        };
-                        let _ = c.set_metadata(metadata);
+                        let chapter = current_chapter.and_then(|i| active_chapters.get(i));
+                        c.set_metadata(shown_metadata(&metadata, chapter));
                     }
+                    now_playing = Some(metadata);
 
                     // Trigger system notification directly
                     super::art_worker::trigger_notification(&app, &title, &artist);
                 }
-                AudioCommand::SetRepeat(_mode) => {
-                    // Stored for future MPRIS LoopStatus integration
+                AudioCommand::SetRepeat(mode) => {
+                    repeat_one.set(mode == RepeatMode::Track);
+                    if let Some(ref mut c) = controls {
+                        c.set_repeat(mode);
+                    }
+                    let mut q = queue.lock().unwrap();
+                    q.set_repeat(mode);
+                    queue::changed(&app, &q, &send);
                 }
                 AudioCommand::SetSpeed(s) => {
-                    let clamped = s.clamp(0.25, 3.0);
+                    let clamped = s.clamp(MIN_SPEED, MAX_SPEED);
                     *speed.write().unwrap() = clamped;
+                    track_speed = None;
+                    apply_speed(&effects, &mut controls, sink.as_ref(), clamped);
+                    emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
+                }
+                AudioCommand::SetTrackSpeed(s) => {
+                    track_speed = s.map(|s| s.clamp(MIN_SPEED, MAX_SPEED));
+                    apply_speed(&effects, &mut controls, sink.as_ref(), track_speed.unwrap_or(*speed.read().unwrap()));
+                    emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
+                }
+                AudioCommand::SetSpeedMode(mode) => {
+                    effects.tempo.write().unwrap().mode = mode;
                     if let Some(ref sk) = sink {
-                        // Snapshot current source position and start new epoch
-                        if let Some(start) = epoch_start {
-                            let wall_ms = start.elapsed().as_millis() as u64;
-                            epoch_source_ms += (wall_ms as f64 * epoch_speed as f64) as u64;
-                            epoch_start = Some(Instant::now());
+                        sk.set_speed(sink_rate());
+                    }
+                }
+                AudioCommand::SetPitch(semitones) => {
+                    effects.tempo.write().unwrap().semitones = semitones.clamp(-MAX_PITCH, MAX_PITCH);
+                    if let Some(ref sk) = sink {
+                        sk.set_speed(sink_rate());
+                    }
+                }
+                AudioCommand::SetOutputDevice(name) => {
+                    preferred_device = name.clone();
+                    // Rendered output stays where it is; the device is
+                    // remembered for when the device backend is back.
+                    if backend == output::Backend::Device {
+                        switch_to = Some(name);
+                    }
+                }
+                AudioCommand::DeviceProbed { name, present } => {
+                    probing = false;
+                    // Unless the preference or the output changed meanwhile.
+                    if present
+                        && name == preferred_device
+                        && switch_to.is_none()
+                        && output.as_ref().is_some_and(|o| !o.is_on(&name))
+                    {
+                        switch_to = Some(name);
+                    }
+                }
+                AudioCommand::SetSleepTimer(timer) => {
+                    if timer.as_ref().is_some_and(SleepTimer::on_last_track) {
+                        if let Some(n) = next_up.take() {
+                            n.discard();
                         }
-                        epoch_speed = clamped;
-                        sk.set_speed(clamped);
+                        wanted_preload = None;
+                    }
+                    // A fade in progress is called off along with its timer.
+                    if let (Some(s), None, None) = (&sink, &active_fade, &crossfade_in) {
+                        s.set_volume(*volume.read().unwrap());
+                    }
+                    sleep = timer;
+                    sleep_status = None;
+                    if sleep.is_none() {
+                        let _ = app.emit("sleep-timer", None::<SleepStatus>);
+                    }
+                }
+                AudioCommand::RampUp(over) => {
+                    if let Some(ref s) = sink {
+                        s.set_volume(0.0);
+                    }
+                    ramp = Some((over, None));
+                }
+                AudioCommand::SetAbLoop(range) => {
+                    ab_loop = range.filter(|&(a, b)| a < b && active_id.is_some());
+                    emit_ab_loop(&app, ab_loop);
+                }
+                AudioCommand::NextChapter => {
+                    let pos = play_pos(held_ms, active_clock.as_deref());
+                    match chapters::next_start(&active_chapters, pos) {
+                        Some(start) => send(AudioCommand::Seek(start as f64 / 1000.0)),
+                        None => send(AudioCommand::Next),
+                    }
+                }
+                AudioCommand::PreviousChapter => {
+                    let pos = play_pos(held_ms, active_clock.as_deref());
+                    match chapters::previous_start(&active_chapters, pos, RESTART_THRESHOLD_MS) {
+                        Some(start) => send(AudioCommand::Seek(start as f64 / 1000.0)),
+                        None => send(AudioCommand::Previous),
+                    }
+                }
+                AudioCommand::Chapters { video_id, chapters } => {
+                    if active_id.as_ref() == Some(&video_id) {
+                        active_chapters = chapters;
+                        current_chapter = None;
+                        let _ = app.emit("chapters", &active_chapters);
+                    }
+                }
+                AudioCommand::Segments { video_id, segments } => {
+                    if active_id.as_ref() == Some(&video_id) {
+                        active_segments = segments;
                     }
-                    emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed);
                 }
             }
         }
 
+        // A stream dies with its device (unplugged, sound server restart).
+        // Playback freezes until an output is back, and a fallback device is
+        // left as soon as the preferred one returns. A download that playback
+        // has caught up with is not the device's fault, so it isn't judged
+        // while rebuffering.
+        if waiting.is_none() && output.as_ref().is_some_and(Output::stalled) {
+            eprintln!("[sunder] audio output lost");
+            output = None;
+            for f in fade_outs.drain(..) {
+                f.sink.stop();
+            }
+            crossfade_in = None;
+            if let (Some(s), None) = (&sink, reconnecting) {
+                let pausing = matches!(
+                    active_fade,
+                    Some(ActiveFade { action: FadeAction::Pause, .. })
+                );
+                let resume = match waiting.take() {
+                    Some(w) => {
+                        held_ms = w.seek_ms.or(held_ms);
+                        w.resume
+                    }
+                    None => *state.read().unwrap() == PlaybackState::Playing && !pausing,
+                };
+                let pos = play_pos(held_ms, active_clock.as_deref());
+                s.pause();
+                held_ms = Some(pos);
+                position_ms.store(pos, Ordering::Release);
+                reconnecting = Some(resume);
+                *state.write().unwrap() = PlaybackState::Reconnecting;
+                emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
+            }
+            active_fade = None;
+            switch_to = Some(preferred_device.clone());
+            last_probe = Instant::now();
+        } else if switch_to.is_none() && !probing && last_probe.elapsed() >= DEVICE_PROBE {
+            last_probe = Instant::now();
+            match &output {
+                None => switch_to = Some(preferred_device.clone()),
+                // Listing devices can take a while; playback doesn't wait
+                // for it.
+                Some(o) if !o.is_on(&preferred_device) => {
+                    probing = true;
+                    let tx = tx.clone();
+                    let name = preferred_device.clone();
+                    std::thread::spawn(move || {
+                        let present = output::list_devices().iter().any(|d| d.name == name);
+                        let _ = tx.send(AudioCommand::DeviceProbed { name, present });
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+
+        if let Some(name) = switch_to.take() {
+            match Output::open(&backend, &name) {
+                Ok(new_output) => {
+                    // Sinks can't move between streams: the current track is
+                    // reopened on the new device at the same position, and a
+                    // crossfade in progress completes instantly.
+                    for f in fade_outs.drain(..) {
+                        f.sink.stop();
+                    }
+                    crossfade_in = None;
+                    let old_output = output.replace(new_output);
+                    let resume = reconnecting.take();
+                    if let (Some(old), Some(id)) = (sink.take(), active_id.clone()) {
+                        let pos_ms = play_pos(held_ms, active_clock.as_deref());
+                        let paused = resume.map_or(old.is_paused(), |r| !r);
+                        let reopened = reopen_track(&app, &id, streaming.as_ref(), &effects)
+                            .and_then(|(source, clock)| {
+                                let s = open_sink(output.as_ref())?;
+                                s.set_speed(sink_rate());
+                                s.set_volume(if active_fade.is_some() {
+                                    old.volume()
+                                } else {
+                                    *volume.read().unwrap()
+                                });
+                                s.append(repeat_one.wrap(source));
+                                if paused {
+                                    s.pause();
+                                }
+                                let d = Duration::from_secs_f64(pos_ms as f64 / 1000.0 / sink_rate() as f64);
+                                if let Err(e) = s.try_seek(d) {
+                                    eprintln!("[sunder] seek failed: {e}");
+                                }
+                                Ok((s, clock))
+                            });
+                        old.stop();
+                        match reopened {
+                            Ok((s, clock)) => {
+                                sink = Some(s);
+                                active_clock = Some(clock);
+                                held_ms = None;
+                                match resume {
+                                    Some(true) => *state.write().unwrap() = PlaybackState::Playing,
+                                    Some(false) => *state.write().unwrap() = PlaybackState::Paused,
+                                    None => {}
+                                }
+                                if resume.is_some() {
+                                    emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
+                                }
+                                // A gaplessly queued track sat in the old sink.
+                                if let Some(n) = next_up.take_if(|n| matches!(n.queued, Queued::Appended(_))) {
+                                    wanted_preload = None;
+                                    let _ = tx.send(AudioCommand::Preload {
+                                        video_id: n.video_id.clone(),
+                                        duration_ms: n.duration_ms,
+                                        album: n.album.clone(),
+                                    });
+                                    n.discard();
+                                }
+                            }
+                            Err(e) => {
+                                let _ = tx.send(AudioCommand::LoadFailed {
+                                    session_id: current_session.load(Ordering::SeqCst),
+                                    video_id: id,
+                                    error: e.to_string(),
+                                });
+                            }
+                        }
+                    }
+                    drop(old_output);
+                    eprintln!(
+                        "[sunder] output switched to {}",
+                        if name.is_empty() { "default device" } else { &name }
+                    );
+                }
+                Err(e) => eprintln!("[sunder] {e}"),
+            }
+        }
+
         // Step the active fade non-blockingly
         if let Some(ref mut f) = active_fade {
             f.steps_taken += 1;
@@ -522,7 +1355,7 @@ fn audio_thread(
                             s.pause();
                         }
                         *state.write().unwrap() = PlaybackState::Paused;
-                        emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed);
+                        emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
                     }
                     FadeAction::SetVolume => {}
                 }
@@ -530,6 +1363,17 @@ fn audio_thread(
             }
         }
 
+        fade_outs.retain(FadeOut::step);
+        if let Some(ref x) = crossfade_in {
+            let t = fade_progress(x.started, x.duration);
+            if let Some(ref s) = sink {
+                s.set_volume(*volume.read().unwrap() * x.curve.fade_in(t));
+            }
+            if t >= 1.0 {
+                crossfade_in = None;
+            }
+        }
+
         // Only update MPRIS when playback state or position actually changed
         if let Some(ref mut c) = controls {
             let st = state.read().unwrap().clone();
@@ -547,13 +1391,13 @@ fn audio_thread(
 
                 match st {
                     PlaybackState::Playing => {
-                        let _ = c.set_playback(MediaPlayback::Playing { progress });
+                        c.set_playback(MediaPlayback::Playing { progress });
                     }
                     PlaybackState::Paused => {
-                        let _ = c.set_playback(MediaPlayback::Paused { progress });
+                        c.set_playback(MediaPlayback::Paused { progress });
                     }
                     PlaybackState::Stopped | PlaybackState::Idle => {
-                        let _ = c.set_playback(MediaPlayback::Stopped);
+                        c.set_playback(MediaPlayback::Stopped);
                     }
                     _ => {}
                 }
@@ -565,32 +1409,304 @@ fn audio_thread(
 
         let mut track_ended = false;
 
-        // Compute current source position from epoch
-        let cur_source_ms = if let Some(pause_pos) = paused_pos_ms {
-            pause_pos
-        } else if let Some(start) = epoch_start {
-            let wall_ms = start.elapsed().as_millis() as u64;
-            epoch_source_ms + (wall_ms as f64 * epoch_speed as f64) as u64
-        } else {
-            0
-        };
+        // The source wrapped around under repeat-one: the track starts over.
+        let restarts = repeat_one.restarts();
+        if restarts != seen_restarts {
+            seen_restarts = restarts;
+            if let (Some(id), true) = (&active_id, sink.is_some()) {
+                eprintln!("[sunder] repeating {id}");
+                segment_mark = None;
+                if let Some(ref mut c) = controls {
+                    c.seeked(Duration::from_millis(active_span.start_ms));
+                }
+                let _ = app.state::<crate::db::SearchCache>().record_listen(id);
+            }
+        }
+
+        let mut cur_source_ms = play_pos(held_ms, active_clock.as_deref());
+
+        // A-B loop: back to A as soon as B is reached.
+        if let (Some((a, b)), Some(s), None) = (ab_loop, &sink, held_ms) {
+            if cur_source_ms >= b && *state.read().unwrap() == PlaybackState::Playing {
+                let d = Duration::from_secs_f64(a as f64 / 1000.0 / sink_rate() as f64);
+                match s.try_seek(d) {
+                    Ok(()) => {
+                        cur_source_ms = play_pos(held_ms, active_clock.as_deref());
+                        if let Some(ref mut c) = controls {
+                            c.seeked(Duration::from_millis(cur_source_ms));
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("[sunder] A-B loop seek failed: {e}");
+                        ab_loop = None;
+                        emit_ab_loop(&app, ab_loop);
+                    }
+                }
+            }
+        }
+        // SponsorBlock: jump over a segment as soon as playback runs into it.
+        if let (Some(s), None) = (&sink, held_ms) {
+            if *state.read().unwrap() == PlaybackState::Playing {
+                let entered = segments::entered(&active_segments, segment_mark, cur_source_ms).cloned();
+                if let Some(seg) = entered {
+                    let d = Duration::from_secs_f64(seg.end_ms as f64 / 1000.0 / sink_rate() as f64);
+                    match s.try_seek(d) {
+                        Ok(()) => {
+                            eprintln!("[sunder] skipped {} segment at {}ms", seg.category, seg.start_ms);
+                            let _ = app.emit("segment-skipped", &seg);
+                            cur_source_ms = play_pos(held_ms, active_clock.as_deref());
+                            if let Some(ref mut c) = controls {
+                                c.seeked(Duration::from_millis(cur_source_ms));
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("[sunder] segment skip failed: {e}");
+                            active_segments.clear();
+                        }
+                    }
+                }
+                segment_mark = Some(cur_source_ms);
+            }
+        }
         position_ms.store(cur_source_ms, Ordering::Release);
 
-        if let Some(ref s) = sink {
-            if !s.empty() {
-                let dur = duration_ms.load(Ordering::Relaxed);
-                // Force completion if position far exceeds reported duration
-                // (guards against decoders that don't EOF cleanly)
-                if dur > 0
-                    && cur_source_ms > dur + 2000
-                    && *state.read().unwrap() == PlaybackState::Playing
-                {
-                    eprintln!(
-                        "[sunder] position ({cur_source_ms}ms) exceeded duration ({dur}ms), forcing track end"
-                    );
-                    track_ended = true;
+        // Entering another chapter retitles the media controls and, past the
+        // first one, raises a notification.
+        let chapter = chapters::chapter_at(&active_chapters, cur_source_ms);
+        if chapter != current_chapter {
+            let announce = current_chapter.is_some();
+            current_chapter = chapter;
+            let _ = app.emit("chapter", chapter);
+            let playing = now_playing.as_ref().filter(|m| Some(&m.track_id) == active_id.as_ref());
+            if let Some(m) = playing {
+                let chapter = chapter.map(|i| &active_chapters[i]);
+                if let Some(ref mut c) = controls {
+                    c.set_metadata(shown_metadata(m, chapter));
+                }
+                if let (true, Some(chapter)) = (announce, chapter) {
+                    super::art_worker::trigger_notification(&app, &chapter.title, &m.title);
+                }
+            }
+        }
+
+        // The decoded file knows its length better than the metadata did;
+        // take its word and keep it for next time.
+        let decoded_ms = active_clock.as_ref().and_then(|c| c.total_ms());
+        if let (Some(total), Some(id)) = (decoded_ms, &active_id) {
+            if total != duration_ms.load(Ordering::Relaxed) {
+                duration_ms.store(total, Ordering::Release);
+                let _ = app
+                    .state::<crate::db::SearchCache>()
+                    .set_track_duration(id, total as f64 / 1000.0);
+                if let (Some(c), Some(m)) = (controls.as_mut(), now_playing.as_mut()) {
+                    if &m.track_id == id {
+                        m.length = Duration::from_millis(total);
+                        let chapter = current_chapter.and_then(|i| active_chapters.get(i));
+                        c.set_metadata(shown_metadata(m, chapter));
+                    }
+                }
+                emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
+            }
+        }
+
+        // Keep a streamed track behind its download: pause before the decoder
+        // runs out of data, or once the track has started playing silence
+        // for want of it, and resume (applying any pending seek) once enough
+        // has arrived.
+        if let (Some(dl), Some(s), None) = (&streaming, &sink, reconnecting) {
+            let buffered = dl.buffered_ms();
+            let done = dl.is_done();
+            match waiting.take() {
+                None => {
+                    if !done
+                        && !s.is_paused()
+                        && (cur_source_ms + UNDERRUN_MARGIN_MS > buffered || dl.starved())
+                    {
+                        eprintln!("[sunder] buffer underrun at {cur_source_ms}ms");
+                        active_fade = None;
+                        s.pause();
+                        s.set_volume(*volume.read().unwrap());
+                        *state.write().unwrap() = PlaybackState::Buffering;
+                        waiting = Some(Rebuffer {
+                            seek_ms: None,
+                            resume: true,
+                        });
+                        emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
+                    }
+                }
+                Some(w) => {
+                    let target = w.seek_ms.unwrap_or(cur_source_ms);
+                    if done || buffered >= target + REBUFFER_MS {
+                        if let Some(ms) = w.seek_ms {
+                            let d = Duration::from_secs_f64(ms as f64 / 1000.0 / sink_rate() as f64);
+                            if let Err(e) = s.try_seek(d) {
+                                eprintln!("[sunder] seek failed: {e}");
+                            }
+                        }
+                        if w.resume {
+                            s.play();
+                            *state.write().unwrap() = PlaybackState::Playing;
+                        }
+                        held_ms = None;
+                        emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
+                    } else {
+                        waiting = Some(w);
+                    }
+                }
+            }
+            if done && waiting.is_none() {
+                streaming = None;
+            }
+        }
+
+        // Alarm ramp: a gain over the volume, silent until playback starts.
+        if let Some((over, ref mut started)) = ramp {
+            if started.is_none() && *state.read().unwrap() == PlaybackState::Playing {
+                *started = Some(Instant::now());
+            }
+            let t = started.map_or(0.0, |at| (at.elapsed().as_secs_f32() / over.as_secs_f32()).min(1.0));
+            if let (Some(s), None, None) = (&sink, &active_fade, &crossfade_in) {
+                // Squared, so loudness rises evenly rather than mostly at the start.
+                s.set_volume(*volume.read().unwrap() * t * t);
+            }
+            if t >= 1.0 {
+                ramp = None;
+            }
+        }
+
+        // Sleep timer: fade out towards the stop, and report the time left.
+        if let Some(ref timer) = sleep {
+            let now = Instant::now();
+            let dur = duration_ms.load(Ordering::Relaxed);
+            let track_left = active_id
+                .as_deref()
+                .and_then(|id| sleep::track_end(dur, chapters::split_id(id).1, active_span))
+                .map(|end| sleep::time_left(cur_source_ms, end, effects.tempo.read().unwrap().speed));
+            let remaining = timer.remaining(now, track_left);
+            let status = timer.status(remaining);
+            if status.fading {
+                if let (Some(s), None, None) = (&sink, &active_fade, &crossfade_in) {
+                    s.set_volume(*volume.read().unwrap() * timer.gain(remaining));
+                }
+            }
+            if timer.expired(now) {
+                eprintln!("[sunder] sleep timer stopping playback");
+                sleep = None;
+                sleep_status = None;
+                let _ = app.emit("sleep-timer", None::<SleepStatus>);
+                send(AudioCommand::Stop);
+            } else if sleep_status.as_ref() != Some(&status) {
+                let _ = app.emit("sleep-timer", &status);
+                sleep_status = Some(status);
+            }
+        }
+
+        // Handover to the preloaded track. An appended source has already
+        // taken over inside the same sink at the gapless boundary; a held one
+        // starts in its own sink once the current track is within the
+        // crossfade window, or right away if it ended early.
+        let handover = match (&sink, &next_up) {
+            (Some(s), Some(n)) => match &n.queued {
+                Queued::Appended(cancel) => s.len() == 1 && !cancel.load(Ordering::Relaxed),
+                Queued::Held { crossfade, .. } => {
+                    // Measured against where the track stops playing: its
+                    // chapter or trimmed span may end before the file does.
+                    let end = active_id.as_deref().and_then(|id| {
+                        sleep::track_end(duration_ms.load(Ordering::Relaxed), chapters::split_id(id).1, active_span)
+                    });
+                    let window_ms =
+                        (crossfade.duration.as_millis() as f64 * effects.tempo.read().unwrap().speed as f64) as u64;
+                    *state.read().unwrap() == PlaybackState::Playing
+                        && (s.empty() || end.is_some_and(|end| cur_source_ms + window_ms >= end))
                 }
-            } else if *state.read().unwrap() == PlaybackState::Playing {
+            },
+            _ => false,
+        };
+        if handover {
+            if let Some(n) = next_up.take() {
+                let mut promoted = true;
+                if let Queued::Held { source, crossfade } = n.queued {
+                    match open_sink(output.as_ref()) {
+                        Ok(new_sink) => {
+                            new_sink.set_speed(sink_rate());
+                            let old = sink.replace(new_sink);
+                            match old {
+                                Some(old) if !old.empty() => {
+                                    eprintln!("[sunder] crossfading into {}", n.video_id);
+                                    fade_outs.push(FadeOut::new(
+                                        old,
+                                        crossfade.duration,
+                                        crossfade.curve,
+                                    ));
+                                    crossfade_in = Some(CrossfadeIn {
+                                        started: Instant::now(),
+                                        duration: crossfade.duration,
+                                        curve: crossfade.curve,
+                                    });
+                                    if let Some(ref s) = sink {
+                                        s.set_volume(0.0);
+                                    }
+                                }
+                                old => {
+                                    if let Some(old) = old {
+                                        old.stop();
+                                    }
+                                    if let Some(ref s) = sink {
+                                        s.set_volume(*volume.read().unwrap());
+                                    }
+                                }
+                            }
+                            if let Some(ref s) = sink {
+                                s.append(repeat_one.wrap(source));
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("[sunder] crossfade sink failed: {e}");
+                            promoted = false;
+                        }
+                    }
+                } else {
+                    eprintln!("[sunder] gapless transition to {}", n.video_id);
+                }
+                wanted_preload = None;
+                if promoted {
+                    streaming = None;
+                    waiting = None;
+                    duration_ms.store(n.duration_ms, Ordering::Release);
+                    position_ms.store(n.clock.position_ms(), Ordering::Release);
+                    active_span = n.span;
+                    active_clock = Some(n.clock);
+                    held_ms = None;
+                    if ab_loop.take().is_some() {
+                        emit_ab_loop(&app, None);
+                    }
+                    if let Some(ref id) = active_id {
+                        let _ = app.state::<crate::db::SearchCache>().clear_resume_point(id);
+                    }
+                    active_id = Some(n.video_id.clone());
+                    active_album = n.album;
+                    if track_speed.take().is_some() {
+                        apply_speed(&effects, &mut controls, sink.as_ref(), *speed.read().unwrap());
+                    }
+                    clear_chapters(&app, &mut active_chapters);
+                    look_up_chapters(&app, &n.video_id, n.duration_ms, &tx);
+                    active_segments.clear();
+                    segment_mark = None;
+                    look_up_segments(&app, &n.video_id, &tx);
+                    if let Some(ref mut timer) = sleep {
+                        timer.track_ended();
+                    }
+                    emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
+                    let mut q = queue.lock().unwrap();
+                    q.focus(&n.video_id);
+                    queue::announce(&app, &q, &send);
+                }
+            }
+        }
+
+        // A track is over once the sink has played everything decoded.
+        if let (false, Some(s)) = (handover, &sink) {
+            if s.empty() && *state.read().unwrap() == PlaybackState::Playing {
                 eprintln!("[sunder] track finished");
                 track_ended = true;
             }
@@ -600,14 +1716,41 @@ fn audio_thread(
             if let Some(s) = sink.take() {
                 s.stop();
             }
+            if let Some(n) = next_up.take() {
+                n.discard();
+            }
+            wanted_preload = None;
             active_fade = None;
+            crossfade_in = None;
+            streaming = None;
+            waiting = None;
             *state.write().unwrap() = PlaybackState::Idle;
+            // Played to the end: next time starts over.
+            if let Some(ref id) = active_id {
+                let _ = app.state::<crate::db::SearchCache>().clear_resume_point(id);
+            }
             active_id = None;
-            epoch_start = None;
-            epoch_source_ms = 0;
-            paused_pos_ms = None;
+            clear_chapters(&app, &mut active_chapters);
+            active_segments.clear();
+            active_clock = None;
+            held_ms = None;
+            if ab_loop.take().is_some() {
+                emit_ab_loop(&app, None);
+            }
             position_ms.store(0, Ordering::Release);
-            let _ = app.emit("track-finished", ());
+            // The track the sleep timer was waiting for: stop here.
+            if sleep.as_mut().is_some_and(|t| !t.track_ended()) {
+                eprintln!("[sunder] sleep timer stopping after the track");
+                sleep = None;
+                sleep_status = None;
+                let _ = app.emit("sleep-timer", None::<SleepStatus>);
+                *state.write().unwrap() = PlaybackState::Stopped;
+            } else {
+                let mut q = queue.lock().unwrap();
+                if q.advance(false).is_some() {
+                    queue::play_current(&app, &q, &send);
+                }
+            }
         }
 
         // Only emit when state, position, or volume actually changed (debounce 200ms)
@@ -618,7 +1761,7 @@ fn audio_thread(
         let pos_changed = cur_pos.abs_diff(last_emit_pos) > 200;
         let vol_changed = (cur_vol - last_emit_vol).abs() > 0.001;
         if state_changed || pos_changed || vol_changed {
-            emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed);
+            emit_state(&app, &state, &position_ms, &duration_ms, &volume, &speed, track_speed);
             last_emit_state = Some(cur_state);
             last_emit_pos = cur_pos;
             last_emit_vol = cur_vol;
@@ -626,13 +1769,18 @@ fn audio_thread(
     }
 }
 
-/// Prune the cache directory to at most `keep` MP3 files (by modification time).
+/// Prune the cache directory to at most `keep` audio files (by modification time).
 fn cleanup_cache(cache_dir: &std::path::Path, keep: usize) {
     let mut entries: Vec<_> = std::fs::read_dir(cache_dir)
         .into_iter()
         .flatten()
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().map(|ext| ext == "mp3").unwrap_or(false))
+        .filter(|e| {
+            e.path()
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| crate::downloads::AUDIO_EXTENSIONS.contains(&ext))
+        })
         .filter_map(|e| {
             let modified = e.metadata().ok()?.modified().ok()?;
             Some((e.path(), modified))
@@ -649,51 +1797,135 @@ fn cleanup_cache(cache_dir: &std::path::Path, keep: usize) {
     }
 }
 
-/// Download audio via yt-dlp to a temp MP3 file, then decode with rodio.
-/// symphonia 0.5 cannot decode YouTube's M4A containers (SeekError on init),
-/// so we let yt-dlp + ffmpeg convert to MP3 which symphonia handles perfectly.
+/// Start playback of `video_id`. Offline and cached copies open directly.
+/// Otherwise the track is streamed: yt-dlp writes the WebM/Opus stream into
+/// a growing file that the decoder reads while it is written, so playback
+/// starts after a few seconds of buffer instead of the full download. If
+/// that can't start, fall back to downloading the whole file.
 fn start_streaming(
     video_id: &str,
     state: &Arc<RwLock<PlaybackState>>,
-    stream_handle: &rodio::OutputStreamHandle,
-    eq_settings: &Arc<RwLock<EqSettings>>,
+    effects: &Effects,
     app: &tauri::AppHandle,
     current_session: &Arc<AtomicUsize>,
     session_id: usize,
-) -> Result<Sink, crate::error::AppError> {
+    duration_ms: u64,
+) -> Result<(Opened, AudibleSpan, Option<Arc<Progressive>>), crate::error::AppError> {
+    *state.write().unwrap() = PlaybackState::Buffering;
+
+    let superseded = || current_session.load(Ordering::SeqCst) != session_id;
+    let cache_dir = cache_dir()?;
+    // A chapter can start anywhere in its upload, so chapter tracks wait for
+    // the whole file rather than streaming it from the top.
+    let (file_id, window) = chapters::split_id(video_id);
+
+    let mut download = None;
+    if window.is_none() && local_audio(video_id, app, &cache_dir).is_none() {
+        let _ = app.emit(
+            "download-progress",
+            serde_json::json!({ "percent": 0.0, "stage": "preparing" }),
+        );
+        match Progressive::start(app, video_id, &cache_dir, duration_ms) {
+            Ok(dl) if dl.wait_for(REBUFFER_MS, || !superseded()) => download = Some(dl),
+            Ok(dl) => {
+                dl.abandon();
+                eprintln!("[sunder] progressive playback unavailable, downloading in full");
+            }
+            Err(e) => eprintln!("[sunder] progressive playback unavailable: {e}"),
+        }
+    }
+
+    // Abort early if the user skipped to another track during download
+    if superseded() {
+        if let Some(dl) = download {
+            dl.abandon();
+        }
+        return Err(crate::error::AppError::Audio("session superseded".into()));
+    }
+
+    let (source, span) = match download {
+        Some(ref dl) => {
+            let gain = normalization_gain(app, video_id, None, false);
+            let span = play_span(app, video_id, None, false);
+            let opened = dl.open_with(|file| open_source(Box::new(file), Some("webm"), gain, span, None, true, effects));
+            (opened.map(|(source, clock)| (dl.gate(source), clock)), span)
+        }
+        None => {
+            let play_path = fetch_audio(file_id, app, true)?;
+            if superseded() {
+                return Err(crate::error::AppError::Audio("session superseded".into()));
+            }
+            let gain = normalization_gain(app, file_id, Some(&play_path), false);
+            let span = play_span(app, video_id, Some(&play_path), false);
+            (open_file(&play_path, gain, span, window, true, effects), span)
+        }
+    };
+    let opened = match source {
+        Ok(opened) => opened,
+        Err(e) => {
+            if let Some(dl) = download {
+                dl.abandon();
+            }
+            return Err(e);
+        }
+    };
+
+    Ok((opened, span.unwrap_or(AudibleSpan::WHOLE), download))
+}
+
+/// The temp cache directory for streamed tracks, created if missing.
+fn cache_dir() -> Result<std::path::PathBuf, crate::error::AppError> {
+    let dir = std::env::temp_dir().join("sunder");
+    std::fs::create_dir_all(&dir).map_err(crate::error::AppError::Io)?;
+    Ok(dir)
+}
+
+/// An existing local copy of `video_id`: the offline download if there is
+/// one, otherwise the temp cache entry.
+fn local_audio(
+    video_id: &str,
+    app: &tauri::AppHandle,
+    cache_dir: &std::path::Path,
+) -> Option<std::path::PathBuf> {
+    let downloads = crate::downloads::DownloadManager::dir_for(app);
+    crate::downloads::find_audio(&downloads, video_id)
+        .or_else(|| crate::downloads::find_audio(cache_dir, video_id))
+}
+
+/// Resolve a playable file for `video_id`, downloading it into the temp cache
+/// when neither an offline copy nor a cached file exists. `report_progress`
+/// controls whether `download-progress` events are emitted; preloads run
+/// quietly so they don't disturb the UI of the track that is playing.
+fn fetch_audio(
+    video_id: &str,
+    app: &tauri::AppHandle,
+    report_progress: bool,
+) -> Result<std::path::PathBuf, crate::error::AppError> {
     let url = format!("https://www.youtube.com/watch?v={video_id}");
     let bin = ytdlp_bin();
 
-    let cache_dir = std::env::temp_dir().join("sunder");
-    std::fs::create_dir_all(&cache_dir).map_err(crate::error::AppError::Io)?;
+    // Offline-first: a persistently downloaded copy always wins. It never
+    // touches the network and is exempt from the LRU temp-cache cleanup.
+    let cache_dir = cache_dir()?;
     cleanup_cache(&cache_dir, 50);
+    let local = local_audio(video_id, app, &cache_dir);
 
     let out_template = cache_dir.join(format!("{video_id}.%(ext)s"));
-    let expected_path = cache_dir.join(format!("{video_id}.mp3"));
 
-    // Offline-first: a persistently downloaded copy always wins. It never
-    // touches the network and is exempt from the LRU temp-cache cleanup.
-    let download_path =
-        crate::downloads::DownloadManager::dir_for(app).join(format!("{video_id}.mp3"));
-
-    *state.write().unwrap() = PlaybackState::Buffering;
+    let progress = |payload: serde_json::Value| {
+        if report_progress {
+            let _ = app.emit("download-progress", payload);
+        }
+    };
 
-    if !download_path.exists() && !expected_path.exists() {
-        let _ = app.emit(
-            "download-progress",
-            serde_json::json!({
-                "percent": 0.0, "stage": "preparing"
-            }),
-        );
+    if local.is_none() {
+        progress(serde_json::json!({ "percent": 0.0, "stage": "preparing" }));
 
         let out_path_str = out_template.to_str().unwrap_or_default();
         let base_args: Vec<&str> = vec![
             url.as_str(),
-            "--extract-audio",
-            "--audio-format",
-            "mp3",
-            "--audio-quality",
-            "2",
+            "-f",
+            AUDIO_FORMAT,
             "-o",
             out_path_str,
             "--no-playlist",
@@ -707,15 +1939,10 @@ fn start_streaming(
         for attempt in 0..2u8 {
             if attempt > 0 {
                 eprintln!("[sunder] retrying download (attempt {})", attempt + 1);
-                for ext in [
-                    "mp3",
-                    "webm",
-                    "m4a",
-                    "opus",
-                    "part",
-                    "webm.part",
-                    "m4a.part",
-                ] {
+                for ext in crate::downloads::AUDIO_EXTENSIONS
+                    .iter()
+                    .chain(&["opus", "part", "webm.part", "m4a.part"])
+                {
                     let _ = std::fs::remove_file(cache_dir.join(format!("{video_id}.{ext}")));
                 }
             }
@@ -742,26 +1969,9 @@ fn start_streaming(
             if let Some(stdout) = child.stdout.take() {
                 for line in io::BufReader::new(stdout).lines().map_while(Result::ok) {
                     if let Some(pct) = parse_download_pct(&line) {
-                        let _ = app.emit(
-                            "download-progress",
-                            serde_json::json!({
-                                "percent": pct, "stage": "downloading"
-                            }),
-                        );
-                    } else if line.contains("[ExtractAudio]") {
-                        let _ = app.emit(
-                            "download-progress",
-                            serde_json::json!({
-                                "percent": 100.0, "stage": "converting"
-                            }),
-                        );
+                        progress(serde_json::json!({ "percent": pct, "stage": "downloading" }));
                     } else if line.contains("[youtube]") || line.contains("[info]") {
-                        let _ = app.emit(
-                            "download-progress",
-                            serde_json::json!({
-                                "percent": 0.0, "stage": "extracting"
-                            }),
-                        );
+                        progress(serde_json::json!({ "percent": 0.0, "stage": "extracting" }));
                     }
                 }
             }
@@ -775,7 +1985,7 @@ fn start_streaming(
                 }
             };
 
-            if status.success() && expected_path.exists() {
+            if status.success() && crate::downloads::find_audio(&cache_dir, video_id).is_some() {
                 last_error.clear();
                 break;
             }
@@ -810,32 +2020,20 @@ fn start_streaming(
         if !last_error.is_empty() {
             return Err(crate::error::AppError::Extraction(last_error));
         }
-
-        if !expected_path.exists() {
-            return Err(crate::error::AppError::Extraction(format!(
-                "yt-dlp produced no output at {}",
-                expected_path.display()
-            )));
-        }
-    } else if download_path.exists() {
-        eprintln!("[sunder] offline hit: {}", download_path.display());
-    } else {
-        eprintln!("[sunder] cache hit: {}", expected_path.display());
-    }
-
-    // Abort early if the user skipped to another track during download
-    if current_session.load(Ordering::SeqCst) != session_id {
-        return Err(crate::error::AppError::Audio("session superseded".into()));
+    } else if let Some(path) = &local {
+        eprintln!("[sunder] local hit: {}", path.display());
     }
 
-    // Prefer the persistent offline copy when present.
-    let play_path = if download_path.exists() {
-        &download_path
-    } else {
-        &expected_path
-    };
+    let play_path = local
+        .or_else(|| crate::downloads::find_audio(&cache_dir, video_id))
+        .ok_or_else(|| {
+            crate::error::AppError::Extraction(format!(
+                "yt-dlp produced no output in {}",
+                cache_dir.display()
+            ))
+        })?;
 
-    let file_len = std::fs::metadata(play_path)
+    let file_len = std::fs::metadata(&play_path)
         .map(|m| m.len())
         .unwrap_or(0);
     eprintln!(
@@ -844,19 +2042,348 @@ fn start_streaming(
         play_path.display()
     );
 
-    let file = std::fs::File::open(play_path).map_err(crate::error::AppError::Io)?;
-    let decoder = Decoder::new(io::BufReader::with_capacity(64 * 1024, file)) // this is to improve RAM usage. 64KB is enough.
-        .map_err(|e| crate::error::AppError::Audio(format!("decoder init failed: {e}")))?;
+    Ok(play_path)
+}
+
+/// Linear gain that brings a track to the configured loudness target.
+/// Unanalysed tracks get the fallback gain. With `analyze_now` they are
+/// measured first (preloads have time to spare); otherwise the measurement
+/// runs in the background and applies from the next play. Without a `path`
+/// (the file is still downloading) nothing is measured here.
+fn normalization_gain(
+    app: &tauri::AppHandle,
+    video_id: &str,
+    path: Option<&std::path::Path>,
+    analyze_now: bool,
+) -> f32 {
+    let config = app.state::<crate::config::ConfigManager>().get();
+    let album_mode = match config.normalization_mode.as_str() {
+        "track" => false,
+        "album" => true,
+        _ => return 1.0,
+    };
+    let db = app.state::<crate::db::SearchCache>();
+    let mut measured = db.get_loudness(video_id).ok().flatten();
+    if let (None, Some(path)) = (measured, path) {
+        if analyze_now {
+            measured = loudness::analyze_and_store(app, video_id, path);
+        } else {
+            let app = app.clone();
+            let video_id = video_id.to_string();
+            let path = path.to_path_buf();
+            std::thread::spawn(move || loudness::analyze_and_store(&app, &video_id, &path));
+        }
+    }
+
+    let gain_db = match measured {
+        Some(track) => {
+            let reference = if album_mode {
+                Loudness::combine(&db.album_loudness(video_id).unwrap_or_default())
+                    .unwrap_or(track)
+            } else {
+                track
+            };
+            reference.gain_db(config.normalization_target_lufs)
+        }
+        None => config.normalization_fallback_db,
+    };
+    10f32.powf(gain_db as f32 / 20.0)
+}
+
+/// Remember where a track at least as long as the configured minimum was
+/// left, or forget it once it has (nearly) been played through.
+fn save_resume_point(app: &tauri::AppHandle, video_id: &str, pos_ms: u64, duration_ms: u64, speed: f32) {
+    let min_minutes = app.state::<crate::config::ConfigManager>().get().resume_min_minutes;
+    if min_minutes <= 0.0 || (duration_ms as f64) < min_minutes * 60_000.0 {
+        return;
+    }
+    let db = app.state::<crate::db::SearchCache>();
+    let result = if pos_ms < RESUME_MIN_PROGRESS_MS || pos_ms + RESUME_END_MARGIN_MS >= duration_ms {
+        db.clear_resume_point(video_id)
+    } else {
+        db.set_resume_point(video_id, &ResumePoint { position_ms: pos_ms, speed })
+    };
+    if let Err(e) = result {
+        eprintln!("[sunder] failed to save resume point for {video_id}: {e}");
+    }
+}
+
+/// Part of the track to play: between its cue points, and inside those
+/// only the audible span when silence trimming is on.
+fn play_span(
+    app: &tauri::AppHandle,
+    video_id: &str,
+    path: Option<&std::path::Path>,
+    analyze_now: bool,
+) -> Option<AudibleSpan> {
+    let cues = app
+        .state::<crate::db::SearchCache>()
+        .get_cue_points(video_id)
+        .unwrap_or_default();
+    within_cues(&cues, audible_span(app, video_id, path, analyze_now))
+}
+
+/// The `audible` span narrowed down to the part between `cues`.
+fn within_cues(cues: &CuePoints, audible: Option<AudibleSpan>) -> Option<AudibleSpan> {
+    if *cues == CuePoints::default() {
+        return audible;
+    }
+    let audible = audible.unwrap_or(AudibleSpan::WHOLE);
+    Some(AudibleSpan {
+        start_ms: cues.start_ms.unwrap_or(0).max(audible.start_ms),
+        end_ms: cues.end_ms.unwrap_or(u64::MAX).min(audible.end_ms),
+    })
+}
 
-    let sink =
-        Sink::try_new(stream_handle).map_err(|e| crate::error::AppError::Audio(e.to_string()))?;
-    sink.set_volume(0.0);
-    sink.append(EqSource::new(
-        decoder.convert_samples(),
-        eq_settings.clone(),
-    ));
+/// Part of the track to play when silence trimming is on. Like loudness,
+/// an unanalysed track is scanned right away with `analyze_now`, otherwise
+/// in the background so the trim applies from the next play.
+fn audible_span(
+    app: &tauri::AppHandle,
+    video_id: &str,
+    path: Option<&std::path::Path>,
+    analyze_now: bool,
+) -> Option<AudibleSpan> {
+    let config = app.state::<crate::config::ConfigManager>().get();
+    // Chapter tracks are cut at the uploader's marks already.
+    if !config.trim_silence || chapters::split_id(video_id).1.is_some() {
+        return None;
+    }
+    let threshold = config.silence_threshold_db;
+    let found = app
+        .state::<crate::db::SearchCache>()
+        .get_audible_span(video_id, threshold)
+        .ok()
+        .flatten();
+    match (found, path) {
+        (Some(span), _) => Some(span),
+        (None, Some(path)) if analyze_now => silence::analyze_and_store(app, video_id, path, threshold),
+        (None, Some(path)) => {
+            let app = app.clone();
+            let video_id = video_id.to_string();
+            let path = path.to_path_buf();
+            std::thread::spawn(move || silence::analyze_and_store(&app, &video_id, &path, threshold));
+            None
+        }
+        (None, None) => None,
+    }
+}
 
-    Ok(sink)
+/// Shared settings of the processing stages every track runs through.
+#[derive(Clone)]
+pub struct Effects {
+    pub eq: Arc<RwLock<EqSettings>>,
+    pub stereo: Arc<RwLock<StereoSettings>>,
+    pub dynamics: Arc<RwLock<DynamicsSettings>>,
+    pub tempo: Arc<RwLock<TempoSettings>>,
+    pub spectrum: Spectrum,
+}
+
+/// Decode audio and wrap it in the gain, EQ, stereo, dynamics and tempo
+/// stages, with the spectrum tap last. `extension` hints
+/// the container to the probe. With a `window` (ms) only that part of the
+/// file plays, counted from its start. Comes with the clock that tracks its
+/// position.
+fn open_source(
+    source: Box<dyn symphonia::core::io::MediaSource>,
+    extension: Option<&str>,
+    gain: f32,
+    span: Option<AudibleSpan>,
+    window: Option<(u64, u64)>,
+    fade_in: bool,
+    effects: &Effects,
+) -> Result<Opened, crate::error::AppError> {
+    let decoder = NativeDecoder::new(source, extension)?;
+    let decoder: TrackSource = match window {
+        Some((start, end)) => Box::new(Window::new(decoder, start, end)),
+        None => Box::new(decoder),
+    };
+    let (decoder, clock) = Clocked::new(decoder);
+    let decoded: TrackSource = match span {
+        Some(span) => Box::new(Trim::new(decoder, span)),
+        None => Box::new(decoder),
+    };
+    let eq = EqSource::new(decoded.amplify(gain), effects.eq.clone(), fade_in);
+    let stereo = StereoSource::new(eq, effects.stereo.clone());
+    let limited = Dynamics::new(stereo, effects.dynamics.clone());
+    let stretched = TimeStretch::new(limited, effects.tempo.clone());
+    Ok((Box::new(effects.spectrum.tap(stretched)), clock))
+}
+
+fn open_file(
+    path: &std::path::Path,
+    gain: f32,
+    span: Option<AudibleSpan>,
+    window: Option<(u64, u64)>,
+    fade_in: bool,
+    effects: &Effects,
+) -> Result<Opened, crate::error::AppError> {
+    let file = std::fs::File::open(path)?;
+    let extension = path.extension().and_then(|e| e.to_str());
+    open_source(Box::new(file), extension, gain, span, window, fade_in, effects)
+}
+
+/// Open the track that is already playing again, from its download if it is
+/// still streaming, otherwise from the local copy.
+fn reopen_track(
+    app: &tauri::AppHandle,
+    video_id: &str,
+    streaming: Option<&Arc<Progressive>>,
+    effects: &Effects,
+) -> Result<Opened, crate::error::AppError> {
+    if let Some(dl) = streaming.filter(|dl| !dl.is_done()) {
+        let gain = normalization_gain(app, video_id, None, false);
+        let span = play_span(app, video_id, None, false);
+        return dl
+            .open_with(|file| open_source(Box::new(file), Some("webm"), gain, span, None, true, effects))
+            .map(|(source, clock)| (dl.gate(source), clock));
+    }
+    let (file_id, window) = chapters::split_id(video_id);
+    let path = local_audio(file_id, app, &cache_dir()?)
+        .ok_or_else(|| crate::error::AppError::Audio(format!("{video_id} is no longer cached")))?;
+    let gain = normalization_gain(app, file_id, Some(&path), false);
+    let span = play_span(app, video_id, None, false);
+    open_file(&path, gain, span, window, true, effects)
+}
+
+fn open_sink(output: Option<&Output>) -> Result<Sink, crate::error::AppError> {
+    output
+        .ok_or_else(|| crate::error::AppError::Audio("no audio output device".into()))?
+        .sink()
+}
+
+/// Whether Previous at `pos_ms` goes back to the start of the track rather
+/// than to the track before it.
+fn restarts_on_previous(pos_ms: u64) -> bool {
+    pos_ms > RESTART_THRESHOLD_MS
+}
+
+/// Position to report: a seek still waiting to be applied, or else where
+/// the playing source has got to.
+fn play_pos(held_ms: Option<u64>, clock: Option<&PlayClock>) -> u64 {
+    held_ms.or_else(|| clock.map(PlayClock::position_ms)).unwrap_or(0)
+}
+
+#[derive(serde::Serialize, Clone)]
+struct AbLoopPayload {
+    a_ms: u64,
+    b_ms: u64,
+}
+
+fn emit_ab_loop(app: &tauri::AppHandle, range: Option<(u64, u64)>) {
+    let _ = app.emit("ab-loop", range.map(|(a_ms, b_ms)| AbLoopPayload { a_ms, b_ms }));
+}
+
+/// Look up the chapters of `video_id`: from the cache, or for a long track
+/// that never was, from yt-dlp in the background. Arrive as
+/// [`AudioCommand::Chapters`] when there are any.
+fn look_up_chapters(
+    app: &tauri::AppHandle,
+    video_id: &str,
+    duration_ms: u64,
+    tx: &std::sync::mpsc::Sender<AudioCommand>,
+) {
+    // A chapter track is a single chapter already.
+    if chapters::split_id(video_id).1.is_some() {
+        return;
+    }
+    let known = app.state::<crate::db::SearchCache>().get_chapters(video_id).ok().flatten();
+    let send = {
+        let tx = tx.clone();
+        let video_id = video_id.to_string();
+        move |chapters: Vec<Chapter>| {
+            if !chapters.is_empty() {
+                let _ = tx.send(AudioCommand::Chapters { video_id, chapters });
+            }
+        }
+    };
+    if let Some(chapters) = known {
+        send(chapters);
+        return;
+    }
+    if duration_ms < CHAPTER_LOOKUP_MIN_MS {
+        return;
+    }
+    let app = app.clone();
+    let video_id = video_id.to_string();
+    tauri::async_runtime::spawn(async move {
+        match app.state::<crate::extraction::Extractor>().metadata(&video_id).await {
+            Ok((_, chapters)) => {
+                let _ = app.state::<crate::db::SearchCache>().set_chapters(&video_id, &chapters);
+                send(chapters);
+            }
+            Err(e) => eprintln!("[sunder] chapter lookup for {video_id} failed: {e}"),
+        }
+    });
+}
+
+/// Look up the SponsorBlock segments of `video_id` when skipping is on:
+/// from the cache while fresh, otherwise from the server in the background.
+/// The wanted categories arrive as [`AudioCommand::Segments`], moved into a
+/// chapter track's range.
+fn look_up_segments(app: &tauri::AppHandle, video_id: &str, tx: &std::sync::mpsc::Sender<AudioCommand>) {
+    let config = app.state::<crate::config::ConfigManager>().get();
+    if !config.sponsorblock_enabled {
+        return;
+    }
+    let (file_id, window) = chapters::split_id(video_id);
+    // A download cut by SponsorBlock has nothing left to skip, and its
+    // timings no longer match the upload's.
+    let download = crate::downloads::find_audio(&crate::downloads::DownloadManager::dir_for(app), file_id);
+    let cache = app.state::<crate::db::SearchCache>();
+    if download.is_some_and(|p| cache.is_cut_download(&p.to_string_lossy()).unwrap_or(false)) {
+        return;
+    }
+    let send = {
+        let tx = tx.clone();
+        let video_id = video_id.to_string();
+        let categories = config.sponsorblock_categories.clone();
+        move |all: Vec<Segment>| {
+            let segments = segments::to_skip(&all, &categories, window);
+            if !segments.is_empty() {
+                let _ = tx.send(AudioCommand::Segments { video_id, segments });
+            }
+        }
+    };
+    if let Some(known) = cache.get_segments(file_id).ok().flatten() {
+        send(known);
+        return;
+    }
+    let app = app.clone();
+    let file_id = file_id.to_string();
+    tauri::async_runtime::spawn(async move {
+        let extractor = app.state::<crate::extraction::Extractor>();
+        let categories = segments::to_look_up(&config.sponsorblock_categories);
+        let cache = app.state::<crate::db::SearchCache>();
+        match extractor.segments(&file_id, &config.sponsorblock_api, &categories).await {
+            Ok(all) => {
+                let _ = cache.set_segments(&file_id, &all);
+                send(all);
+            }
+            Err(e) => {
+                eprintln!("[sunder] SponsorBlock lookup for {file_id} failed: {e}");
+                let _ = cache.set_segments_failed(&file_id);
+            }
+        }
+    });
+}
+
+fn clear_chapters(app: &tauri::AppHandle, chapters: &mut Vec<Chapter>) {
+    if !chapters.is_empty() {
+        chapters.clear();
+        let _ = app.emit("chapters", &*chapters);
+    }
+}
+
+/// What the media controls show: while a chapter plays, its title, with the
+/// track's own title as the album.
+fn shown_metadata(m: &Metadata, chapter: Option<&Chapter>) -> Metadata {
+    let mut shown = m.clone();
+    if let Some(chapter) = chapter {
+        shown.title = chapter.title.clone();
+        shown.album = m.title.clone();
+    }
+    shown
 }
 
 #[derive(serde::Serialize, Clone)]
@@ -866,6 +2393,20 @@ struct ProgressPayload {
     state: String,
     volume: f32,
     speed: f32,
+    /// Speed the current track resumed at, playing in place of `speed`.
+    track_speed: Option<f32>,
+}
+
+/// Play at `playing` speed: the tempo stage and sink rate between them, and
+/// the rate the media controls show.
+fn apply_speed(effects: &Effects, controls: &mut Option<MediaControls>, sink: Option<&Sink>, playing: f32) {
+    effects.tempo.write().unwrap().speed = playing;
+    if let Some(c) = controls {
+        c.set_rate(playing as f64);
+    }
+    if let Some(sk) = sink {
+        sk.set_speed(effects.tempo.read().unwrap().sink_rate());
+    }
 }
 
 fn emit_state(
@@ -875,6 +2416,7 @@ fn emit_state(
     duration_ms: &Arc<AtomicU64>,
     volume: &Arc<RwLock<f32>>,
     speed: &Arc<RwLock<f32>>,
+    track_speed: Option<f32>,
 ) {
     let _ = app.emit(
         "playback-progress",
@@ -884,12 +2426,91 @@ fn emit_state(
             state: state.read().unwrap().to_string(),
             volume: *volume.read().unwrap(),
             speed: *speed.read().unwrap(),
+            track_speed,
         },
     );
 }
 
-fn parse_download_pct(line: &str) -> Option<f64> {
+pub(super) fn parse_download_pct(line: &str) -> Option<f64> {
     let content = line.trim().strip_prefix("[download]")?;
     let pct_end = content.find('%')?;
     content[..pct_end].trim().parse::<f64>().ok()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    /// `ms` of a constant mono signal at 8 kHz.
+    fn tone(ms: u64) -> TrackSource {
+        Box::new(SamplesBuffer::new(1, 8_000, vec![0.5f32; ms as usize * 8]))
+    }
+
+    #[test]
+    fn appended_track_takes_over_at_the_boundary() {
+        let wav = std::env::temp_dir().join(format!("sunder-handover-{}.wav", std::process::id()));
+        let output = Output::open(&output::Backend::Wav(wav.clone()), "").unwrap();
+        let sink = output.sink().unwrap();
+        let repeat_one = RepeatOne::default();
+        let eq = Arc::new(RwLock::new(EqSettings::default()));
+        let (first, _) = Clocked::new(tone(200));
+        let (second, clock) = Clocked::new(tone(2_000));
+        // Chained the way the engine opens a track and then its preload.
+        sink.append(repeat_one.wrap(Box::new(EqSource::new(first, eq.clone(), true))));
+        sink.append(repeat_one.wrap(Box::new(EqSource::new(second, eq, false))));
+        assert_eq!(sink.len(), 2);
+
+        std::thread::sleep(Duration::from_millis(500));
+        // The engine takes a sink down to one source as the handover.
+        assert_eq!(sink.len(), 1);
+        let pos = clock.position_ms();
+        assert!(pos > 200 && pos < 1_000, "{pos}");
+
+        // From the end of the first track's fade-in to well into the second,
+        // the level never dips.
+        drop(sink);
+        drop(output);
+        let bytes = std::fs::read(&wav).unwrap();
+        let _ = std::fs::remove_file(&wav);
+        let samples: Vec<i16> = bytes[44..].chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        let start = samples.iter().position(|&s| s != 0).unwrap();
+        let per_ms = 48 * 2;
+        let across = &samples[start + 160 * per_ms..start + 400 * per_ms];
+        let full = (0.5 * i16::MAX as f32) as i16;
+        assert!(across.iter().all(|&s| (s - full).abs() <= 2), "{:?}", across.iter().min());
+    }
+
+    #[test]
+    fn cue_points_narrow_the_audible_span() {
+        let audible = AudibleSpan { start_ms: 1_000, end_ms: 200_000 };
+        assert_eq!(within_cues(&CuePoints::default(), None), None);
+        assert_eq!(within_cues(&CuePoints::default(), Some(audible)), Some(audible));
+        let cues = CuePoints { start_ms: Some(30_000), end_ms: None };
+        assert_eq!(
+            within_cues(&cues, None),
+            Some(AudibleSpan { start_ms: 30_000, end_ms: u64::MAX })
+        );
+        let cues = CuePoints { start_ms: Some(500), end_ms: Some(150_000) };
+        assert_eq!(
+            within_cues(&cues, Some(audible)),
+            Some(AudibleSpan { start_ms: 1_000, end_ms: 150_000 })
+        );
+    }
+
+    #[test]
+    fn previous_restarts_a_track_once_it_has_got_going() {
+        assert!(!restarts_on_previous(0));
+        assert!(!restarts_on_previous(RESTART_THRESHOLD_MS));
+        assert!(restarts_on_previous(RESTART_THRESHOLD_MS + 1));
+        assert!(restarts_on_previous(180_000));
+    }
+
+    #[test]
+    fn a_pending_seek_is_the_position() {
+        let (_, clock) = Clocked::new(tone(1_000));
+        assert_eq!(play_pos(None, None), 0);
+        assert_eq!(play_pos(None, Some(&clock)), 0);
+        assert_eq!(play_pos(Some(750), Some(&clock)), 750);
+    }
+}
@@ -0,0 +1,196 @@
+//! Named EQ presets, and the Equalizer APO config format they import from
+//! and export to. AutoEQ's ParametricEQ.txt is a subset of it.
+
+use serde::{Deserialize, Serialize};
+
+use super::equalizer::{EqBand, FilterKind, MAX_BANDS};
+use crate::error::AppError;
+
+/// Q used by APO for filters that don't give one.
+const DEFAULT_Q: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EqPreset {
+    pub name: String,
+    pub preamp_db: f32,
+    pub bands: Vec<EqBand>,
+    /// Shipped with the app; can't be overwritten or deleted.
+    #[serde(default)]
+    pub builtin: bool,
+}
+
+/// The graphic presets the app has always offered.
+pub fn builtin_presets() -> Vec<EqPreset> {
+    [
+        ("Flat", [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+        ("Bass Boost", [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+        ("Treble Boost", [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 4.0, 5.0, 6.0]),
+        ("Vocal", [-2.0, -1.0, 0.0, 2.0, 4.0, 4.0, 2.0, 0.0, -1.0, -2.0]),
+        ("Rock", [4.0, 3.0, 1.0, 0.0, -1.0, 0.0, 1.0, 3.0, 4.0, 4.0]),
+        ("Pop", [-1.0, 1.0, 3.0, 4.0, 3.0, 0.0, -1.0, -1.0, 1.0, 2.0]),
+        ("Jazz", [3.0, 2.0, 0.0, 1.0, -1.0, -1.0, 0.0, 1.0, 3.0, 3.0]),
+        ("Electronic", [4.0, 3.0, 1.0, 0.0, -2.0, -1.0, 0.0, 3.0, 4.0, 3.0]),
+        ("Classical", [3.0, 2.0, 1.0, 0.0, -1.0, -1.0, 0.0, 1.0, 2.0, 3.0]),
+    ]
+    .into_iter()
+    .map(|(name, gains)| EqPreset {
+        name: name.into(),
+        preamp_db: 0.0,
+        bands: EqBand::graphic(&gains),
+        builtin: true,
+    })
+    .collect()
+}
+
+fn invalid(line: usize, msg: impl std::fmt::Display) -> AppError {
+    AppError::Audio(format!("EQ profile line {line}: {msg}"))
+}
+
+/// Read the preamp and filters from an Equalizer APO config or AutoEQ
+/// ParametricEQ.txt. Disabled filters and other directives are skipped.
+pub fn parse_profile(text: &str) -> Result<(f32, Vec<EqBand>), AppError> {
+    let mut preamp_db = 0.0;
+    let mut bands = Vec::new();
+    for (n, raw) in text.lines().enumerate() {
+        let n = n + 1;
+        let line = raw.split('#').next().unwrap_or("").trim();
+        let Some((directive, rest)) = line.split_once(':') else {
+            continue;
+        };
+        let directive = directive.trim();
+        let tokens: Vec<&str> = rest.split_whitespace().collect();
+        if directive.eq_ignore_ascii_case("preamp") {
+            // APO adds up every preamp it meets.
+            preamp_db += tokens
+                .first()
+                .and_then(|t| t.parse::<f32>().ok())
+                .ok_or_else(|| invalid(n, "preamp without a gain"))?;
+        } else if directive.eq_ignore_ascii_case("graphiceq") {
+            return Err(invalid(n, "GraphicEQ isn't supported, use the parametric profile"));
+        } else if directive.split_whitespace().next().is_some_and(|d| d.eq_ignore_ascii_case("filter")) {
+            if let Some(band) = parse_filter(n, &tokens)? {
+                bands.push(band.clamped());
+            }
+        }
+    }
+    if bands.is_empty() && preamp_db == 0.0 {
+        return Err(AppError::Audio("no EQ filters found".into()));
+    }
+    if bands.len() > MAX_BANDS {
+        return Err(AppError::Audio(format!("{} filters; at most {MAX_BANDS} are supported", bands.len())));
+    }
+    Ok((preamp_db, bands))
+}
+
+/// `ON PK Fc 105 Hz Gain 6.5 dB Q 0.70`, None when the filter is OFF.
+fn parse_filter(n: usize, tokens: &[&str]) -> Result<Option<EqBand>, AppError> {
+    match tokens.first().map(|t| t.to_ascii_uppercase()).as_deref() {
+        Some("ON") => {}
+        Some("OFF") => return Ok(None),
+        _ => return Err(invalid(n, "filter must be ON or OFF")),
+    }
+    let code = tokens.get(1).ok_or_else(|| invalid(n, "filter without a type"))?;
+    let kind = match code.to_ascii_uppercase().as_str() {
+        "PK" | "PEQ" | "MODAL" => FilterKind::Peaking,
+        "LS" | "LSC" => FilterKind::LowShelf,
+        "HS" | "HSC" => FilterKind::HighShelf,
+        "LP" | "LPQ" => FilterKind::LowPass,
+        "HP" | "HPQ" => FilterKind::HighPass,
+        "NO" => FilterKind::Notch,
+        other => return Err(invalid(n, format!("unsupported filter type {other}"))),
+    };
+    let value = |key: &str| -> Result<Option<f32>, AppError> {
+        match tokens.iter().position(|t| t.eq_ignore_ascii_case(key)) {
+            Some(i) => {
+                // Bandwidth comes as "BW Oct 1.5".
+                let at = if key == "BW" { i + 2 } else { i + 1 };
+                tokens
+                    .get(at)
+                    .and_then(|t| t.parse().ok())
+                    .map(Some)
+                    .ok_or_else(|| invalid(n, format!("{key} without a value")))
+            }
+            None => Ok(None),
+        }
+    };
+    let freq = value("Fc")?.ok_or_else(|| invalid(n, "filter without Fc"))?;
+    let q = match (value("Q")?, value("BW")?) {
+        (Some(q), _) => q,
+        (None, Some(octaves)) => {
+            let span = 2f32.powf(octaves);
+            span.sqrt() / (span - 1.0)
+        }
+        (None, None) => DEFAULT_Q,
+    };
+    Ok(Some(EqBand {
+        kind,
+        freq,
+        gain_db: value("Gain")?.unwrap_or(0.0),
+        q,
+    }))
+}
+
+/// Write `preset` as an Equalizer APO config. With only peaking and shelf
+/// bands this is also a valid AutoEQ ParametricEQ.txt.
+pub fn write_profile(preset: &EqPreset) -> String {
+    let mut out = format!("Preamp: {:.1} dB\n", preset.preamp_db);
+    for (i, b) in preset.bands.iter().enumerate() {
+        let code = match b.kind {
+            FilterKind::Peaking => "PK",
+            FilterKind::LowShelf => "LSC",
+            FilterKind::HighShelf => "HSC",
+            FilterKind::LowPass => "LPQ",
+            FilterKind::HighPass => "HPQ",
+            FilterKind::Notch => "NO",
+        };
+        out.push_str(&format!("Filter {}: ON {code} Fc {} Hz", i + 1, b.freq.round()));
+        if matches!(b.kind, FilterKind::Peaking | FilterKind::LowShelf | FilterKind::HighShelf) {
+            out.push_str(&format!(" Gain {:.1} dB", b.gain_db));
+        }
+        out.push_str(&format!(" Q {:.2}\n", b.q));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUTOEQ: &str = "Preamp: -6.2 dB
+Filter 1: ON LSC Fc 105 Hz Gain 6.5 dB Q 0.70
+Filter 2: ON PK Fc 180 Hz Gain -3.1 dB Q 0.52
+Filter 3: OFF PK Fc 900 Hz Gain 2.0 dB Q 1.00
+Filter 4: ON HSC Fc 10000 Hz Gain -2.4 dB Q 0.70
+";
+
+    #[test]
+    fn parses_autoeq_and_round_trips() {
+        let (preamp, bands) = parse_profile(AUTOEQ).unwrap();
+        assert!((preamp + 6.2).abs() < 1e-6);
+        assert_eq!(bands.len(), 3);
+        assert_eq!(bands[0].kind, FilterKind::LowShelf);
+        assert_eq!(bands[2].kind, FilterKind::HighShelf);
+        assert!((bands[1].gain_db + 3.1).abs() < 1e-6);
+
+        let preset = EqPreset { name: "x".into(), preamp_db: preamp, bands: bands.clone(), builtin: false };
+        let (again_preamp, again) = parse_profile(&write_profile(&preset)).unwrap();
+        assert!((again_preamp - preamp).abs() < 0.05);
+        assert_eq!(again.len(), bands.len());
+        assert!(again.iter().zip(&bands).all(|(a, b)| a.kind == b.kind && (a.q - b.q).abs() < 0.01));
+    }
+
+    #[test]
+    fn reads_apo_extras_and_rejects_the_unknown() {
+        let (preamp, bands) = parse_profile(
+            "# headphones\nDevice: all\nPreamp: -3 dB\nPreamp: -1 dB\nFilter: ON PK Fc 1000 Hz Gain 3 dB BW Oct 1.0\nFilter: ON LP Fc 15000 Hz",
+        )
+        .unwrap();
+        assert!((preamp + 4.0).abs() < 1e-6);
+        assert!((bands[0].q - 1.414).abs() < 0.01);
+        assert!((bands[1].q - DEFAULT_Q).abs() < 1e-6);
+
+        assert!(parse_profile("Filter: ON BP Fc 100 Hz").is_err());
+        assert!(parse_profile("GraphicEQ: 20 -1; 40 0").is_err());
+        assert!(parse_profile("nothing here").is_err());
+    }
+}
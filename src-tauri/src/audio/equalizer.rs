@@ -1,39 +1,176 @@
 use std::sync::{Arc, RwLock};
 
 use rodio::Source;
+use serde::{Deserialize, Serialize};
 
-pub const BAND_COUNT: usize = 10;
-
-const BAND_FREQUENCIES: [f32; BAND_COUNT] = [
+/// Centre frequencies of the classic ten-band graphic EQ.
+pub const GRAPHIC_FREQUENCIES: [f32; 10] = [
     32.0, 64.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
 ];
 
-const Q_FACTOR: f32 = 1.414;
+const GRAPHIC_Q: f32 = 1.414;
+
+pub const MAX_BANDS: usize = 32;
+pub const MAX_GAIN_DB: f32 = 24.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    Peaking,
+    LowShelf,
+    HighShelf,
+    LowPass,
+    HighPass,
+    Notch,
+}
+
+/// One filter of the EQ. `gain_db` only matters to peaking and shelf bands.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EqBand {
+    pub kind: FilterKind,
+    pub freq: f32,
+    pub gain_db: f32,
+    pub q: f32,
+}
+
+impl EqBand {
+    /// The ten graphic bands at the given gains.
+    pub fn graphic(gains: &[f32]) -> Vec<Self> {
+        GRAPHIC_FREQUENCIES
+            .iter()
+            .zip(gains.iter().chain(std::iter::repeat(&0.0)))
+            .map(|(&freq, &gain_db)| Self {
+                kind: FilterKind::Peaking,
+                freq,
+                gain_db,
+                q: GRAPHIC_Q,
+            })
+            .collect()
+    }
+
+    /// Pulled into a range every filter design handles.
+    pub fn clamped(self) -> Self {
+        Self {
+            kind: self.kind,
+            freq: self.freq.clamp(10.0, 22_000.0),
+            gain_db: self.gain_db.clamp(-MAX_GAIN_DB, MAX_GAIN_DB),
+            q: self.q.clamp(0.1, 20.0),
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct EqSettings {
     pub enabled: bool,
-    pub gains: [f32; BAND_COUNT],
+    /// Gain before the filters, so boosts have headroom.
+    pub preamp_db: f32,
+    /// Use `auto_preamp_db` of the bands instead of `preamp_db`.
+    pub auto_preamp: bool,
+    pub bands: Vec<EqBand>,
 }
 
 impl Default for EqSettings {
     fn default() -> Self {
         Self {
             enabled: false,
-            gains: [0.0; BAND_COUNT],
+            preamp_db: 0.0,
+            auto_preamp: false,
+            bands: EqBand::graphic(&[]),
         }
     }
 }
 
-struct BiquadCoeffs {
-    b0: f64,
-    b1: f64,
-    b2: f64,
-    a1: f64,
-    a2: f64,
+pub(super) struct BiquadCoeffs {
+    pub(super) b0: f64,
+    pub(super) b1: f64,
+    pub(super) b2: f64,
+    pub(super) a1: f64,
+    pub(super) a2: f64,
 }
 
-struct BiquadState {
+impl BiquadCoeffs {
+    /// RBJ cookbook design of `band` at sample rate `sr`.
+    pub(super) fn design(band: &EqBand, sr: f32) -> Self {
+        // Above Nyquist the formulas fold back; keep just under it.
+        let freq = band.freq.min(sr * 0.49) as f64;
+        let a = 10.0_f64.powf(band.gain_db as f64 / 40.0);
+        let w0 = 2.0 * std::f64::consts::PI * freq / sr as f64;
+        let (sin_w0, cos_w0) = w0.sin_cos();
+        let alpha = sin_w0 / (2.0 * band.q as f64);
+
+        let (b0, b1, b2, a0, a1, a2) = match band.kind {
+            FilterKind::Peaking => (
+                1.0 + alpha * a,
+                -2.0 * cos_w0,
+                1.0 - alpha * a,
+                1.0 + alpha / a,
+                -2.0 * cos_w0,
+                1.0 - alpha / a,
+            ),
+            FilterKind::LowShelf => {
+                let s = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos_w0 + s),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos_w0),
+                    a * ((a + 1.0) - (a - 1.0) * cos_w0 - s),
+                    (a + 1.0) + (a - 1.0) * cos_w0 + s,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos_w0),
+                    (a + 1.0) + (a - 1.0) * cos_w0 - s,
+                )
+            }
+            FilterKind::HighShelf => {
+                let s = 2.0 * a.sqrt() * alpha;
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos_w0 + s),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos_w0),
+                    a * ((a + 1.0) + (a - 1.0) * cos_w0 - s),
+                    (a + 1.0) - (a - 1.0) * cos_w0 + s,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos_w0),
+                    (a + 1.0) - (a - 1.0) * cos_w0 - s,
+                )
+            }
+            FilterKind::LowPass => (
+                (1.0 - cos_w0) / 2.0,
+                1.0 - cos_w0,
+                (1.0 - cos_w0) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos_w0,
+                1.0 - alpha,
+            ),
+            FilterKind::HighPass => (
+                (1.0 + cos_w0) / 2.0,
+                -(1.0 + cos_w0),
+                (1.0 + cos_w0) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos_w0,
+                1.0 - alpha,
+            ),
+            FilterKind::Notch => (1.0, -2.0 * cos_w0, 1.0, 1.0 + alpha, -2.0 * cos_w0, 1.0 - alpha),
+        };
+        Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    /// Gain at `freq`, in dB.
+    fn response_db(&self, freq: f64, sr: f64) -> f64 {
+        let w = 2.0 * std::f64::consts::PI * freq / sr;
+        let (s1, c1) = w.sin_cos();
+        let (s2, c2) = (2.0 * w).sin_cos();
+        let num_re = self.b0 + self.b1 * c1 + self.b2 * c2;
+        let num_im = -(self.b1 * s1 + self.b2 * s2);
+        let den_re = 1.0 + self.a1 * c1 + self.a2 * c2;
+        let den_im = -(self.a1 * s1 + self.a2 * s2);
+        let power = (num_re * num_re + num_im * num_im) / (den_re * den_re + den_im * den_im);
+        10.0 * power.max(1e-20).log10()
+    }
+}
+
+pub(super) struct BiquadState {
     x1: f64,
     x2: f64,
     y1: f64,
@@ -41,7 +178,7 @@ struct BiquadState {
 }
 
 impl BiquadState {
-    fn new() -> Self {
+    pub(super) fn new() -> Self {
         Self {
             x1: 0.0,
             x2: 0.0,
@@ -50,7 +187,7 @@ impl BiquadState {
         }
     }
 
-    fn process(&mut self, c: &BiquadCoeffs, x: f64) -> f64 {
+    pub(super) fn process(&mut self, c: &BiquadCoeffs, x: f64) -> f64 {
         let y = c.b0 * x + c.b1 * self.x1 + c.b2 * self.x2 - c.a1 * self.y1 - c.a2 * self.y2;
         self.x2 = self.x1;
         self.x1 = x;
@@ -60,21 +197,26 @@ impl BiquadState {
     }
 }
 
-fn peaking_eq(freq: f32, gain_db: f32, q: f32, sr: f32) -> BiquadCoeffs {
-    let a = 10.0_f64.powf(gain_db as f64 / 40.0);
-    let w0 = 2.0 * std::f64::consts::PI * freq as f64 / sr as f64;
-    let sin_w0 = w0.sin();
-    let cos_w0 = w0.cos();
-    let alpha = sin_w0 / (2.0 * q as f64);
+/// Combined response of `bands` at `points` log-spaced frequencies from
+/// 20 Hz to 20 kHz, as (Hz, dB) pairs.
+pub fn frequency_response(bands: &[EqBand], sr: f32, points: usize) -> Vec<(f32, f32)> {
+    let coeffs: Vec<BiquadCoeffs> = bands.iter().map(|b| BiquadCoeffs::design(b, sr)).collect();
+    let points = points.max(2);
+    (0..points)
+        .map(|i| {
+            let freq = 20.0 * 1000f64.powf(i as f64 / (points - 1) as f64);
+            let db: f64 = coeffs.iter().map(|c| c.response_db(freq, sr as f64)).sum();
+            (freq as f32, db as f32)
+        })
+        .collect()
+}
 
-    let a0 = 1.0 + alpha / a;
-    BiquadCoeffs {
-        b0: (1.0 + alpha * a) / a0,
-        b1: (-2.0 * cos_w0) / a0,
-        b2: (1.0 - alpha * a) / a0,
-        a1: (-2.0 * cos_w0) / a0,
-        a2: (1.0 - alpha / a) / a0,
-    }
+/// Preamp that keeps the loudest boost of `bands` from going over 0 dBFS.
+pub fn auto_preamp_db(bands: &[EqBand], sr: f32) -> f32 {
+    let peak = frequency_response(bands, sr, 256)
+        .into_iter()
+        .fold(0.0f32, |max, (_, db)| max.max(db));
+    -peak
 }
 
 pub struct EqSource<S: Source<Item = f32>> {
@@ -82,8 +224,10 @@ pub struct EqSource<S: Source<Item = f32>> {
     settings: Arc<RwLock<EqSettings>>,
     states: Vec<Vec<BiquadState>>,
     coeffs: Vec<BiquadCoeffs>,
-    cached_gains: [f32; BAND_COUNT],
+    cached_bands: Vec<EqBand>,
     enabled: bool,
+    preamp: f64,
+    auto_preamp_db: f32,
     channels: u16,
     sample_rate: u32,
     channel_idx: u16,
@@ -92,47 +236,51 @@ pub struct EqSource<S: Source<Item = f32>> {
 }
 
 impl<S: Source<Item = f32>> EqSource<S> {
-    pub fn new(inner: S, settings: Arc<RwLock<EqSettings>>) -> Self {
+    /// `fade_in` fades the start in to keep it from popping; a source that
+    /// continues another one gaplessly starts at full level instead.
+    pub fn new(inner: S, settings: Arc<RwLock<EqSettings>>, fade_in: bool) -> Self {
         let channels = inner.channels();
         let sample_rate = inner.sample_rate();
-
-        let states = (0..channels)
-            .map(|_| (0..BAND_COUNT).map(|_| BiquadState::new()).collect())
-            .collect();
-
-        let coeffs = BAND_FREQUENCIES
-            .iter()
-            .map(|&f| peaking_eq(f, 0.0, Q_FACTOR, sample_rate as f32))
-            .collect();
-
         let enabled = settings.read().unwrap().enabled;
+        let fade_samples = (sample_rate as f32 * 0.15) as usize; // 150ms fade
 
-        Self {
+        let mut eq = Self {
             inner,
             settings,
-            states,
-            coeffs,
-            cached_gains: [0.0; BAND_COUNT],
+            states: (0..channels).map(|_| Vec::new()).collect(),
+            coeffs: Vec::new(),
+            cached_bands: Vec::new(),
             enabled,
+            preamp: 1.0,
+            auto_preamp_db: 0.0,
             channels,
             sample_rate,
             channel_idx: 0,
-            fade_samples: (sample_rate as f32 * 0.15) as usize, // 150ms fade
-            fade_counter: 0,
-        }
+            fade_samples,
+            fade_counter: if fade_in { 0 } else { fade_samples },
+        };
+        eq.refresh();
+        eq
     }
 
     fn refresh(&mut self) {
         let s = self.settings.read().unwrap();
         self.enabled = s.enabled;
-        if s.gains != self.cached_gains {
-            self.cached_gains = s.gains;
-            self.coeffs = BAND_FREQUENCIES
+        if s.bands != self.cached_bands {
+            self.cached_bands = s.bands.clone();
+            self.coeffs = self
+                .cached_bands
                 .iter()
-                .zip(self.cached_gains.iter())
-                .map(|(&f, &g)| peaking_eq(f, g, Q_FACTOR, self.sample_rate as f32))
+                .map(|b| BiquadCoeffs::design(b, self.sample_rate as f32))
                 .collect();
+            // Bands keep their filter memory; only added ones start fresh.
+            for ch_states in &mut self.states {
+                ch_states.resize_with(self.coeffs.len(), BiquadState::new);
+            }
+            self.auto_preamp_db = auto_preamp_db(&self.cached_bands, self.sample_rate as f32);
         }
+        let preamp_db = if s.auto_preamp { self.auto_preamp_db } else { s.preamp_db };
+        self.preamp = 10f64.powf(preamp_db as f64 / 20.0);
     }
 }
 
@@ -149,11 +297,12 @@ impl<S: Source<Item = f32>> Iterator for EqSource<S> {
         }
 
         let mut out = if self.enabled {
-            let mut v = sample as f64;
+            let mut v = sample as f64 * self.preamp;
             for (i, state) in self.states[ch].iter_mut().enumerate() {
                 v = state.process(&self.coeffs[i], v);
             }
-            v.clamp(-1.0, 1.0) as f32
+            // Overs are left to the limiter after the EQ.
+            v as f32
         } else {
             sample
         };
@@ -201,3 +350,58 @@ impl<S: Source<Item = f32>> Source for EqSource<S> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn band(kind: FilterKind, freq: f32, gain_db: f32) -> EqBand {
+        EqBand { kind, freq, gain_db, q: 0.707 }
+    }
+
+    fn at(bands: &[EqBand], freq: f32) -> f32 {
+        let curve = frequency_response(bands, 48_000.0, 301);
+        curve
+            .iter()
+            .min_by(|a, b| (a.0 - freq).abs().total_cmp(&(b.0 - freq).abs()))
+            .unwrap()
+            .1
+    }
+
+    #[test]
+    fn filter_types_shape_the_response() {
+        let peak = [EqBand { q: 1.0, ..band(FilterKind::Peaking, 1000.0, 6.0) }];
+        assert!((at(&peak, 1000.0) - 6.0).abs() < 0.2);
+        assert!(at(&peak, 20.0).abs() < 0.2);
+
+        let low_shelf = [band(FilterKind::LowShelf, 200.0, -6.0)];
+        assert!((at(&low_shelf, 20.0) + 6.0).abs() < 0.3);
+        assert!(at(&low_shelf, 10_000.0).abs() < 0.3);
+
+        let high_shelf = [band(FilterKind::HighShelf, 5000.0, 4.0)];
+        assert!((at(&high_shelf, 20_000.0) - 4.0).abs() < 0.5);
+
+        let low_pass = [band(FilterKind::LowPass, 1000.0, 0.0)];
+        assert!(at(&low_pass, 100.0).abs() < 0.2);
+        assert!(at(&low_pass, 10_000.0) < -30.0);
+
+        let high_pass = [band(FilterKind::HighPass, 1000.0, 0.0)];
+        assert!(at(&high_pass, 100.0) < -30.0);
+
+        let notch = [EqBand { q: 5.0, ..band(FilterKind::Notch, 1000.0, 0.0) }];
+        assert!(at(&notch, 1000.0) < -30.0);
+        assert!(at(&notch, 5000.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn band_responses_add_up() {
+        let low = band(FilterKind::LowShelf, 300.0, 5.0);
+        let peak = band(FilterKind::Peaking, 500.0, -3.0);
+        for freq in [100.0, 500.0, 2000.0] {
+            let sum = at(&[low], freq) + at(&[peak], freq);
+            assert!((at(&[low, peak], freq) - sum).abs() < 1e-3);
+        }
+        // Neighbouring graphic bands overlap, lifting the middle further.
+        assert!(at(&EqBand::graphic(&[3.0; 10]), 1000.0) > 3.0);
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+use std::path::Path;
+
+use rodio::Source;
+
+use super::decoder::NativeDecoder;
+use super::equalizer::{BiquadCoeffs, BiquadState};
+use crate::error::AppError;
+
+/// Result of an EBU R128 / ITU-R BS.1770 measurement.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+pub struct Loudness {
+    /// Gated integrated loudness in LUFS.
+    pub integrated_lufs: f64,
+    /// Maximum inter-sample peak in dBTP.
+    pub true_peak_dbtp: f64,
+}
+
+impl Loudness {
+    /// Treat several tracks as one programme (album mode). The integrated
+    /// value is the energy mean of the tracks, which is close to measuring
+    /// the whole album in one pass without having to decode it again.
+    pub fn combine(tracks: &[Loudness]) -> Option<Loudness> {
+        if tracks.is_empty() {
+            return None;
+        }
+        let energy = tracks
+            .iter()
+            .map(|l| 10f64.powf(l.integrated_lufs / 10.0))
+            .sum::<f64>()
+            / tracks.len() as f64;
+        Some(Loudness {
+            integrated_lufs: 10.0 * energy.log10(),
+            true_peak_dbtp: tracks
+                .iter()
+                .map(|l| l.true_peak_dbtp)
+                .fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+
+    /// Gain in dB that brings this measurement to `target_lufs`, limited so
+    /// the true peak stays under -1 dBTP.
+    pub fn gain_db(&self, target_lufs: f64) -> f64 {
+        let gain = target_lufs - self.integrated_lufs;
+        gain.min(TRUE_PEAK_CEILING_DBTP - self.true_peak_dbtp)
+    }
+}
+
+const TRUE_PEAK_CEILING_DBTP: f64 = -1.0;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Gating blocks are 400ms long and overlap by 75%, so they are built from
+/// four consecutive 100ms sub-blocks.
+const SUB_BLOCKS_PER_BLOCK: usize = 4;
+
+/// 4x oversampling for true-peak detection, 12 taps per phase.
+const OVERSAMPLE: usize = 4;
+const TAPS_PER_PHASE: usize = 12;
+
+pub struct LoudnessMeter {
+    channels: usize,
+    shelf: BiquadCoeffs,
+    highpass: BiquadCoeffs,
+    filters: Vec<[BiquadState; 2]>,
+    sub_block_len: usize,
+    sub_block_energy: f64,
+    sub_block_pos: usize,
+    recent: VecDeque<f64>,
+    blocks: Vec<f64>,
+    interp: Vec<[f64; TAPS_PER_PHASE]>,
+    history: Vec<[f64; TAPS_PER_PHASE]>,
+    peak: f64,
+    channel_idx: usize,
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        let channels = channels.max(1) as usize;
+        let sr = sample_rate.max(1) as f64;
+        Self {
+            channels,
+            shelf: k_weighting_shelf(sr),
+            highpass: k_weighting_highpass(sr),
+            filters: (0..channels)
+                .map(|_| [BiquadState::new(), BiquadState::new()])
+                .collect(),
+            sub_block_len: ((sr / 10.0).round() as usize).max(1),
+            sub_block_energy: 0.0,
+            sub_block_pos: 0,
+            recent: VecDeque::with_capacity(SUB_BLOCKS_PER_BLOCK),
+            blocks: Vec::new(),
+            interp: interpolation_phases(),
+            history: vec![[0.0; TAPS_PER_PHASE]; channels],
+            peak: 0.0,
+            channel_idx: 0,
+        }
+    }
+
+    /// Feed interleaved samples.
+    pub fn push(&mut self, sample: f32) {
+        let ch = self.channel_idx;
+        let x = sample as f64;
+
+        let [shelf, highpass] = &mut self.filters[ch];
+        let weighted = highpass.process(&self.highpass, shelf.process(&self.shelf, x));
+        // Surround channels (Ls/Rs in 5.1) carry a +1.5 dB weight; the LFE
+        // channel is left out of the sum altogether.
+        let weight = match ch {
+            3 if self.channels >= 6 => 0.0,
+            4.. if self.channels > 4 => 1.41,
+            _ => 1.0,
+        };
+        self.sub_block_energy += weight * weighted * weighted;
+
+        let history = &mut self.history[ch];
+        history.copy_within(1.., 0);
+        history[TAPS_PER_PHASE - 1] = x;
+        for phase in &self.interp {
+            let y: f64 = phase.iter().zip(history.iter()).map(|(h, s)| h * s).sum();
+            self.peak = self.peak.max(y.abs());
+        }
+        self.peak = self.peak.max(x.abs());
+
+        self.channel_idx += 1;
+        if self.channel_idx == self.channels {
+            self.channel_idx = 0;
+            self.sub_block_pos += 1;
+            if self.sub_block_pos == self.sub_block_len {
+                self.finish_sub_block();
+            }
+        }
+    }
+
+    fn finish_sub_block(&mut self) {
+        if self.recent.len() == SUB_BLOCKS_PER_BLOCK {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(self.sub_block_energy / self.sub_block_len as f64);
+        self.sub_block_energy = 0.0;
+        self.sub_block_pos = 0;
+        if self.recent.len() == SUB_BLOCKS_PER_BLOCK {
+            self.blocks
+                .push(self.recent.iter().sum::<f64>() / SUB_BLOCKS_PER_BLOCK as f64);
+        }
+    }
+
+    /// Final measurement, or `None` when nothing rose above the absolute gate
+    /// (silence or a track shorter than one gating block).
+    pub fn finish(&self) -> Option<Loudness> {
+        let abs_gated: Vec<f64> = self
+            .blocks
+            .iter()
+            .copied()
+            .filter(|&e| energy_to_lufs(e) > ABSOLUTE_GATE_LUFS)
+            .collect();
+        if abs_gated.is_empty() {
+            return None;
+        }
+        let relative_gate =
+            energy_to_lufs(abs_gated.iter().sum::<f64>() / abs_gated.len() as f64)
+                + RELATIVE_GATE_LU;
+        let gated: Vec<f64> = abs_gated
+            .into_iter()
+            .filter(|&e| energy_to_lufs(e) > relative_gate)
+            .collect();
+        if gated.is_empty() {
+            return None;
+        }
+        Some(Loudness {
+            integrated_lufs: energy_to_lufs(gated.iter().sum::<f64>() / gated.len() as f64),
+            true_peak_dbtp: 20.0 * self.peak.max(1e-9).log10(),
+        })
+    }
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.max(1e-12).log10()
+}
+
+/// Stage 1 of the K-weighting filter: a high shelf modelling the head.
+/// Coefficients are derived for any rate so they match BS.1770 at 48 kHz.
+fn k_weighting_shelf(sr: f64) -> BiquadCoeffs {
+    let f0 = 1_681.974_450_955_533;
+    let gain_db = 3.999_843_853_973_347;
+    let q = 0.707_175_236_955_419_6;
+    let k = (std::f64::consts::PI * f0 / sr).tan();
+    let vh = 10f64.powf(gain_db / 20.0);
+    let vb = vh.powf(0.499_666_774_154_541_6);
+    let a0 = 1.0 + k / q + k * k;
+    BiquadCoeffs {
+        b0: (vh + vb * k / q + k * k) / a0,
+        b1: 2.0 * (k * k - vh) / a0,
+        b2: (vh - vb * k / q + k * k) / a0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+    }
+}
+
+/// Stage 2 of the K-weighting filter: the RLB high-pass.
+fn k_weighting_highpass(sr: f64) -> BiquadCoeffs {
+    let f0 = 38.135_470_876_024_44;
+    let q = 0.500_327_037_323_877_3;
+    let k = (std::f64::consts::PI * f0 / sr).tan();
+    let a0 = 1.0 + k / q + k * k;
+    BiquadCoeffs {
+        b0: 1.0,
+        b1: -2.0,
+        b2: 1.0,
+        a1: 2.0 * (k * k - 1.0) / a0,
+        a2: (1.0 - k / q + k * k) / a0,
+    }
+}
+
+/// Polyphase windowed-sinc interpolator used to estimate inter-sample peaks.
+/// Each phase is applied to the last `TAPS_PER_PHASE` input samples (oldest
+/// first) and yields one of the oversampled points between them.
+fn interpolation_phases() -> Vec<[f64; TAPS_PER_PHASE]> {
+    let len = OVERSAMPLE * TAPS_PER_PHASE;
+    let center = (len - 1) as f64 / 2.0;
+    (0..OVERSAMPLE)
+        .map(|phase| {
+            let mut taps = [0.0; TAPS_PER_PHASE];
+            for (i, tap) in taps.iter_mut().enumerate() {
+                let n = (TAPS_PER_PHASE - 1 - i) * OVERSAMPLE + phase;
+                let x = (n as f64 - center) / OVERSAMPLE as f64;
+                let sinc = if x.abs() < 1e-9 {
+                    1.0
+                } else {
+                    (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+                };
+                let window = 0.5
+                    - 0.5 * (2.0 * std::f64::consts::PI * (n as f64 + 0.5) / len as f64).cos();
+                *tap = sinc * window;
+            }
+            let sum: f64 = taps.iter().sum();
+            if sum.abs() > 1e-9 {
+                taps.iter_mut().for_each(|t| *t /= sum);
+            }
+            taps
+        })
+        .collect()
+}
+
+/// Decode a whole file and measure it. Runs at decode speed, so callers keep
+/// it off the audio thread.
+pub fn analyze_file(path: &Path) -> Result<Option<Loudness>, AppError> {
+    let file = std::fs::File::open(path)?;
+    let decoder = NativeDecoder::new(Box::new(file), path.extension().and_then(|e| e.to_str()))?;
+    let mut meter = LoudnessMeter::new(decoder.channels(), decoder.sample_rate());
+    for sample in decoder {
+        meter.push(sample);
+    }
+    Ok(meter.finish())
+}
+
+/// Measure a track and remember the result. Failures are logged and leave
+/// the track unanalysed so the fallback gain keeps applying.
+pub fn analyze_and_store(app: &tauri::AppHandle, track_id: &str, path: &Path) -> Option<Loudness> {
+    use tauri::Manager;
+    match analyze_file(path) {
+        Ok(Some(l)) => {
+            eprintln!(
+                "[sunder] loudness {track_id}: {:.1} LUFS, {:.1} dBTP",
+                l.integrated_lufs, l.true_peak_dbtp
+            );
+            let _ = app.state::<crate::db::SearchCache>().set_loudness(track_id, &l);
+            Some(l)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            eprintln!("[sunder] loudness analysis failed for {track_id}: {e}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(meter: &mut LoudnessMeter, freq: f64, amplitude: f64, rate: u32, channels: u16, secs: f64) {
+        let frames = (rate as f64 * secs) as usize;
+        for i in 0..frames {
+            let v = amplitude * (2.0 * std::f64::consts::PI * freq * i as f64 / rate as f64).sin();
+            for _ in 0..channels {
+                meter.push(v as f32);
+            }
+        }
+    }
+
+    #[test]
+    fn full_scale_1k_sine_reads_minus_3_lufs_per_channel() {
+        // BS.1770: a 0 dBFS 1 kHz sine in one channel reads -3.01 LKFS.
+        let mut meter = LoudnessMeter::new(1, 48_000);
+        sine(&mut meter, 1000.0, 1.0, 48_000, 1, 5.0);
+        let l = meter.finish().unwrap();
+        assert!((l.integrated_lufs + 3.01).abs() < 0.1, "{}", l.integrated_lufs);
+        assert!(l.true_peak_dbtp.abs() < 0.2, "{}", l.true_peak_dbtp);
+    }
+
+    #[test]
+    fn stereo_minus_20_dbfs_sine_reads_minus_20_lufs() {
+        let mut meter = LoudnessMeter::new(2, 44_100);
+        sine(&mut meter, 1000.0, 0.1, 44_100, 2, 5.0);
+        let l = meter.finish().unwrap();
+        assert!((l.integrated_lufs + 20.0).abs() < 0.1, "{}", l.integrated_lufs);
+    }
+
+    #[test]
+    fn lfe_channel_is_not_measured() {
+        // Rumble on the LFE channel of a 5.1 stream, silence everywhere else.
+        let mut meter = LoudnessMeter::new(6, 48_000);
+        for i in 0..48_000 * 2 {
+            let v = 0.5 * (2.0 * std::f64::consts::PI * 60.0 * i as f64 / 48_000.0).sin();
+            for ch in 0..6 {
+                meter.push(if ch == 3 { v as f32 } else { 0.0 });
+            }
+        }
+        assert!(meter.finish().is_none());
+    }
+
+    #[test]
+    fn silence_is_gated_out() {
+        let mut meter = LoudnessMeter::new(2, 48_000);
+        sine(&mut meter, 1000.0, 0.0, 48_000, 2, 2.0);
+        assert!(meter.finish().is_none());
+    }
+
+    #[test]
+    fn gain_respects_true_peak_ceiling() {
+        let quiet = Loudness { integrated_lufs: -24.0, true_peak_dbtp: -3.0 };
+        assert!((quiet.gain_db(-14.0) - 2.0).abs() < 1e-9);
+        let loud = Loudness { integrated_lufs: -8.0, true_peak_dbtp: 0.5 };
+        assert!((loud.gain_db(-14.0) + 6.0).abs() < 1e-9);
+    }
+}
@@ -1,6 +1,26 @@
+pub mod chapters;
+pub mod clock;
+pub mod controls;
+pub mod crossfade;
+pub mod decoder;
+pub mod dynamics;
 pub mod engine;
+pub mod eq_presets;
 pub mod equalizer;
+pub mod loudness;
+#[cfg(target_os = "linux")]
+pub mod mpris;
+pub mod output;
+pub mod progressive;
+pub mod queue;
+pub mod repeat;
+pub mod segments;
+pub mod silence;
+pub mod sleep;
+pub mod spectrum;
 pub mod state;
+pub mod stereo;
+pub mod tempo;
 pub mod art_worker;
 
 pub use engine::AudioHandle;
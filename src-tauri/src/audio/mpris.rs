@@ -0,0 +1,518 @@
+//! MPRIS service on the session bus. souvlaki's covers the basics only, so
+//! Linux gets its own to expose loop, shuffle, rate and the track list.
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dbus::arg::{PropMap, RefArg, Variant};
+use dbus::blocking::stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged;
+use dbus::blocking::Connection;
+use dbus::channel::Sender;
+use dbus::message::{MessageType, SignalArgs};
+use dbus::{Message, Path};
+use dbus_crossroads::{Crossroads, IfaceBuilder, MethodErr};
+use souvlaki::{MediaControlEvent, MediaPlayback, MediaPosition, SeekDirection};
+
+use super::controls::{ControlEvent, Metadata};
+use super::engine::{MAX_SPEED, MIN_SPEED};
+use super::queue::{QueueSnapshot, RepeatMode};
+use crate::error::AppError;
+
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER_IFACE: &str = "org.mpris.MediaPlayer2.Player";
+const TRACKLIST_IFACE: &str = "org.mpris.MediaPlayer2.TrackList";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+type Handler = Arc<dyn Fn(ControlEvent) + Send + Sync>;
+
+enum Update {
+    Metadata(Metadata),
+    Playback(MediaPlayback),
+    Volume(f64),
+    Repeat(RepeatMode),
+    Rate(f64),
+    Queue(QueueSnapshot),
+    Seeked(Duration),
+}
+
+/// What the player properties report; lives in the object tree.
+struct State {
+    metadata: Metadata,
+    playing: Option<bool>,
+    position: Duration,
+    /// When `position` was last reported.
+    reported: Instant,
+    rate: f64,
+    volume: f64,
+    repeat: RepeatMode,
+    shuffle: bool,
+    tracks: Vec<Metadata>,
+}
+
+impl State {
+    fn status(&self) -> &'static str {
+        match self.playing {
+            Some(true) => "Playing",
+            Some(false) => "Paused",
+            None => "Stopped",
+        }
+    }
+
+    /// Position extrapolated from the last report while playing.
+    fn position(&self) -> Duration {
+        if self.playing == Some(true) {
+            self.position + self.reported.elapsed().mul_f64(self.rate)
+        } else {
+            self.position
+        }
+    }
+
+    fn track_ids(&self) -> Vec<Path<'static>> {
+        self.tracks.iter().map(|t| track_path(&t.track_id)).collect()
+    }
+}
+
+/// Object path naming a track. Video ids may hold `-` and `_`, which paths
+/// don't allow, so anything but ASCII letters and digits is hex-escaped.
+fn track_path(id: &str) -> Path<'static> {
+    if id.is_empty() {
+        return Path::from(NO_TRACK);
+    }
+    let mut path = String::from("/org/mpris/MediaPlayer2/Track/");
+    for b in id.bytes() {
+        if b.is_ascii_alphanumeric() {
+            path.push(b as char);
+        } else {
+            path.push_str(&format!("_{b:02x}"));
+        }
+    }
+    Path::from(path)
+}
+
+fn metadata_dict(m: &Metadata) -> PropMap {
+    let mut dict = PropMap::new();
+    let mut insert = |k: &str, v: Box<dyn RefArg>| {
+        dict.insert(k.to_string(), Variant(v));
+    };
+    insert("mpris:trackid", Box::new(track_path(&m.track_id)));
+    insert("mpris:length", Box::new(m.length.as_micros() as i64));
+    if !m.art_url.is_empty() {
+        insert("mpris:artUrl", Box::new(m.art_url.clone()));
+    }
+    insert("xesam:title", Box::new(m.title.clone()));
+    insert("xesam:artist", Box::new(vec![m.artist.clone()]));
+    if !m.album.is_empty() {
+        insert("xesam:album", Box::new(m.album.clone()));
+    }
+    dict
+}
+
+fn loop_status(mode: RepeatMode) -> &'static str {
+    match mode {
+        RepeatMode::Off => "None",
+        RepeatMode::Queue => "Playlist",
+        RepeatMode::Track => "Track",
+    }
+}
+
+fn parse_loop_status(status: &str) -> Option<RepeatMode> {
+    match status {
+        "None" => Some(RepeatMode::Off),
+        "Playlist" => Some(RepeatMode::Queue),
+        "Track" => Some(RepeatMode::Track),
+        _ => None,
+    }
+}
+
+/// Handle to the service thread. Dropping it shuts the service down.
+pub struct Mpris {
+    updates: mpsc::Sender<Update>,
+}
+
+impl Mpris {
+    /// Claim `org.mpris.MediaPlayer2.{bus_name}` and serve it on a thread of
+    /// its own; requests from clients are passed to `handler`.
+    pub fn start(
+        bus_name: &str,
+        identity: &str,
+        handler: impl Fn(ControlEvent) + Send + Sync + 'static,
+    ) -> Result<Self, AppError> {
+        let conn = Connection::new_session().map_err(|e| AppError::Audio(e.to_string()))?;
+        conn.request_name(format!("org.mpris.MediaPlayer2.{bus_name}"), false, true, false)
+            .map_err(|e| AppError::Audio(e.to_string()))?;
+        let (updates, rx) = mpsc::channel();
+        let identity = identity.to_string();
+        let handler: Handler = Arc::new(handler);
+        std::thread::Builder::new()
+            .name("sunder-mpris".into())
+            .spawn(move || {
+                if let Err(e) = serve(conn, identity, handler, rx) {
+                    eprintln!("[sunder] MPRIS service stopped: {e}");
+                }
+            })
+            .map_err(|e| AppError::Audio(e.to_string()))?;
+        Ok(Self { updates })
+    }
+
+    pub fn set_metadata(&self, metadata: Metadata) {
+        let _ = self.updates.send(Update::Metadata(metadata));
+    }
+
+    pub fn set_playback(&self, playback: MediaPlayback) {
+        let _ = self.updates.send(Update::Playback(playback));
+    }
+
+    pub fn set_volume(&self, volume: f64) {
+        let _ = self.updates.send(Update::Volume(volume));
+    }
+
+    pub fn set_repeat(&self, mode: RepeatMode) {
+        let _ = self.updates.send(Update::Repeat(mode));
+    }
+
+    pub fn set_rate(&self, rate: f64) {
+        let _ = self.updates.send(Update::Rate(rate));
+    }
+
+    pub fn set_queue(&self, queue: QueueSnapshot) {
+        let _ = self.updates.send(Update::Queue(queue));
+    }
+
+    pub fn seeked(&self, position: Duration) {
+        let _ = self.updates.send(Update::Seeked(position));
+    }
+}
+
+fn register_root(cr: &mut Crossroads, identity: String) -> dbus_crossroads::IfaceToken<State> {
+    cr.register("org.mpris.MediaPlayer2", |b: &mut IfaceBuilder<State>| {
+        b.property("Identity").get(move |_, _| Ok(identity.clone()));
+        b.property("CanQuit").get(|_, _| Ok(false));
+        b.property("CanRaise").get(|_, _| Ok(false));
+        b.property("HasTrackList").get(|_, _| Ok(true));
+        b.property("SupportedUriSchemes").get(|_, _| Ok(Vec::<String>::new()));
+        b.property("SupportedMimeTypes").get(|_, _| Ok(Vec::<String>::new()));
+        b.method("Raise", (), (), |_, _, _: ()| Ok(()));
+        b.method("Quit", (), (), |_, _, _: ()| Ok(()));
+    })
+}
+
+fn register_player(cr: &mut Crossroads, handler: &Handler) -> dbus_crossroads::IfaceToken<State> {
+    cr.register(PLAYER_IFACE, |b: &mut IfaceBuilder<State>| {
+        for (name, event) in [
+            ("Next", MediaControlEvent::Next),
+            ("Previous", MediaControlEvent::Previous),
+            ("Pause", MediaControlEvent::Pause),
+            ("PlayPause", MediaControlEvent::Toggle),
+            ("Stop", MediaControlEvent::Stop),
+            ("Play", MediaControlEvent::Play),
+        ] {
+            let h = handler.clone();
+            b.method(name, (), (), move |_, _, _: ()| {
+                h(ControlEvent::Media(event.clone()));
+                Ok(())
+            });
+        }
+        let h = handler.clone();
+        b.method("Seek", ("Offset",), (), move |_, _, (offset,): (i64,)| {
+            let direction = if offset < 0 {
+                SeekDirection::Backward
+            } else {
+                SeekDirection::Forward
+            };
+            h(ControlEvent::Media(MediaControlEvent::SeekBy(
+                direction,
+                Duration::from_micros(offset.unsigned_abs()),
+            )));
+            Ok(())
+        });
+        let h = handler.clone();
+        b.method(
+            "SetPosition",
+            ("TrackId", "Position"),
+            (),
+            move |_, state: &mut State, (track, position): (Path, i64)| {
+                // Stale requests, for a track that is no longer current, are ignored.
+                if track != track_path(&state.metadata.track_id) {
+                    return Ok(());
+                }
+                if let Ok(us) = u64::try_from(position) {
+                    let position = Duration::from_micros(us);
+                    if position <= state.metadata.length {
+                        h(ControlEvent::Media(MediaControlEvent::SetPosition(MediaPosition(position))));
+                    }
+                }
+                Ok(())
+            },
+        );
+        b.method("OpenUri", ("Uri",), (), |_, _, _: (String,)| Ok(()));
+
+        b.property("PlaybackStatus").get(|_, state: &mut State| Ok(state.status().to_string()));
+        b.property("LoopStatus")
+            .get(|_, state: &mut State| Ok(loop_status(state.repeat).to_string()))
+            .set({
+                let h = handler.clone();
+                move |_, state: &mut State, status: String| {
+                    let mode = parse_loop_status(&status).ok_or_else(|| MethodErr::invalid_arg(&status))?;
+                    state.repeat = mode;
+                    h(ControlEvent::SetRepeat(mode));
+                    Ok(Some(status))
+                }
+            });
+        // Changes to these are announced once the engine has applied them.
+        b.property("Shuffle")
+            .get(|_, state: &mut State| Ok(state.shuffle))
+            .set({
+                let h = handler.clone();
+                move |_, _, shuffle: bool| {
+                    h(ControlEvent::SetShuffle(shuffle));
+                    Ok(None)
+                }
+            });
+        b.property("Rate")
+            .get(|_, state: &mut State| Ok(state.rate))
+            .set({
+                let h = handler.clone();
+                move |_, _, rate: f64| {
+                    // The spec reserves 0.0 for clients that mean "pause".
+                    if rate <= 0.0 {
+                        h(ControlEvent::Media(MediaControlEvent::Pause));
+                    } else {
+                        h(ControlEvent::SetRate(rate));
+                    }
+                    Ok(None)
+                }
+            });
+        b.property("MinimumRate").get(|_, _| Ok(MIN_SPEED as f64)).emits_changed_const();
+        b.property("MaximumRate").get(|_, _| Ok(MAX_SPEED as f64)).emits_changed_const();
+        b.property("Metadata").get(|_, state: &mut State| Ok(metadata_dict(&state.metadata)));
+        b.property("Volume")
+            .get(|_, state: &mut State| Ok(state.volume))
+            .set({
+                let h = handler.clone();
+                move |_, _, volume: f64| {
+                    h(ControlEvent::Media(MediaControlEvent::SetVolume(volume)));
+                    Ok(Some(volume))
+                }
+            });
+        b.property("Position")
+            .get(|_, state: &mut State| Ok(state.position().as_micros() as i64))
+            .emits_changed_false();
+        for name in ["CanGoNext", "CanGoPrevious", "CanPlay", "CanPause", "CanSeek", "CanControl"] {
+            b.property(name).get(|_, _| Ok(true)).emits_changed_const();
+        }
+        b.signal::<(i64,), _>("Seeked", ("Position",));
+    })
+}
+
+fn register_tracklist(cr: &mut Crossroads, handler: &Handler) -> dbus_crossroads::IfaceToken<State> {
+    cr.register(TRACKLIST_IFACE, |b: &mut IfaceBuilder<State>| {
+        b.property("Tracks")
+            .get(|_, state: &mut State| Ok(state.track_ids()))
+            .emits_changed_invalidates();
+        // The queue is edited in the app; clients can only jump around in it.
+        b.property("CanEditTracks").get(|_, _| Ok(false)).emits_changed_const();
+        b.method(
+            "GetTracksMetadata",
+            ("TrackIds",),
+            ("Metadata",),
+            |_, state: &mut State, (ids,): (Vec<Path>,)| {
+                let found = ids
+                    .iter()
+                    .filter_map(|id| state.tracks.iter().find(|t| track_path(&t.track_id) == *id))
+                    .map(metadata_dict)
+                    .collect::<Vec<_>>();
+                Ok((found,))
+            },
+        );
+        b.method(
+            "AddTrack",
+            ("Uri", "AfterTrack", "SetAsCurrent"),
+            (),
+            |_, _, _: (String, Path, bool)| Ok(()),
+        );
+        b.method("RemoveTrack", ("TrackId",), (), |_, _, _: (Path,)| Ok(()));
+        let h = handler.clone();
+        b.method("GoTo", ("TrackId",), (), move |_, state: &mut State, (id,): (Path,)| {
+            if let Some(i) = state.tracks.iter().position(|t| track_path(&t.track_id) == id) {
+                h(ControlEvent::GoTo(i));
+            }
+            Ok(())
+        });
+        b.signal::<(Vec<Path<'static>>, Path<'static>), _>("TrackListReplaced", ("Tracks", "CurrentTrack"));
+    })
+}
+
+fn properties_changed(iface: &str, changed: Option<(&str, Box<dyn RefArg>)>, invalidated: &[&str]) -> Message {
+    PropertiesPropertiesChanged {
+        interface_name: iface.to_string(),
+        changed_properties: changed
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), Variant(value)))
+            .collect::<HashMap<_, _>>(),
+        invalidated_properties: invalidated.iter().map(|s| s.to_string()).collect(),
+    }
+    .to_emit_message(&Path::from(OBJECT_PATH))
+}
+
+/// Apply an update from the engine and return the signals announcing it.
+fn apply(state: &mut State, update: Update) -> Vec<Message> {
+    let path = Path::from(OBJECT_PATH);
+    let changed = |name: &str, value: Box<dyn RefArg>| properties_changed(PLAYER_IFACE, Some((name, value)), &[]);
+    match update {
+        Update::Metadata(m) => {
+            state.metadata = m;
+            vec![changed("Metadata", Box::new(metadata_dict(&state.metadata)))]
+        }
+        Update::Playback(p) => {
+            let (playing, position) = match p {
+                MediaPlayback::Playing { progress } => (Some(true), progress),
+                MediaPlayback::Paused { progress } => (Some(false), progress),
+                MediaPlayback::Stopped => (None, None),
+            };
+            state.position = position.map_or(Duration::ZERO, |p| p.0);
+            state.reported = Instant::now();
+            if state.playing == playing {
+                return Vec::new();
+            }
+            state.playing = playing;
+            vec![changed("PlaybackStatus", Box::new(state.status().to_string()))]
+        }
+        Update::Volume(v) => {
+            state.volume = v;
+            vec![changed("Volume", Box::new(v))]
+        }
+        Update::Repeat(mode) if mode != state.repeat => {
+            state.repeat = mode;
+            vec![changed("LoopStatus", Box::new(loop_status(mode).to_string()))]
+        }
+        Update::Rate(rate) if rate != state.rate => {
+            state.position = state.position();
+            state.reported = Instant::now();
+            state.rate = rate;
+            vec![changed("Rate", Box::new(rate))]
+        }
+        Update::Queue(q) => {
+            let mut out = Vec::new();
+            if q.shuffled != state.shuffle {
+                state.shuffle = q.shuffled;
+                out.push(changed("Shuffle", Box::new(q.shuffled)));
+            }
+            let tracks: Vec<Metadata> = q.tracks.iter().map(Metadata::from).collect();
+            if tracks.iter().map(|t| &t.track_id).ne(state.tracks.iter().map(|t| &t.track_id)) {
+                state.tracks = tracks;
+                let current = usize::try_from(q.index)
+                    .ok()
+                    .and_then(|i| state.tracks.get(i))
+                    .map_or_else(|| Path::from(NO_TRACK), |t| track_path(&t.track_id));
+                out.push(
+                    Message::signal(&path, &TRACKLIST_IFACE.into(), &"TrackListReplaced".into())
+                        .append2(state.track_ids(), current),
+                );
+                out.push(properties_changed(TRACKLIST_IFACE, None, &["Tracks"]));
+            }
+            out
+        }
+        Update::Seeked(position) => {
+            state.position = position;
+            state.reported = Instant::now();
+            vec![Message::signal(&path, &PLAYER_IFACE.into(), &"Seeked".into())
+                .append1(position.as_micros() as i64)]
+        }
+        Update::Repeat(_) | Update::Rate(_) => Vec::new(),
+    }
+}
+
+fn serve(
+    conn: Connection,
+    identity: String,
+    handler: Handler,
+    updates: mpsc::Receiver<Update>,
+) -> Result<(), dbus::Error> {
+    let mut cr = Crossroads::new();
+    let root = register_root(&mut cr, identity);
+    let player = register_player(&mut cr, &handler);
+    let tracklist = register_tracklist(&mut cr, &handler);
+    let path = Path::from(OBJECT_PATH);
+    cr.insert(
+        path.clone(),
+        &[root, player, tracklist],
+        State {
+            metadata: Metadata::default(),
+            playing: None,
+            position: Duration::ZERO,
+            reported: Instant::now(),
+            rate: 1.0,
+            volume: 1.0,
+            repeat: RepeatMode::Off,
+            shuffle: false,
+            tracks: Vec::new(),
+        },
+    );
+
+    loop {
+        loop {
+            let update = match updates.try_recv() {
+                Ok(u) => u,
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => return Ok(()),
+            };
+            let Some(state) = cr.data_mut::<State>(&path) else {
+                break;
+            };
+            for msg in apply(state, update) {
+                let _ = conn.send(msg);
+            }
+        }
+
+        if conn.channel().read_write(Some(Duration::from_millis(50))).is_err() {
+            return Err(dbus::Error::new_failed("session bus connection lost"));
+        }
+        while let Some(msg) = conn.channel().pop_message() {
+            if msg.msg_type() == MessageType::MethodCall {
+                let _ = cr.handle_message(msg, &conn);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_paths_escape_what_paths_disallow() {
+        assert_eq!(&*track_path("dQw4w9WgXcQ"), "/org/mpris/MediaPlayer2/Track/dQw4w9WgXcQ");
+        assert_eq!(&*track_path("a-b_c#1"), "/org/mpris/MediaPlayer2/Track/a_2db_5fc_231");
+        assert_eq!(&*track_path(""), NO_TRACK);
+        assert_ne!(track_path("a-b"), track_path("a_b"));
+    }
+
+    #[test]
+    fn position_runs_on_at_the_rate_while_playing() {
+        let mut state = State {
+            metadata: Metadata::default(),
+            playing: Some(false),
+            position: Duration::from_secs(10),
+            reported: Instant::now() - Duration::from_secs(2),
+            rate: 1.5,
+            volume: 1.0,
+            repeat: RepeatMode::Off,
+            shuffle: false,
+            tracks: Vec::new(),
+        };
+        assert_eq!(state.position(), Duration::from_secs(10));
+        state.playing = Some(true);
+        let position = state.position().as_secs_f64();
+        assert!((13.0..13.1).contains(&position), "{position}");
+    }
+
+    #[test]
+    fn loop_status_round_trips() {
+        for mode in [RepeatMode::Off, RepeatMode::Queue, RepeatMode::Track] {
+            assert_eq!(parse_loop_status(loop_status(mode)), Some(mode));
+        }
+        assert_eq!(parse_loop_status("track"), None);
+    }
+}
@@ -0,0 +1,450 @@
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use rodio::cpal::traits::HostTrait;
+use rodio::dynamic_mixer::{self, DynamicMixer, DynamicMixerController};
+use rodio::{DeviceTrait, OutputStream, OutputStreamHandle, Sink, Source};
+
+use crate::error::AppError;
+
+/// A stream whose heartbeat hasn't advanced for this long is considered dead.
+const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+const HEARTBEAT_PERIOD: Duration = Duration::from_millis(200);
+
+/// Format of everything not played on a device: 48 kHz 16-bit stereo, the
+/// sample format Snapcast's pipe source expects by default.
+const RENDER_RATE: u32 = 48_000;
+const RENDER_CHANNELS: u16 = 2;
+/// Frames mixed at a time when rendering.
+const RENDER_CHUNK: usize = 960;
+/// Chunks a FIFO may fall behind by before they are dropped.
+const FIFO_BACKLOG: usize = 10;
+
+/// Where the mixed audio goes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Backend {
+    /// The sound card picked in the device menu.
+    Device,
+    /// Nowhere, at the pace of real time: for running headless.
+    Null,
+    /// A WAV file of everything that plays.
+    Wav(PathBuf),
+    /// Raw PCM into a named pipe, as read by Snapcast's pipe source.
+    Fifo(PathBuf),
+}
+
+impl Backend {
+    /// Parse "device", "null", "wav:<path>" or "fifo:<path>".
+    pub fn parse(spec: &str) -> Result<Self, AppError> {
+        let spec = spec.trim();
+        let (kind, path) = spec.split_once(':').unwrap_or((spec, ""));
+        let path = || match path.trim() {
+            "" => Err(AppError::Audio(format!("output backend {kind:?} needs a path"))),
+            p => Ok(PathBuf::from(p)),
+        };
+        match kind {
+            "" | "device" => Ok(Self::Device),
+            "null" => Ok(Self::Null),
+            "wav" => Ok(Self::Wav(path()?)),
+            "fifo" => Ok(Self::Fifo(path()?)),
+            _ => Err(AppError::Audio(format!("unknown output backend {spec:?}"))),
+        }
+    }
+
+    /// The backend named by `SUNDER_OUTPUT`, or else by the config; the
+    /// device when neither makes sense.
+    pub fn selected(configured: &str) -> Self {
+        let spec = std::env::var("SUNDER_OUTPUT").unwrap_or_else(|_| configured.to_string());
+        Self::parse(&spec).unwrap_or_else(|e| {
+            eprintln!("[sunder] {e}, using the audio device");
+            Self::Device
+        })
+    }
+}
+
+/// An audio output as shown in the device picker.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct OutputDevice {
+    pub name: String,
+    pub is_default: bool,
+}
+
+/// Output devices of the default host, default device first.
+pub fn list_devices() -> Vec<OutputDevice> {
+    let host = rodio::cpal::default_host();
+    let default = host.default_output_device().and_then(|d| d.name().ok());
+    let mut devices: Vec<OutputDevice> = host
+        .output_devices()
+        .into_iter()
+        .flatten()
+        .filter_map(|d| d.name().ok())
+        .map(|name| OutputDevice {
+            is_default: default.as_ref() == Some(&name),
+            name,
+        })
+        .collect();
+    devices.sort_by_key(|d| !d.is_default);
+    devices
+}
+
+/// An open output stream. rodio swallows stream errors, so a silent source
+/// plays alongside the music and records when the device last pulled
+/// samples; a stream that stops pulling has been lost.
+pub struct Output {
+    target: Target,
+    /// Device the stream was opened on; empty for the system default.
+    device: String,
+    opened: Instant,
+    heartbeat: Arc<AtomicU64>,
+}
+
+enum Target {
+    Device {
+        _stream: OutputStream,
+        handle: OutputStreamHandle,
+    },
+    /// Mixed on a thread of our own and written wherever the backend says.
+    Rendered {
+        mixer: Arc<DynamicMixerController<f32>>,
+        _render: Render,
+    },
+}
+
+impl Output {
+    /// Open `backend`; for the device backend a stream on the device called
+    /// `preferred`, or on the default device when `preferred` is empty or
+    /// can't be opened.
+    pub fn open(backend: &Backend, preferred: &str) -> Result<Self, AppError> {
+        let (target, device) = match backend {
+            Backend::Device => {
+                let (stream, handle, device) = open_stream(preferred)?;
+                (Target::Device { _stream: stream, handle }, device)
+            }
+            _ => {
+                let (mixer, mixed) = dynamic_mixer::mixer(RENDER_CHANNELS, RENDER_RATE);
+                let render = Render::start(mixed, Writer::open(backend)?);
+                (Target::Rendered { mixer, _render: render }, preferred.to_string())
+            }
+        };
+        let opened = Instant::now();
+        let heartbeat = Arc::new(AtomicU64::new(0));
+        let beat = heartbeat.clone();
+        let output = Self {
+            target,
+            device,
+            opened,
+            heartbeat,
+        };
+        output.play(rodio::source::Zero::<f32>::new(2, 48_000).periodic_access(
+            HEARTBEAT_PERIOD,
+            move |_| beat.store(opened.elapsed().as_millis() as u64, Ordering::Relaxed),
+        ))?;
+        Ok(output)
+    }
+
+    fn play(&self, source: impl Source<Item = f32> + Send + 'static) -> Result<(), AppError> {
+        match &self.target {
+            Target::Device { handle, .. } => handle.play_raw(source).map_err(|e| AppError::Audio(e.to_string())),
+            Target::Rendered { mixer, .. } => {
+                mixer.add(source);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn sink(&self) -> Result<Sink, AppError> {
+        let (sink, queue) = Sink::new_idle();
+        self.play(queue)?;
+        Ok(sink)
+    }
+
+    /// Whether this stream is on the `preferred` device rather than a fallback.
+    /// Rendered output has no device to fall back from.
+    pub fn is_on(&self, preferred: &str) -> bool {
+        matches!(self.target, Target::Rendered { .. }) || self.device == preferred
+    }
+
+    /// True once the device has stopped consuming audio (unplugged, or the
+    /// sound server went away).
+    pub fn stalled(&self) -> bool {
+        let last = Duration::from_millis(self.heartbeat.load(Ordering::Relaxed));
+        self.opened.elapsed().saturating_sub(last) > STALL_TIMEOUT
+    }
+}
+
+fn open_stream(preferred: &str) -> Result<(OutputStream, OutputStreamHandle, String), AppError> {
+    if !preferred.is_empty() {
+        let device = rodio::cpal::default_host()
+            .output_devices()
+            .into_iter()
+            .flatten()
+            .find(|d| d.name().is_ok_and(|n| n == preferred));
+        match device.map(|d| OutputStream::try_from_device(&d)) {
+            Some(Ok((stream, handle))) => return Ok((stream, handle, preferred.to_string())),
+            Some(Err(e)) => eprintln!("[sunder] output device {preferred:?} failed: {e}"),
+            None => eprintln!("[sunder] output device {preferred:?} not found, using default"),
+        }
+    }
+    let (stream, handle) =
+        OutputStream::try_default().map_err(|e| AppError::Audio(format!("no audio output device: {e}")))?;
+    Ok((stream, handle, String::new()))
+}
+
+/// Pulls the mix in real time and hands it to a [`Writer`] until dropped.
+struct Render {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Render {
+    fn start(mut mixed: DynamicMixer<f32>, mut writer: Writer) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let thread = std::thread::spawn(move || {
+            let started = Instant::now();
+            let mut frames: u64 = 0;
+            let mut chunk = Vec::with_capacity(RENDER_CHUNK * RENDER_CHANNELS as usize);
+            while !stopped.load(Ordering::Relaxed) {
+                let due = Duration::from_secs_f64(frames as f64 / RENDER_RATE as f64);
+                if let Some(wait) = due.checked_sub(started.elapsed()) {
+                    std::thread::sleep(wait);
+                }
+                chunk.clear();
+                chunk.extend(
+                    mixed
+                        .by_ref()
+                        .take(RENDER_CHUNK * RENDER_CHANNELS as usize)
+                        .map(|s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16),
+                );
+                writer.write(&chunk);
+                frames += RENDER_CHUNK as u64;
+            }
+            writer.finish();
+        });
+        Self {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Render {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Destination of rendered 16-bit samples. Write errors are logged and the
+/// audio dropped, so the output keeps its pace and is never taken for lost.
+enum Writer {
+    Null,
+    Wav(Option<WavFile>),
+    Fifo(SyncSender<Vec<i16>>),
+}
+
+impl Writer {
+    fn open(backend: &Backend) -> Result<Self, AppError> {
+        match backend {
+            Backend::Device | Backend::Null => Ok(Self::Null),
+            Backend::Wav(path) => Ok(Self::Wav(Some(WavFile::create(path)?))),
+            Backend::Fifo(path) => {
+                let (tx, rx) = sync_channel(FIFO_BACKLOG);
+                let path = path.clone();
+                std::thread::spawn(move || feed_fifo(&path, rx));
+                Ok(Self::Fifo(tx))
+            }
+        }
+    }
+
+    fn write(&mut self, samples: &[i16]) {
+        match self {
+            Self::Null => {}
+            Self::Wav(file) => {
+                if let Some(Err(e)) = file.as_mut().map(|f| f.write(samples)) {
+                    eprintln!("[sunder] WAV output failed: {e}");
+                    *file = None;
+                }
+            }
+            // Full while nobody reads the pipe: the chunk is dropped.
+            Self::Fifo(tx) => {
+                let _ = tx.try_send(samples.to_vec());
+            }
+        }
+    }
+
+    fn finish(self) {
+        if let Self::Wav(Some(file)) = self {
+            if let Err(e) = file.finish() {
+                eprintln!("[sunder] WAV output failed: {e}");
+            }
+        }
+    }
+}
+
+/// A 16-bit PCM WAV file whose header is brought up to date every so often,
+/// so what was written stays playable if the app never gets to finish it.
+struct WavFile {
+    out: BufWriter<File>,
+    data_bytes: u32,
+    since_header: u32,
+}
+
+impl WavFile {
+    const HEADER_EVERY: u32 = RENDER_RATE * RENDER_CHANNELS as u32 * 2;
+
+    fn create(path: &std::path::Path) -> Result<Self, AppError> {
+        let mut wav = Self {
+            out: BufWriter::new(File::create(path)?),
+            data_bytes: 0,
+            since_header: 0,
+        };
+        wav.write_header()?;
+        Ok(wav)
+    }
+
+    fn write_header(&mut self) -> std::io::Result<()> {
+        let channels = RENDER_CHANNELS as u32;
+        let mut header = Vec::with_capacity(44);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&self.data_bytes.saturating_add(36).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&RENDER_CHANNELS.to_le_bytes());
+        header.extend_from_slice(&RENDER_RATE.to_le_bytes());
+        header.extend_from_slice(&(RENDER_RATE * channels * 2).to_le_bytes());
+        header.extend_from_slice(&(RENDER_CHANNELS * 2).to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&self.data_bytes.to_le_bytes());
+
+        let end = self.out.stream_position()?;
+        self.out.seek(SeekFrom::Start(0))?;
+        self.out.write_all(&header)?;
+        if end > 0 {
+            self.out.seek(SeekFrom::Start(end))?;
+        }
+        self.out.flush()
+    }
+
+    fn write(&mut self, samples: &[i16]) -> std::io::Result<()> {
+        let bytes: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        // The sizes in the header can't describe more than 4 GiB.
+        if self.data_bytes.checked_add(bytes.len() as u32 + 36).is_none() {
+            return Ok(());
+        }
+        self.out.write_all(&bytes)?;
+        self.data_bytes += bytes.len() as u32;
+        self.since_header += bytes.len() as u32;
+        if self.since_header >= Self::HEADER_EVERY {
+            self.since_header = 0;
+            self.write_header()?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> std::io::Result<()> {
+        self.write_header()
+    }
+}
+
+/// Write chunks into the named pipe at `path` while anyone reads it. Opening
+/// blocks until a reader turns up, and a reader that goes away is waited
+/// for again; meanwhile the render thread drops what doesn't fit the backlog.
+fn feed_fifo(path: &std::path::Path, rx: Receiver<Vec<i16>>) {
+    loop {
+        let mut pipe = match std::fs::OpenOptions::new().write(true).open(path) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("[sunder] FIFO output {}: {e}", path.display());
+                // Keep the backlog from going stale while the pipe is missing.
+                while rx.try_recv().is_ok() {}
+                std::thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+        };
+        // Whatever piled up before the reader came is too old to play.
+        while rx.try_recv().is_ok() {}
+        loop {
+            let Ok(chunk) = rx.recv() else {
+                return;
+            };
+            let bytes: Vec<u8> = chunk.iter().flat_map(|s| s.to_le_bytes()).collect();
+            if let Err(e) = pipe.write_all(&bytes) {
+                eprintln!("[sunder] FIFO reader went away: {e}");
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_backends() {
+        assert_eq!(Backend::parse("").unwrap(), Backend::Device);
+        assert_eq!(Backend::parse("null").unwrap(), Backend::Null);
+        assert_eq!(
+            Backend::parse("fifo:/tmp/snapfifo").unwrap(),
+            Backend::Fifo(PathBuf::from("/tmp/snapfifo"))
+        );
+        assert!(Backend::parse("wav:").is_err());
+        assert!(Backend::parse("pulse").is_err());
+    }
+
+    #[test]
+    fn wav_backend_records_what_plays() {
+        let path = std::env::temp_dir().join(format!("sunder_wav_{}.wav", std::process::id()));
+        let (mixer, mixed) = dynamic_mixer::mixer(RENDER_CHANNELS, RENDER_RATE);
+        let render = Render::start(mixed, Writer::open(&Backend::Wav(path.clone())).unwrap());
+        mixer.add(rodio::source::SineWave::new(440.0).take_duration(Duration::from_millis(100)));
+        std::thread::sleep(Duration::from_millis(300));
+        drop(render);
+
+        let wav = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(&wav[..4], b"RIFF");
+        let data_bytes = u32::from_le_bytes(wav[40..44].try_into().unwrap()) as usize;
+        assert_eq!(wav.len(), 44 + data_bytes);
+        assert!(data_bytes >= RENDER_RATE as usize / 10 * 4);
+        let samples: Vec<i16> = wav[44..].chunks(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        assert!(samples.iter().any(|&s| s.abs() > 1_000));
+    }
+
+    #[test]
+    fn sinks_play_through_a_rendered_output() {
+        let output = Output::open(&Backend::Null, "USB DAC").unwrap();
+        assert!(output.is_on("USB DAC"));
+        assert!(output.is_on(""));
+        let sink = output.sink().unwrap();
+        sink.append(rodio::source::SineWave::new(440.0).take_duration(Duration::from_millis(100)));
+        assert_eq!(sink.len(), 1);
+        std::thread::sleep(Duration::from_millis(300));
+        assert!(sink.empty());
+    }
+
+    #[test]
+    fn an_output_that_stops_pulling_is_stalled() {
+        let mut output = Output::open(&Backend::Null, "").unwrap();
+        std::thread::sleep(HEARTBEAT_PERIOD * 2);
+        assert!(!output.stalled());
+
+        // A mix without the heartbeat in it, as when the device stops asking.
+        let (mixer, mixed) = dynamic_mixer::mixer(RENDER_CHANNELS, RENDER_RATE);
+        let render = Render::start(mixed, Writer::open(&Backend::Null).unwrap());
+        output.target = Target::Rendered { mixer, _render: render };
+        std::thread::sleep(STALL_TIMEOUT + HEARTBEAT_PERIOD * 2);
+        assert!(output.stalled());
+    }
+}
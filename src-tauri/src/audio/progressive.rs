@@ -0,0 +1,536 @@
+use std::fs::File;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::Source;
+use symphonia::core::io::MediaSource;
+use tauri::Emitter;
+
+use super::engine::TrackSource;
+use crate::error::AppError;
+
+const POLL: Duration = Duration::from_millis(20);
+/// Bitrate assumed for a download of unknown duration: on the high side for
+/// YouTube's Opus streams, so buffered time is underestimated.
+const FALLBACK_BYTES_PER_SEC: u64 = 24_000;
+/// How far the download has to be ahead of the decoder for it to read on
+/// without reaching the end of what has been written. The demuxer reads at
+/// most 32 KiB at a time, and a read that comes up short is topped up.
+const READ_MARGIN: u64 = 16 * 1024;
+
+/// A track being streamed by yt-dlp into a growing WebM file in the temp
+/// cache. WebM/Opus is used because YouTube muxes its index ahead of the
+/// audio, so the file can be opened and seeked before it is complete. The
+/// file is written as `<id>.webm.part` and renamed once complete, so an
+/// interrupted download never looks like a cached track.
+pub struct Progressive {
+    /// Download progress in hundredths of a percent, as reported by yt-dlp.
+    progress: AtomicU64,
+    duration_ms: u64,
+    /// Bytes of the file written so far, and read by the decoder so far.
+    written: AtomicU64,
+    read_to: AtomicU64,
+    /// Set while playback has caught up with the download and plays silence.
+    starved: AtomicBool,
+    done: AtomicBool,
+    failed: AtomicBool,
+    cancel: AtomicBool,
+    child: Mutex<Option<Child>>,
+    partial: PathBuf,
+}
+
+impl Progressive {
+    /// Spawn yt-dlp for `video_id`. Progress is reported as
+    /// `download-progress` events; once finished the file is renamed to
+    /// `<cache_dir>/<id>.webm` and measured for loudness normalization.
+    /// `duration_ms` converts download progress into buffered time.
+    pub fn start(
+        app: &tauri::AppHandle,
+        video_id: &str,
+        cache_dir: &Path,
+        duration_ms: u64,
+    ) -> Result<Arc<Self>, AppError> {
+        let final_path = cache_dir.join(format!("{video_id}.webm"));
+        let partial = cache_dir.join(format!("{video_id}.webm.part"));
+        let mut file = File::create(&partial)?;
+
+        let url = format!("https://www.youtube.com/watch?v={video_id}");
+        let mut child = Command::new(super::engine::ytdlp_bin())
+            .args([
+                url.as_str(),
+                "-f",
+                "bestaudio[ext=webm][acodec=opus]",
+                "-o",
+                "-",
+                "--no-playlist",
+                "--newline",
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| AppError::Extraction(format!("failed to spawn yt-dlp: {e}")))?;
+        let mut stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+
+        let dl = Arc::new(Self {
+            progress: AtomicU64::new(0),
+            duration_ms,
+            written: AtomicU64::new(0),
+            read_to: AtomicU64::new(0),
+            starved: AtomicBool::new(false),
+            done: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+            cancel: AtomicBool::new(false),
+            child: Mutex::new(Some(child)),
+            partial,
+        });
+
+        if let Some(stderr) = stderr {
+            let dl = dl.clone();
+            let app = app.clone();
+            std::thread::spawn(move || {
+                for line in io::BufReader::new(stderr).lines().map_while(Result::ok) {
+                    if let Some(pct) = super::engine::parse_download_pct(&line) {
+                        dl.progress.store((pct * 100.0) as u64, Ordering::Release);
+                        let _ = app.emit(
+                            "download-progress",
+                            serde_json::json!({ "percent": pct, "stage": "downloading" }),
+                        );
+                    } else if line.starts_with("ERROR") {
+                        eprintln!("[sunder] yt-dlp: {line}");
+                    }
+                }
+            });
+        }
+
+        let dl_clone = dl.clone();
+        let app = app.clone();
+        let video_id = video_id.to_string();
+        std::thread::spawn(move || {
+            let copied = match stdout.as_mut() {
+                Some(out) => dl_clone.copy(out, &mut file),
+                None => Ok(0),
+            };
+            dl_clone.finish(copied, &app, &video_id, &final_path);
+        });
+
+        Ok(dl)
+    }
+
+    /// Copy yt-dlp's output into the file, counting what has been written.
+    fn copy(&self, from: &mut impl Read, to: &mut File) -> io::Result<u64> {
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let n = match from.read(&mut buf) {
+                Ok(0) => return Ok(self.written.load(Ordering::Acquire)),
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            to.write_all(&buf[..n])?;
+            self.written.fetch_add(n as u64, Ordering::Release);
+        }
+    }
+
+    fn finish(
+        &self,
+        copied: io::Result<u64>,
+        app: &tauri::AppHandle,
+        video_id: &str,
+        final_path: &Path,
+    ) {
+        let status = self.child.lock().unwrap().take().map(|mut c| c.wait());
+        let ok = matches!(status, Some(Ok(s)) if s.success())
+            && matches!(copied, Ok(n) if n > 0)
+            && !self.cancel.load(Ordering::Relaxed);
+
+        // Renaming keeps the reader's open handle valid on every platform
+        // (std opens files with delete sharing on Windows).
+        if ok && std::fs::rename(&self.partial, final_path).is_ok() {
+            eprintln!("[sunder] progressive download complete: {}", final_path.display());
+            self.progress.store(10_000, Ordering::Release);
+            self.done.store(true, Ordering::Release);
+            super::loudness::analyze_and_store(app, video_id, final_path);
+            return;
+        }
+
+        if !self.cancel.load(Ordering::Relaxed) {
+            eprintln!("[sunder] progressive download of {video_id} failed");
+        }
+        let _ = std::fs::remove_file(&self.partial);
+        self.failed.store(true, Ordering::Release);
+        self.done.store(true, Ordering::Release);
+    }
+
+    /// Milliseconds of audio available on disk, estimated from download
+    /// progress, or from the bytes written when the track duration isn't
+    /// known.
+    pub fn buffered_ms(&self) -> u64 {
+        if self.is_done() {
+            return u64::MAX;
+        }
+        if self.duration_ms == 0 {
+            return self.written.load(Ordering::Acquire) * 1000 / FALLBACK_BYTES_PER_SEC;
+        }
+        self.duration_ms * self.progress.load(Ordering::Acquire) / 10_000
+    }
+
+    /// True once yt-dlp has exited, successfully or not.
+    pub fn is_done(&self) -> bool {
+        self.done.load(Ordering::Acquire)
+    }
+
+    pub fn failed(&self) -> bool {
+        self.failed.load(Ordering::Acquire)
+    }
+
+    /// True while playback has run into the end of the download; it plays
+    /// silence until more arrives. Cleared as soon as it has, even before a
+    /// paused track is pulled again.
+    pub fn starved(&self) -> bool {
+        self.starved.load(Ordering::Acquire) && !self.readable()
+    }
+
+    /// Whether the decoder can read on without reaching the end of what has
+    /// been written.
+    fn readable(&self) -> bool {
+        self.is_done() || self.written.load(Ordering::Acquire) >= self.read_to.load(Ordering::Acquire) + READ_MARGIN
+    }
+
+    /// Block until `ms` of audio is buffered or the download ends. Returns
+    /// false when it failed, or `keep_waiting` says the result is no longer
+    /// wanted.
+    pub fn wait_for(&self, ms: u64, keep_waiting: impl Fn() -> bool) -> bool {
+        while !self.is_done() && self.buffered_ms() < ms {
+            if !keep_waiting() {
+                return false;
+            }
+            std::thread::sleep(POLL);
+        }
+        !self.failed()
+    }
+
+    /// Stop the download if it is still running; the partial file is removed.
+    pub fn abandon(&self) {
+        if !self.is_done() {
+            self.cancel.store(true, Ordering::Relaxed);
+            if let Some(child) = self.child.lock().unwrap().as_mut() {
+                let _ = child.kill();
+            }
+        }
+    }
+
+    /// Hand the download to `open` to set up decoding. Until it returns the
+    /// file can't seek, so the demuxer reads the headers in order instead
+    /// of asking for a length that isn't known yet.
+    pub fn open_with<T>(
+        self: &Arc<Self>,
+        open: impl FnOnce(GrowingFile) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let opening = Arc::new(AtomicBool::new(!self.is_done()));
+        let file = GrowingFile {
+            file: File::open(&self.partial)?,
+            download: self.clone(),
+            opening: opening.clone(),
+        };
+        let opened = open(file);
+        opening.store(false, Ordering::Release);
+        opened
+    }
+
+    /// Wrap the track decoded from this download so that it plays silence
+    /// rather than have the decoder wait for data inside the audio callback.
+    pub fn gate(self: &Arc<Self>, source: TrackSource) -> TrackSource {
+        Box::new(Gate {
+            inner: source,
+            download: self.clone(),
+            channel: 0,
+            silent: false,
+        })
+    }
+}
+
+/// Reader over a file that is still being written. While the track is being
+/// opened, off the audio thread, reads at the current end wait for more
+/// data. Afterwards the reads come from the audio callback, which must never
+/// block: there they fail with `WouldBlock` instead, and [`Gate`] keeps the
+/// decoder from getting that far.
+pub struct GrowingFile {
+    file: File,
+    download: Arc<Progressive>,
+    opening: Arc<AtomicBool>,
+}
+
+impl GrowingFile {
+    fn mark(&mut self) -> io::Result<()> {
+        let pos = self.file.stream_position()?;
+        self.download.read_to.store(pos, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl Read for GrowingFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let mut n = self.file.read(buf)?;
+            if n == 0 && !buf.is_empty() && !self.download.is_done() {
+                if !self.opening.load(Ordering::Acquire) {
+                    return Err(io::ErrorKind::WouldBlock.into());
+                }
+                std::thread::sleep(POLL);
+                continue;
+            }
+            if n == 0 {
+                // Pick up anything written between the read and the check.
+                n = self.file.read(buf)?;
+            }
+            self.mark()?;
+            return Ok(n);
+        }
+    }
+}
+
+impl Seek for GrowingFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        if matches!(pos, SeekFrom::End(_)) && !self.download.is_done() {
+            return Err(io::ErrorKind::Unsupported.into());
+        }
+        let pos = self.file.seek(pos)?;
+        self.mark()?;
+        Ok(pos)
+    }
+}
+
+impl MediaSource for GrowingFile {
+    fn is_seekable(&self) -> bool {
+        !self.opening.load(Ordering::Acquire)
+    }
+
+    /// The final size isn't known while downloading.
+    fn byte_len(&self) -> Option<u64> {
+        if self.download.is_done() {
+            self.file.metadata().ok().map(|m| m.len())
+        } else {
+            None
+        }
+    }
+}
+
+/// Plays silence, a frame at a time, while the download hasn't got far
+/// enough ahead of the decoder, and reports it as starved.
+struct Gate {
+    inner: TrackSource,
+    download: Arc<Progressive>,
+    /// Channel of the next sample within its frame.
+    channel: u16,
+    silent: bool,
+}
+
+impl Iterator for Gate {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.channel == 0 {
+            self.silent = !self.download.readable();
+            self.download.starved.store(self.silent, Ordering::Release);
+        }
+        self.channel = (self.channel + 1) % self.inner.channels().max(1);
+        if self.silent {
+            Some(0.0)
+        } else {
+            self.inner.next()
+        }
+    }
+}
+
+impl Source for Gate {
+    /// Silence can start on any frame, so no span of the inner source's
+    /// is promised.
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.channel = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A download with no yt-dlp behind it; the test writes the file.
+    fn download(name: &str, duration_ms: u64) -> (Arc<Progressive>, File) {
+        let partial = std::env::temp_dir().join(format!("sunder-progressive-{}-{name}.webm.part", std::process::id()));
+        let file = File::create(&partial).unwrap();
+        let dl = Arc::new(Progressive {
+            progress: AtomicU64::new(0),
+            duration_ms,
+            written: AtomicU64::new(0),
+            read_to: AtomicU64::new(0),
+            starved: AtomicBool::new(false),
+            done: AtomicBool::new(false),
+            failed: AtomicBool::new(false),
+            cancel: AtomicBool::new(false),
+            child: Mutex::new(None),
+            partial,
+        });
+        (dl, file)
+    }
+
+    /// Write `bytes` the way yt-dlp's output is copied in.
+    fn append(dl: &Progressive, file: &mut File, mut bytes: &[u8]) {
+        dl.copy(&mut bytes, file).unwrap();
+    }
+
+    #[test]
+    fn buffered_time_comes_from_progress_or_bytes() {
+        let (timed, _) = download("timed", 200_000);
+        timed.progress.store(2_500, Ordering::Release);
+        assert_eq!(timed.buffered_ms(), 50_000);
+
+        let (untimed, mut file) = download("untimed", 0);
+        assert_eq!(untimed.buffered_ms(), 0);
+        append(&untimed, &mut file, &[0; FALLBACK_BYTES_PER_SEC as usize * 2]);
+        assert_eq!(untimed.buffered_ms(), 2_000);
+        untimed.done.store(true, Ordering::Release);
+        assert_eq!(untimed.buffered_ms(), u64::MAX);
+        let _ = std::fs::remove_file(&untimed.partial);
+        let _ = std::fs::remove_file(&timed.partial);
+    }
+
+    #[test]
+    fn playback_starts_once_enough_is_buffered() {
+        let (dl, mut file) = download("start", 0);
+        let writer = {
+            let dl = dl.clone();
+            std::thread::spawn(move || {
+                for _ in 0..4 {
+                    std::thread::sleep(POLL);
+                    append(&dl, &mut file, &[0; FALLBACK_BYTES_PER_SEC as usize / 2]);
+                }
+            })
+        };
+        assert!(dl.wait_for(1_500, || true));
+        assert!(dl.buffered_ms() >= 1_500);
+        writer.join().unwrap();
+
+        // Given up on as soon as it is no longer wanted.
+        assert!(!dl.wait_for(60_000, || false));
+        let _ = std::fs::remove_file(&dl.partial);
+    }
+
+    #[test]
+    fn growing_file_waits_only_while_opening() {
+        let (dl, mut file) = download("grow", 0);
+        let writer = {
+            let dl = dl.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(POLL * 2);
+                append(&dl, &mut file, b"webm");
+                file
+            })
+        };
+        let mut growing = dl
+            .open_with(|mut f| {
+                assert!(!f.is_seekable());
+                let mut head = [0; 2];
+                f.read_exact(&mut head).unwrap();
+                assert_eq!(&head, b"we");
+                Ok(f)
+            })
+            .unwrap();
+        let mut file = writer.join().unwrap();
+        assert!(growing.is_seekable());
+        assert_eq!(growing.byte_len(), None);
+        assert_eq!(growing.seek(SeekFrom::End(0)).unwrap_err().kind(), io::ErrorKind::Unsupported);
+
+        // Once open, reads come from the audio callback and never wait.
+        let mut read = Vec::new();
+        let err = growing.read_to_end(&mut read).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(read, b"bm");
+        assert_eq!(dl.read_to.load(Ordering::Acquire), 4);
+
+        append(&dl, &mut file, b"!");
+        dl.done.store(true, Ordering::Release);
+        read.clear();
+        growing.read_to_end(&mut read).unwrap();
+        assert_eq!(read, b"!");
+        assert_eq!(growing.byte_len(), Some(5));
+        let _ = std::fs::remove_file(&dl.partial);
+    }
+
+    #[test]
+    fn gate_plays_silence_until_the_download_is_ahead() {
+        use rodio::buffer::SamplesBuffer;
+
+        let (dl, mut file) = download("gate", 0);
+        let track: TrackSource = Box::new(SamplesBuffer::new(2, 48_000, vec![0.5f32; 8]));
+        let mut gated = dl.gate(track);
+        assert_eq!(gated.next(), Some(0.0));
+        assert!(dl.starved());
+
+        // Data arriving mid-frame lets the next frame through, not the
+        // second half of this one.
+        append(&dl, &mut file, &[0; READ_MARGIN as usize]);
+        assert_eq!(gated.next(), Some(0.0));
+        assert_eq!(gated.next(), Some(0.5));
+        assert!(!dl.starved());
+        assert_eq!(gated.next(), Some(0.5));
+
+        dl.read_to.store(1, Ordering::Release);
+        assert_eq!(gated.next(), Some(0.0));
+        assert!(dl.starved());
+        assert_eq!(gated.next(), Some(0.0));
+        dl.done.store(true, Ordering::Release);
+        assert_eq!(gated.by_ref().collect::<Vec<_>>(), vec![0.5; 6]);
+        assert!(!dl.starved());
+        let _ = std::fs::remove_file(&dl.partial);
+    }
+
+    #[test]
+    fn half_written_webm_opens_and_seeks() {
+        use super::super::decoder::tests::{frame_index, webm};
+        use super::super::decoder::NativeDecoder;
+        use rodio::Source;
+
+        let (dl, mut file) = download("webm", 3_000);
+        let fixture = webm(3);
+        let (head, tail) = fixture.split_at(fixture.len() / 2);
+        append(&dl, &mut file, head);
+        let mut decoder = dl
+            .open_with(|f| NativeDecoder::new(Box::new(f), Some("webm")))
+            .unwrap();
+
+        decoder.try_seek(Duration::from_millis(1_000)).unwrap();
+        assert_eq!(frame_index(decoder.next().unwrap()), 8_000);
+        decoder.try_seek(Duration::from_millis(250)).unwrap();
+        assert_eq!(frame_index(decoder.next().unwrap()), 2_000);
+
+        append(&dl, &mut file, tail);
+        dl.done.store(true, Ordering::Release);
+        decoder.try_seek(Duration::from_millis(2_500)).unwrap();
+        assert_eq!(frame_index(decoder.next().unwrap()), 20_000);
+        let _ = std::fs::remove_file(&dl.partial);
+    }
+}
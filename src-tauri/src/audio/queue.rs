@@ -0,0 +1,475 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+
+use super::engine::AudioCommand;
+use super::AudioHandle;
+use crate::discord::{DiscordPresence, PresenceCommand};
+use crate::models::Track;
+
+/// How long the queue has to stay unchanged before it is written out.
+const SAVE_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    /// Wrap around to the first track after the last.
+    Queue,
+    /// Play the current track again instead of advancing.
+    Track,
+}
+
+impl RepeatMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Self::Off),
+            "queue" => Some(Self::Queue),
+            "track" => Some(Self::Track),
+            _ => None,
+        }
+    }
+}
+
+/// The queue as the frontend renders it.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueSnapshot {
+    pub tracks: Vec<Track>,
+    /// Index of the current track, -1 when nothing is selected.
+    pub index: i64,
+    pub shuffled: bool,
+    pub repeat: RepeatMode,
+}
+
+/// What plays and in which order. Shuffling reorders the list itself, so
+/// play order is always list order; the cursor marks the current track.
+#[derive(Debug, Default)]
+pub struct PlayQueue {
+    tracks: Vec<Track>,
+    cursor: Option<usize>,
+    /// Track order from before shuffling, set while shuffled.
+    unshuffled: Option<Vec<String>>,
+    repeat: RepeatMode,
+}
+
+impl PlayQueue {
+    pub fn snapshot(&self) -> QueueSnapshot {
+        QueueSnapshot {
+            tracks: self.tracks.clone(),
+            index: self.cursor.map_or(-1, |c| c as i64),
+            shuffled: self.unshuffled.is_some(),
+            repeat: self.repeat,
+        }
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    pub fn current(&self) -> Option<&Track> {
+        self.cursor.and_then(|c| self.tracks.get(c))
+    }
+
+    pub fn set_repeat(&mut self, mode: RepeatMode) {
+        self.repeat = mode;
+    }
+
+    /// Replace the queue, with `cursor` (if in range) as the current track.
+    pub fn set_tracks(&mut self, tracks: Vec<Track>, cursor: Option<usize>) {
+        self.cursor = cursor.filter(|&c| c < tracks.len());
+        self.tracks = tracks;
+        self.unshuffled = None;
+    }
+
+    /// Make the track at `index` current.
+    pub fn select(&mut self, index: usize) -> Option<&Track> {
+        if index >= self.tracks.len() {
+            return None;
+        }
+        self.cursor = Some(index);
+        self.current()
+    }
+
+    /// Make `track` current: jump to it when queued, otherwise insert it
+    /// right after the current track.
+    pub fn select_track(&mut self, track: Track) {
+        let index = match self.tracks.iter().position(|t| t.id == track.id) {
+            Some(i) => i,
+            None => {
+                let at = self.cursor.map_or(0, |c| c + 1);
+                self.tracks.insert(at, track);
+                at
+            }
+        };
+        self.cursor = Some(index);
+    }
+
+    /// Move the cursor to `id` after the engine started it on its own
+    /// (gapless or crossfade), preferring the slot auto-advance would pick.
+    pub fn focus(&mut self, id: &str) {
+        if let Some(i) = self.next_index(false).filter(|&i| self.tracks[i].id == id) {
+            self.cursor = Some(i);
+        } else if let Some(i) = self.tracks.iter().position(|t| t.id == id) {
+            self.cursor = Some(i);
+        }
+    }
+
+    /// Add tracks to the end, skipping ones already queued.
+    pub fn append(&mut self, tracks: Vec<Track>) {
+        for track in tracks {
+            if !self.tracks.iter().any(|t| t.id == track.id) {
+                self.tracks.push(track);
+            }
+        }
+    }
+
+    /// Queue tracks to play right after the current one, in the given order.
+    /// Tracks already queued elsewhere are moved rather than duplicated.
+    pub fn insert_next(&mut self, tracks: Vec<Track>) {
+        let current = self.current().map(|t| t.id.clone());
+        self.tracks
+            .retain(|t| current.as_ref() == Some(&t.id) || !tracks.iter().any(|n| n.id == t.id));
+        self.cursor = current.as_ref().and_then(|id| self.tracks.iter().position(|t| &t.id == id));
+        let at = self.cursor.map_or(0, |c| c + 1);
+        let incoming: Vec<Track> = tracks.into_iter().filter(|t| current.as_ref() != Some(&t.id)).collect();
+        self.tracks.splice(at..at, incoming);
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index >= self.tracks.len() {
+            return;
+        }
+        self.tracks.remove(index);
+        self.cursor = match self.cursor {
+            Some(c) if index < c => Some(c - 1),
+            Some(c) if c >= self.tracks.len() => self.tracks.len().checked_sub(1),
+            c => c,
+        };
+    }
+
+    pub fn move_track(&mut self, from: usize, to: usize) {
+        let len = self.tracks.len();
+        if from == to || from >= len || to >= len {
+            return;
+        }
+        let track = self.tracks.remove(from);
+        self.tracks.insert(to, track);
+        self.cursor = self.cursor.map(|c| {
+            if c == from {
+                to
+            } else if from < c && to >= c {
+                c - 1
+            } else if from > c && to <= c {
+                c + 1
+            } else {
+                c
+            }
+        });
+    }
+
+    /// Shuffle everything but the current track, which moves to the front.
+    pub fn shuffle(&mut self) {
+        if self.tracks.len() <= 1 {
+            return;
+        }
+        if self.unshuffled.is_none() {
+            self.unshuffled = Some(self.tracks.iter().map(|t| t.id.clone()).collect());
+        }
+        let current = self.cursor.map(|c| self.tracks.remove(c));
+        let mut rng = Xorshift::seeded();
+        for i in (1..self.tracks.len()).rev() {
+            let j = rng.below(i + 1);
+            self.tracks.swap(i, j);
+        }
+        if let Some(track) = current {
+            self.tracks.insert(0, track);
+            self.cursor = Some(0);
+        }
+    }
+
+    /// Go back to the order from before shuffling. Tracks queued since then
+    /// follow in the order they are in now.
+    pub fn unshuffle(&mut self) {
+        let Some(order) = self.unshuffled.take() else {
+            return;
+        };
+        let current = self.current().map(|t| t.id.clone());
+        let mut rest = std::mem::take(&mut self.tracks);
+        for id in &order {
+            if let Some(i) = rest.iter().position(|t| &t.id == id) {
+                self.tracks.push(rest.remove(i));
+            }
+        }
+        self.tracks.append(&mut rest);
+        self.cursor = current.and_then(|id| self.tracks.iter().position(|t| t.id == id));
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+        self.cursor = None;
+        self.unshuffled = None;
+    }
+
+    /// Index that follows the current track. Automatic advance (`manual`
+    /// false) stays on the current track under repeat-one.
+    fn next_index(&self, manual: bool) -> Option<usize> {
+        let len = self.tracks.len();
+        if len == 0 {
+            return None;
+        }
+        match self.cursor {
+            Some(c) if !manual && self.repeat == RepeatMode::Track => Some(c),
+            Some(c) if c + 1 < len => Some(c + 1),
+            None => Some(0),
+            Some(_) if self.repeat == RepeatMode::Queue => Some(0),
+            Some(_) => None,
+        }
+    }
+
+    fn prev_index(&self, manual: bool) -> Option<usize> {
+        let len = self.tracks.len();
+        match self.cursor? {
+            c if !manual && self.repeat == RepeatMode::Track => Some(c),
+            c if c > 0 => Some(c - 1),
+            _ if self.repeat == RepeatMode::Queue && len > 0 => Some(len - 1),
+            _ => None,
+        }
+    }
+
+    /// The track automatic advance would play next, without moving.
+    pub fn peek_next(&self) -> Option<&Track> {
+        self.next_index(false).map(|i| &self.tracks[i])
+    }
+
+    pub fn advance(&mut self, manual: bool) -> Option<&Track> {
+        self.cursor = Some(self.next_index(manual)?);
+        self.current()
+    }
+
+    pub fn retreat(&mut self, manual: bool) -> Option<&Track> {
+        self.cursor = Some(self.prev_index(manual)?);
+        self.current()
+    }
+}
+
+/// Small xorshift generator; shuffling doesn't need more than that.
+struct Xorshift(u64);
+
+impl Xorshift {
+    fn seeded() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self(nanos | 1)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+/// Start the queue's current track on the engine.
+pub fn play_current(app: &tauri::AppHandle, queue: &PlayQueue, send: &impl Fn(AudioCommand)) {
+    let Some(track) = queue.current() else {
+        return;
+    };
+    send(AudioCommand::Play {
+        video_id: track.id.clone(),
+        duration_ms: (track.duration_secs * 1000.0) as u64,
+        album: track.album.clone(),
+        resume: app
+            .state::<crate::db::SearchCache>()
+            .get_resume_point(&track.id)
+            .ok()
+            .flatten(),
+    });
+    announce(app, queue, send);
+}
+
+/// Publish the current track as now playing (media controls, Discord,
+/// listen history) and preload the one after it. Used directly when the
+/// engine moved on by itself.
+pub fn announce(app: &tauri::AppHandle, queue: &PlayQueue, send: &impl Fn(AudioCommand)) {
+    let Some(track) = queue.current() else {
+        return;
+    };
+    send(AudioCommand::UpdateMetadata {
+        title: track.title.clone(),
+        artist: track.artist.clone(),
+        thumbnail: track.thumbnail.clone(),
+        track_id: track.id.clone(),
+    });
+    app.state::<DiscordPresence>().send(PresenceCommand::SetActivity {
+        title: track.title.clone(),
+        artist: track.artist.clone(),
+        thumbnail: track.thumbnail.clone(),
+    });
+    let _ = app.state::<crate::db::SearchCache>().record_listen(&track.id);
+    changed(app, queue, send);
+}
+
+/// After an edit: keep the engine's preloaded track in line with the queue
+/// and let the frontend know.
+pub fn changed(app: &tauri::AppHandle, queue: &PlayQueue, send: &impl Fn(AudioCommand)) {
+    // Repeat-one loops inside the engine; there is nothing to preload.
+    let next = queue.peek_next().filter(|_| queue.repeat != RepeatMode::Track);
+    send(match next {
+        Some(next) => AudioCommand::Preload {
+            video_id: next.id.clone(),
+            duration_ms: (next.duration_secs * 1000.0) as u64,
+            album: next.album.clone(),
+        },
+        None => AudioCommand::ClearPreload,
+    });
+    send(AudioCommand::QueueChanged);
+    let snapshot = queue.snapshot();
+    let current = usize::try_from(snapshot.index).ok();
+    app.state::<QueueSaver>().save(snapshot.tracks.clone(), current);
+    let _ = app.emit("queue-changed", snapshot);
+}
+
+/// Writes the queue to the database on a thread of its own, once edits have
+/// paused: dragging a track moves it many times but saves it once.
+pub struct QueueSaver {
+    tx: Sender<(Vec<Track>, Option<usize>)>,
+}
+
+impl QueueSaver {
+    pub fn start(app: tauri::AppHandle) -> Self {
+        let (tx, rx) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("sunder-queue-saver".into())
+            .spawn(move || {
+                save_when_settled(rx, |tracks, current| {
+                    if let Err(e) = app.state::<crate::db::SearchCache>().save_queue(tracks, current) {
+                        eprintln!("[sunder] failed to save the queue: {e}");
+                    }
+                })
+            })
+            .expect("failed to spawn queue saver thread");
+        Self { tx }
+    }
+
+    fn save(&self, tracks: Vec<Track>, current: Option<usize>) {
+        let _ = self.tx.send((tracks, current));
+    }
+}
+
+/// Hand the latest queue to `write` each time none newer has come in for
+/// `SAVE_DELAY`, and once more when the sender goes away.
+fn save_when_settled(rx: Receiver<(Vec<Track>, Option<usize>)>, write: impl Fn(&[Track], Option<usize>)) {
+    while let Ok(mut latest) = rx.recv() {
+        loop {
+            match rx.recv_timeout(SAVE_DELAY) {
+                Ok(newer) => latest = newer,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    write(&latest.0, latest.1);
+                    return;
+                }
+            }
+        }
+        write(&latest.0, latest.1);
+    }
+}
+
+/// Put back the queue saved last session, without starting it.
+pub fn restore(app: &tauri::AppHandle) {
+    match app.state::<crate::db::SearchCache>().load_queue() {
+        Ok((tracks, current)) => app.state::<AudioHandle>().queue.lock().unwrap().set_tracks(tracks, current),
+        Err(e) => eprintln!("[sunder] failed to restore the queue: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::track;
+
+    fn ids(q: &PlayQueue) -> Vec<&str> {
+        q.tracks().iter().map(|t| t.id.as_str()).collect()
+    }
+
+    #[test]
+    fn advance_respects_repeat_modes() {
+        let mut q = PlayQueue::default();
+        q.set_tracks(vec![track("a"), track("b")], Some(1));
+        assert!(q.advance(false).is_none());
+
+        q.set_repeat(RepeatMode::Queue);
+        assert_eq!(q.advance(false).unwrap().id, "a");
+        assert_eq!(q.retreat(true).unwrap().id, "b");
+
+        q.set_repeat(RepeatMode::Track);
+        assert_eq!(q.peek_next().unwrap().id, "b");
+        assert_eq!(q.retreat(true).unwrap().id, "a");
+        assert_eq!(q.advance(false).unwrap().id, "a");
+    }
+
+    #[test]
+    fn insert_next_moves_existing_tracks_after_current() {
+        let mut q = PlayQueue::default();
+        q.set_tracks(vec![track("a"), track("b"), track("c"), track("d")], Some(1));
+        q.insert_next(vec![track("a"), track("e"), track("b")]);
+        assert_eq!(ids(&q), ["b", "a", "e", "c", "d"]);
+        assert_eq!(q.current().unwrap().id, "b");
+
+        q.remove(0);
+        assert_eq!(q.current().unwrap().id, "a");
+        q.move_track(3, 0);
+        assert_eq!(ids(&q), ["d", "a", "e", "c"]);
+        assert_eq!(q.current().unwrap().id, "a");
+    }
+
+    #[test]
+    fn shuffle_keeps_current_track_first_and_can_be_undone() {
+        let mut q = PlayQueue::default();
+        q.set_tracks((0..20).map(|i| track(&i.to_string())).collect(), Some(7));
+        q.shuffle();
+        assert_eq!(q.current().unwrap().id, "7");
+        assert_eq!(q.snapshot().index, 0);
+        let mut sorted: Vec<_> = ids(&q).iter().map(|s| s.parse::<u32>().unwrap()).collect();
+        sorted.sort();
+        assert_eq!(sorted, (0..20).collect::<Vec<_>>());
+
+        q.append(vec![track("new")]);
+        q.unshuffle();
+        let mut expected: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        expected.push("new".into());
+        assert_eq!(ids(&q), expected);
+        assert_eq!(q.current().unwrap().id, "7");
+        assert!(!q.snapshot().shuffled);
+    }
+
+    #[test]
+    fn a_burst_of_edits_is_saved_once() {
+        let (tx, rx) = mpsc::channel();
+        let saves = std::sync::Mutex::new(Vec::new());
+        let saver = std::thread::scope(|scope| {
+            let saver = scope.spawn(|| {
+                save_when_settled(rx, |tracks, current| {
+                    saves.lock().unwrap().push((tracks.iter().map(|t| t.id.clone()).collect::<Vec<_>>(), current))
+                })
+            });
+            for current in 0..3 {
+                tx.send((vec![track("a"), track("b"), track("c")], Some(current))).unwrap();
+            }
+            std::thread::sleep(SAVE_DELAY * 2);
+            assert_eq!(saves.lock().unwrap().len(), 1);
+            tx.send((vec![track("d")], None)).unwrap();
+            drop(tx);
+            saver.join()
+        });
+        saver.unwrap();
+        assert_eq!(
+            saves.into_inner().unwrap(),
+            [(vec!["a".to_string(), "b".into(), "c".into()], Some(2)), (vec!["d".to_string()], None)]
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::Source;
+
+use super::engine::TrackSource;
+
+/// Repeat-one switch shared by the engine and every source it plays.
+#[derive(Clone, Default)]
+pub struct RepeatOne {
+    enabled: Arc<AtomicBool>,
+    restarts: Arc<AtomicUsize>,
+}
+
+impl RepeatOne {
+    pub fn set(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Bumped each time a track wraps around to its start.
+    pub fn restarts(&self) -> usize {
+        self.restarts.load(Ordering::Acquire)
+    }
+
+    pub fn wrap(&self, source: TrackSource) -> TrackSource {
+        Box::new(Repeating {
+            inner: source,
+            switch: self.clone(),
+        })
+    }
+}
+
+/// Seeks back to the start when the track runs out while repeat-one is on,
+/// so it loops on the same decoder without a gap.
+struct Repeating {
+    inner: TrackSource,
+    switch: RepeatOne,
+}
+
+impl Iterator for Repeating {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(sample) = self.inner.next() {
+            return Some(sample);
+        }
+        if !self.switch.enabled.load(Ordering::Relaxed) || self.inner.try_seek(Duration::ZERO).is_err() {
+            return None;
+        }
+        self.switch.restarts.fetch_add(1, Ordering::Release);
+        self.inner.next()
+    }
+}
+
+impl Source for Repeating {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn samples() -> TrackSource {
+        Box::new(SamplesBuffer::new(1, 8_000, vec![0.1f32, 0.2, 0.3]))
+    }
+
+    #[test]
+    fn loops_back_to_the_start_while_on() {
+        let repeat_one = RepeatOne::default();
+        repeat_one.set(true);
+        let mut source = repeat_one.wrap(samples());
+        let played: Vec<f32> = source.by_ref().take(7).collect();
+        assert_eq!(played, [0.1, 0.2, 0.3, 0.1, 0.2, 0.3, 0.1]);
+        assert_eq!(repeat_one.restarts(), 2);
+
+        repeat_one.set(false);
+        assert_eq!(source.collect::<Vec<_>>(), [0.2, 0.3]);
+        assert_eq!(repeat_one.restarts(), 2);
+    }
+
+    #[test]
+    fn ends_when_off() {
+        let repeat_one = RepeatOne::default();
+        assert_eq!(repeat_one.wrap(samples()).count(), 3);
+        assert_eq!(repeat_one.restarts(), 0);
+    }
+}
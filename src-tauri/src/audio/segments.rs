@@ -0,0 +1,87 @@
+use crate::models::Segment;
+
+/// The categories SponsorBlock knows. All of them are looked up, so the
+/// cached list still serves when the wanted ones change.
+const CATEGORIES: [&str; 8] = [
+    "sponsor",
+    "selfpromo",
+    "interaction",
+    "intro",
+    "outro",
+    "preview",
+    "music_offtopic",
+    "filler",
+];
+
+/// The categories to ask the server for: every known one and whatever else
+/// is `wanted`.
+pub fn to_look_up(wanted: &[String]) -> Vec<String> {
+    let mut all: Vec<String> = CATEGORIES.iter().map(|c| c.to_string()).collect();
+    all.extend(wanted.iter().filter(|c| !CATEGORIES.contains(&c.as_str())).cloned());
+    all
+}
+
+/// The segments to skip out of all those known for a file: the wanted
+/// categories, moved into a chapter track's `window` (ms) when there is one.
+pub fn to_skip(segments: &[Segment], categories: &[String], window: Option<(u64, u64)>) -> Vec<Segment> {
+    let (start, end) = window.unwrap_or((0, u64::MAX));
+    segments
+        .iter()
+        .filter(|s| categories.contains(&s.category) && s.start_ms < end && s.end_ms > start)
+        .map(|s| Segment {
+            category: s.category.clone(),
+            start_ms: s.start_ms.max(start) - start,
+            end_ms: s.end_ms.min(end) - start,
+        })
+        .collect()
+}
+
+/// The segment playback ran into on its way from `from` to `to`, or started
+/// in when there is no `from`. Landing inside one by seeking doesn't count,
+/// so it can still be listened to on purpose.
+pub fn entered(segments: &[Segment], from: Option<u64>, to: u64) -> Option<&Segment> {
+    segments
+        .iter()
+        .find(|s| from.is_none_or(|f| f < s.start_ms) && s.start_ms <= to && to < s.end_ms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(category: &str, start_ms: u64, end_ms: u64) -> Segment {
+        Segment { category: category.into(), start_ms, end_ms }
+    }
+
+    #[test]
+    fn keeps_wanted_categories_inside_the_window() {
+        let all = [
+            segment("music_offtopic", 0, 12_000),
+            segment("sponsor", 60_000, 90_000),
+            segment("music_offtopic", 170_000, 200_000),
+        ];
+        let wanted = vec!["music_offtopic".to_string()];
+        assert_eq!(to_skip(&all, &wanted, None), vec![all[0].clone(), all[2].clone()]);
+        assert_eq!(
+            to_skip(&all, &wanted, Some((100_000, 180_000))),
+            vec![segment("music_offtopic", 70_000, 80_000)]
+        );
+    }
+
+    #[test]
+    fn looks_up_every_category_and_custom_ones() {
+        let all = to_look_up(&["music_offtopic".into(), "exclusive_access".into()]);
+        assert_eq!(all.len(), CATEGORIES.len() + 1);
+        assert_eq!(all.last().map(String::as_str), Some("exclusive_access"));
+    }
+
+    #[test]
+    fn skips_on_the_way_in_only() {
+        let skip = [segment("intro", 0, 10_000), segment("outro", 50_000, 60_000)];
+        assert_eq!(entered(&skip, None, 0), Some(&skip[0]));
+        assert_eq!(entered(&skip, Some(49_990), 50_040), Some(&skip[1]));
+        // Seeked into: played as asked.
+        assert_eq!(entered(&skip, Some(52_000), 52_050), None);
+        assert_eq!(entered(&skip, Some(20_000), 20_050), None);
+    }
+}
@@ -0,0 +1,269 @@
+use std::path::Path;
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::Source;
+
+use super::decoder::NativeDecoder;
+use crate::error::AppError;
+
+/// Length of the blocks whose peak decides whether audio is silent.
+const BLOCK_MS: u64 = 10;
+/// Kept before the first audible block, so attacks aren't cut.
+const LEAD_PAD_MS: u64 = 100;
+/// Kept after the last one, for the fade below the threshold.
+const TAIL_PAD_MS: u64 = 300;
+
+/// The audible part of a track: everything outside it is below the silence
+/// threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudibleSpan {
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+impl AudibleSpan {
+    /// All of the track, for one with nothing trimmed.
+    pub const WHOLE: AudibleSpan = AudibleSpan { start_ms: 0, end_ms: u64::MAX };
+}
+
+/// Finds the first and last block of a stream that peak above a threshold.
+pub struct SilenceDetector {
+    channels: usize,
+    block_len: usize,
+    threshold: f32,
+    /// Samples seen so far.
+    pos: usize,
+    block_peak: f32,
+    first: Option<usize>,
+    last: usize,
+}
+
+impl SilenceDetector {
+    pub fn new(channels: u16, sample_rate: u32, threshold_db: f64) -> Self {
+        let channels = channels.max(1) as usize;
+        Self {
+            channels,
+            block_len: (sample_rate as u64 * BLOCK_MS / 1000).max(1) as usize * channels,
+            threshold: 10f32.powf(threshold_db as f32 / 20.0),
+            pos: 0,
+            block_peak: 0.0,
+            first: None,
+            last: 0,
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        self.block_peak = self.block_peak.max(sample.abs());
+        self.pos += 1;
+        if self.pos % self.block_len == 0 {
+            self.end_block();
+        }
+    }
+
+    fn end_block(&mut self) {
+        if self.block_peak >= self.threshold {
+            let start = (self.pos - 1) / self.block_len * self.block_len;
+            self.first.get_or_insert(start);
+            self.last = self.pos;
+        }
+        self.block_peak = 0.0;
+    }
+
+    /// None when nothing ever reached the threshold.
+    pub fn finish(mut self, sample_rate: u32) -> Option<AudibleSpan> {
+        if self.pos % self.block_len != 0 {
+            self.end_block();
+        }
+        let to_ms = |samples: usize| (samples / self.channels) as u64 * 1000 / sample_rate.max(1) as u64;
+        let total = to_ms(self.pos);
+        Some(AudibleSpan {
+            start_ms: to_ms(self.first?).saturating_sub(LEAD_PAD_MS),
+            end_ms: (to_ms(self.last) + TAIL_PAD_MS).min(total),
+        })
+    }
+}
+
+/// Decode a whole file and find its audible span. Runs at decode speed, so
+/// callers keep it off the audio thread.
+pub fn analyze_file(path: &Path, threshold_db: f64) -> Result<Option<AudibleSpan>, AppError> {
+    let file = std::fs::File::open(path)?;
+    let decoder = NativeDecoder::new(Box::new(file), path.extension().and_then(|e| e.to_str()))?;
+    let sample_rate = decoder.sample_rate();
+    let mut detector = SilenceDetector::new(decoder.channels(), sample_rate, threshold_db);
+    for sample in decoder {
+        detector.push(sample);
+    }
+    Ok(detector.finish(sample_rate))
+}
+
+/// Find a track's audible span and remember it. Failures are logged and
+/// leave the track untrimmed.
+pub fn analyze_and_store(
+    app: &tauri::AppHandle,
+    track_id: &str,
+    path: &Path,
+    threshold_db: f64,
+) -> Option<AudibleSpan> {
+    use tauri::Manager;
+    match analyze_file(path, threshold_db) {
+        Ok(Some(span)) => {
+            eprintln!(
+                "[sunder] silence {track_id}: audible {} ms to {} ms",
+                span.start_ms, span.end_ms
+            );
+            let _ = app.state::<crate::db::SearchCache>().set_audible_span(track_id, threshold_db, &span);
+            Some(span)
+        }
+        Ok(None) => None,
+        Err(e) => {
+            eprintln!("[sunder] silence analysis failed for {track_id}: {e}");
+            None
+        }
+    }
+}
+
+/// Plays only the audible span of a track. Positions stay those of the
+/// whole file; seeks before the start land on it.
+pub struct Trim<S: Source<Item = f32>> {
+    inner: S,
+    channels: u64,
+    sample_rate: u64,
+    start: u64,
+    end: u64,
+    /// Samples into the file.
+    pos: u64,
+}
+
+impl<S: Source<Item = f32>> Trim<S> {
+    pub fn new(inner: S, span: AudibleSpan) -> Self {
+        let channels = inner.channels().max(1) as u64;
+        let sample_rate = inner.sample_rate() as u64;
+        let at = |ms: u64| ms.saturating_mul(sample_rate) / 1000 * channels;
+        let mut trim = Self {
+            start: at(span.start_ms),
+            end: at(span.end_ms),
+            inner,
+            channels,
+            sample_rate,
+            pos: 0,
+        };
+        if trim.inner.try_seek(Duration::from_millis(span.start_ms)).is_ok() {
+            trim.pos = trim.start;
+        }
+        trim
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for Trim<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        // Sources that can't seek are read through up to the start.
+        while self.pos < self.start {
+            self.inner.next()?;
+            self.pos += 1;
+        }
+        if self.pos >= self.end {
+            return None;
+        }
+        self.pos += 1;
+        self.inner.next()
+    }
+}
+
+impl<S: Source<Item = f32>> Source for Trim<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let start = Duration::from_millis(self.start / self.channels * 1000 / self.sample_rate.max(1));
+        let pos = pos.max(start);
+        self.inner.try_seek(pos)?;
+        let frame = (pos.as_secs_f64() * self.sample_rate as f64) as u64;
+        self.pos = frame * self.channels;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    /// 2 s of silence, 3 s of tone, 4 s of near-silent hiss, stereo at 8 kHz.
+    fn padded() -> Vec<f32> {
+        (0..9 * 8_000)
+            .flat_map(|i| {
+                let t = i as f32 / 8_000.0;
+                let v = if (2.0..5.0).contains(&t) {
+                    (2.0 * std::f32::consts::PI * 440.0 * t).sin() * 0.5
+                } else {
+                    0.0005 * if i % 2 == 0 { 1.0 } else { -1.0 }
+                };
+                [v, v]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_the_span_and_plays_only_it() {
+        let samples = padded();
+        let mut detector = SilenceDetector::new(2, 8_000, -50.0);
+        samples.iter().for_each(|&s| detector.push(s));
+        let span = detector.finish(8_000).unwrap();
+        assert!(span.start_ms.abs_diff(2_000 - LEAD_PAD_MS) <= BLOCK_MS, "{span:?}");
+        assert!(span.end_ms.abs_diff(5_000 + TAIL_PAD_MS) <= BLOCK_MS, "{span:?}");
+
+        let trimmed: Vec<f32> = Trim::new(SamplesBuffer::new(2, 8_000, samples.clone()), span).collect();
+        let expected = (span.end_ms - span.start_ms) as usize * 8 * 2;
+        assert_eq!(trimmed.len(), expected);
+
+        let mut quiet = SilenceDetector::new(2, 8_000, -50.0);
+        samples.iter().for_each(|&s| quiet.push(s * 0.001));
+        assert!(quiet.finish(8_000).is_none());
+    }
+
+    #[test]
+    fn seeks_stay_inside_the_span() {
+        let span = AudibleSpan { start_ms: 2_000, end_ms: 5_000 };
+        let mut trim = Trim::new(SamplesBuffer::new(2, 8_000, padded()), span);
+
+        trim.try_seek(Duration::from_millis(500)).unwrap();
+        assert_eq!(trim.by_ref().count(), 3_000 * 8 * 2);
+
+        trim.try_seek(Duration::from_millis(4_000)).unwrap();
+        assert_eq!(trim.by_ref().count(), 1_000 * 8 * 2);
+
+        trim.try_seek(Duration::from_millis(7_000)).unwrap();
+        assert_eq!(trim.count(), 0);
+    }
+
+    #[test]
+    fn the_span_depends_on_the_threshold() {
+        let samples = padded();
+        let span = |threshold_db| {
+            let mut detector = SilenceDetector::new(2, 8_000, threshold_db);
+            samples.iter().for_each(|&s| detector.push(s));
+            detector.finish(8_000)
+        };
+        let tone = span(-50.0).unwrap();
+        // Low enough to count the hiss as sound, so nothing is trimmed.
+        let hiss = span(-80.0).unwrap();
+        assert_eq!(hiss, AudibleSpan { start_ms: 0, end_ms: 9_000 });
+        assert!(tone.start_ms > hiss.start_ms && tone.end_ms < hiss.end_ms);
+    }
+}
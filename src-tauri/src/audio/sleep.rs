@@ -0,0 +1,167 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::silence::AudibleSpan;
+
+/// When the sleep timer stops playback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum SleepMode {
+    /// After `secs` seconds, whatever is playing.
+    Duration { secs: u64 },
+    /// Once the playing track ends.
+    EndOfTrack,
+    /// Once `count` tracks have ended, the playing one included.
+    Tracks { count: u32 },
+}
+
+/// What the `sleep-timer` event reports.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SleepStatus {
+    pub mode: SleepMode,
+    /// Seconds until playback stops, when known: always for a duration,
+    /// otherwise during the last track.
+    pub remaining_secs: Option<u64>,
+    /// Tracks still to end before playback stops, the playing one included.
+    pub tracks_left: Option<u32>,
+    pub fading: bool,
+}
+
+/// A running sleep timer. Playback fades out over `fade` and then stops.
+#[derive(Debug, Clone)]
+pub struct SleepTimer {
+    mode: SleepMode,
+    deadline: Option<Instant>,
+    tracks_left: u32,
+    fade: Duration,
+}
+
+impl SleepTimer {
+    pub fn new(mode: SleepMode, fade: Duration) -> Self {
+        let (deadline, tracks_left) = match mode {
+            SleepMode::Duration { secs } => (Some(Instant::now() + Duration::from_secs(secs)), 0),
+            SleepMode::EndOfTrack => (None, 1),
+            SleepMode::Tracks { count } => (None, count.max(1)),
+        };
+        Self {
+            mode,
+            deadline,
+            tracks_left,
+            fade,
+        }
+    }
+
+    /// Whether playback stops when the playing track ends, so nothing may be
+    /// lined up behind it.
+    pub fn on_last_track(&self) -> bool {
+        self.deadline.is_none() && self.tracks_left <= 1
+    }
+
+    /// Count a track that played to its end. False when that was the last.
+    pub fn track_ended(&mut self) -> bool {
+        if self.deadline.is_some() {
+            return true;
+        }
+        self.tracks_left = self.tracks_left.saturating_sub(1);
+        self.tracks_left > 0
+    }
+
+    /// Time until playback stops; `track_left` is what remains of the
+    /// playing track, in real time.
+    pub fn remaining(&self, now: Instant, track_left: Option<Duration>) -> Option<Duration> {
+        match self.deadline {
+            Some(deadline) => Some(deadline.saturating_duration_since(now)),
+            None if self.on_last_track() => track_left,
+            None => None,
+        }
+    }
+
+    /// Whether a duration timer has run out.
+    pub fn expired(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|d| now >= d)
+    }
+
+    /// Volume factor: 1 until the fade starts, down to 0 when time is up.
+    pub fn gain(&self, remaining: Option<Duration>) -> f32 {
+        match remaining {
+            Some(left) if left < self.fade => left.as_secs_f32() / self.fade.as_secs_f32(),
+            _ => 1.0,
+        }
+    }
+
+    pub fn status(&self, remaining: Option<Duration>) -> SleepStatus {
+        SleepStatus {
+            mode: self.mode,
+            remaining_secs: remaining.map(|r| r.as_secs_f64().ceil() as u64),
+            tracks_left: self.deadline.is_none().then_some(self.tracks_left),
+            fading: self.gain(remaining) < 1.0,
+        }
+    }
+}
+
+/// Where a track stops playing, in ms on its own clock: the first of its
+/// length, the end of its chapter and the end of its audible span, or
+/// `None` when none of them is known.
+pub fn track_end(duration_ms: u64, window: Option<(u64, u64)>, span: AudibleSpan) -> Option<u64> {
+    [
+        (duration_ms > 0).then_some(duration_ms),
+        window.map(|(start, end)| end - start),
+        (span != AudibleSpan::WHOLE).then_some(span.end_ms),
+    ]
+    .into_iter()
+    .flatten()
+    .min()
+}
+
+/// Real time a track at `position_ms` has left before `end_ms` when it
+/// plays at `speed`.
+pub fn time_left(position_ms: u64, end_ms: u64, speed: f32) -> Duration {
+    Duration::from_secs_f64(end_ms.saturating_sub(position_ms) as f64 / 1000.0 / speed as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fades_out_before_the_deadline() {
+        let timer = SleepTimer::new(SleepMode::Duration { secs: 60 }, Duration::from_secs(10));
+        let start = Instant::now();
+        assert_eq!(timer.gain(timer.remaining(start, None)), 1.0);
+        let late = start + Duration::from_secs(55);
+        let gain = timer.gain(timer.remaining(late, None));
+        assert!(gain > 0.45 && gain <= 0.5, "{gain}");
+        assert!(!timer.expired(late));
+        assert!(timer.expired(start + Duration::from_secs(61)));
+        assert_eq!(timer.gain(timer.remaining(start + Duration::from_secs(61), None)), 0.0);
+    }
+
+    #[test]
+    fn counts_tracks_down_to_the_last() {
+        let mut timer = SleepTimer::new(SleepMode::Tracks { count: 2 }, Duration::from_secs(5));
+        let now = Instant::now();
+        let track_left = Some(Duration::from_secs(3));
+        assert!(!timer.on_last_track());
+        assert_eq!(timer.remaining(now, track_left), None);
+        assert!(timer.track_ended());
+
+        assert!(timer.on_last_track());
+        assert_eq!(timer.remaining(now, track_left), track_left);
+        assert!(timer.status(track_left).fading);
+        assert_eq!(timer.status(track_left).tracks_left, Some(1));
+        assert!(!timer.track_ended());
+        assert!(!timer.expired(now));
+    }
+
+    #[test]
+    fn a_track_ends_where_it_stops_playing() {
+        let trimmed = AudibleSpan { start_ms: 1_000, end_ms: 170_000 };
+        assert_eq!(track_end(180_000, None, AudibleSpan::WHOLE), Some(180_000));
+        assert_eq!(track_end(180_000, None, trimmed), Some(170_000));
+        assert_eq!(track_end(0, Some((60_000, 90_000)), AudibleSpan::WHOLE), Some(30_000));
+        assert_eq!(track_end(0, None, AudibleSpan::WHOLE), None);
+        assert_eq!(time_left(150_000, 170_000, 2.0), Duration::from_secs(10));
+        assert_eq!(time_left(175_000, 170_000, 1.0), Duration::ZERO);
+    }
+}
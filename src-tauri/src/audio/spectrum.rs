@@ -0,0 +1,299 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rodio::source::SeekError;
+use rodio::Source;
+use serde::Serialize;
+use tauri::Emitter;
+
+/// Samples per FFT; about 46 ms at 44.1 kHz.
+const FFT_SIZE: usize = 2048;
+const BAND_COUNT: usize = 64;
+const LOWEST_HZ: f32 = 20.0;
+const HIGHEST_HZ: f32 = 20_000.0;
+const UPDATES_PER_SEC: u32 = 30;
+/// Floor for every reading, so silence doesn't come out as -inf.
+const FLOOR_DB: f32 = -100.0;
+
+/// One hop of audio handed from the tap to the analysis thread.
+struct Snapshot {
+    sample_rate: u32,
+    /// The last `FFT_SIZE` samples, channels mixed down, oldest first.
+    mono: Vec<f32>,
+    sum_squares: Vec<f32>,
+    peaks: Vec<f32>,
+    frames: usize,
+}
+
+#[derive(Serialize, Clone)]
+struct SpectrumPayload {
+    /// Log-spaced bands from 20 Hz up, in dBFS.
+    bands: Vec<f32>,
+    /// Per channel, in dBFS.
+    rms: Vec<f32>,
+    peak: Vec<f32>,
+}
+
+/// Switch and mailbox shared by every tap; the analysis runs on a thread of
+/// its own so the audio thread only ever copies samples.
+#[derive(Clone)]
+pub struct Spectrum {
+    enabled: Arc<AtomicBool>,
+    tx: SyncSender<Snapshot>,
+}
+
+impl Spectrum {
+    pub fn start(app: tauri::AppHandle) -> Self {
+        // Room for one snapshot in flight; the tap drops hops beyond that.
+        let (tx, rx) = mpsc::sync_channel(1);
+        std::thread::Builder::new()
+            .name("sunder-spectrum".into())
+            .spawn(move || analyze(app, rx))
+            .expect("failed to spawn spectrum thread");
+        Self {
+            enabled: Arc::new(AtomicBool::new(false)),
+            tx,
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn tap<S: Source<Item = f32>>(&self, inner: S) -> SpectrumTap<S> {
+        let channels = inner.channels().max(1) as usize;
+        let sample_rate = inner.sample_rate();
+        SpectrumTap {
+            inner,
+            spectrum: self.clone(),
+            channels,
+            sample_rate,
+            hop: (sample_rate / UPDATES_PER_SEC).max(1) as usize,
+            channel_idx: 0,
+            frame_sum: 0.0,
+            history: vec![0.0; FFT_SIZE],
+            write: 0,
+            sum_squares: vec![0.0; channels],
+            peaks: vec![0.0; channels],
+            frames: 0,
+            active: false,
+        }
+    }
+}
+
+/// Passes samples through untouched, collecting them for `Spectrum` while
+/// it is enabled.
+pub struct SpectrumTap<S: Source<Item = f32>> {
+    inner: S,
+    spectrum: Spectrum,
+    channels: usize,
+    sample_rate: u32,
+    /// Frames between snapshots.
+    hop: usize,
+    channel_idx: usize,
+    frame_sum: f32,
+    /// Ring of mixed-down samples.
+    history: Vec<f32>,
+    write: usize,
+    sum_squares: Vec<f32>,
+    peaks: Vec<f32>,
+    frames: usize,
+    /// Whether this hop is being collected; checked once per hop.
+    active: bool,
+}
+
+impl<S: Source<Item = f32>> SpectrumTap<S> {
+    fn end_frame(&mut self) {
+        self.history[self.write] = self.frame_sum / self.channels as f32;
+        self.write = (self.write + 1) % FFT_SIZE;
+        self.frame_sum = 0.0;
+        self.frames += 1;
+        if self.frames < self.hop {
+            return;
+        }
+        let mono = [&self.history[self.write..], &self.history[..self.write]].concat();
+        // Never wait on the analysis: if it is still busy, skip this hop.
+        let _ = self.spectrum.tx.try_send(Snapshot {
+            sample_rate: self.sample_rate,
+            mono,
+            sum_squares: self.sum_squares.clone(),
+            peaks: self.peaks.clone(),
+            frames: self.frames,
+        });
+        self.start_hop();
+    }
+
+    fn start_hop(&mut self) {
+        self.sum_squares.fill(0.0);
+        self.peaks.fill(0.0);
+        self.frames = 0;
+        self.active = self.spectrum.enabled.load(Ordering::Relaxed);
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for SpectrumTap<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.inner.next()?;
+        let ch = self.channel_idx;
+        self.channel_idx = (ch + 1) % self.channels;
+        if ch == 0 && self.frames == 0 && !self.active {
+            self.active = self.spectrum.enabled.load(Ordering::Relaxed);
+        }
+        if self.active {
+            self.frame_sum += sample;
+            self.sum_squares[ch] += sample * sample;
+            self.peaks[ch] = self.peaks[ch].max(sample.abs());
+            if self.channel_idx == 0 {
+                self.end_frame();
+            }
+        }
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for SpectrumTap<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.history.fill(0.0);
+        self.channel_idx = 0;
+        self.frame_sum = 0.0;
+        self.start_hop();
+        Ok(())
+    }
+}
+
+fn db(amplitude: f32) -> f32 {
+    (20.0 * amplitude.max(1e-9).log10()).max(FLOOR_DB)
+}
+
+fn hann() -> Vec<f32> {
+    (0..FFT_SIZE)
+        .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos())
+        .collect()
+}
+
+fn analyze(app: tauri::AppHandle, rx: Receiver<Snapshot>) {
+    let window = hann();
+    let interval = Duration::from_secs(1) / UPDATES_PER_SEC;
+    let mut last_emit = Instant::now() - interval;
+    for snap in rx {
+        // Faster-than-1x playback delivers hops faster than we emit.
+        if last_emit.elapsed() < interval {
+            continue;
+        }
+        last_emit = Instant::now();
+        let frames = snap.frames.max(1) as f32;
+        let _ = app.emit(
+            "audio-spectrum",
+            SpectrumPayload {
+                bands: bands(&snap.mono, &window, snap.sample_rate),
+                rms: snap.sum_squares.iter().map(|s| db((s / frames).sqrt())).collect(),
+                peak: snap.peaks.iter().map(|&p| db(p)).collect(),
+            },
+        );
+    }
+}
+
+/// Hann-windowed spectrum of `samples`, folded into log-spaced bands that
+/// each report their strongest bin. A full-scale sine reads 0 dB.
+fn bands(samples: &[f32], window: &[f32], sample_rate: u32) -> Vec<f32> {
+    let mut re: Vec<f32> = samples.iter().zip(window).map(|(s, w)| s * w).collect();
+    let mut im = vec![0.0; FFT_SIZE];
+    fft(&mut re, &mut im);
+    // The window halves a sine's amplitude, the transform adds N/2.
+    let scale = 4.0 / FFT_SIZE as f32;
+    let bin_hz = sample_rate as f32 / FFT_SIZE as f32;
+    let top = HIGHEST_HZ.min(sample_rate as f32 / 2.0);
+    let step = (top / LOWEST_HZ).powf(1.0 / BAND_COUNT as f32);
+    (0..BAND_COUNT)
+        .map(|b| {
+            let lo = LOWEST_HZ * step.powi(b as i32);
+            let hi = lo * step;
+            // Low bands are narrower than a bin; they take the nearest one.
+            let first = ((lo / bin_hz).round() as usize).min(FFT_SIZE / 2);
+            let last = ((hi / bin_hz).round() as usize).clamp(first, FFT_SIZE / 2);
+            let strongest = (first..=last)
+                .map(|k| (re[k] * re[k] + im[k] * im[k]).sqrt())
+                .fold(0.0, f32::max);
+            db(strongest * scale)
+        })
+        .collect()
+}
+
+/// In-place radix-2 FFT; the length must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * std::f32::consts::PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let tr = re[b] * cos - im[b] * sin;
+                let ti = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_scale_sine_peaks_at_0_db_in_its_band() {
+        let rate = 48_000;
+        let freq = 1000.0;
+        let samples: Vec<f32> = (0..FFT_SIZE)
+            .map(|i| (2.0 * std::f32::consts::PI * freq * i as f32 / rate as f32).sin())
+            .collect();
+        let out = bands(&samples, &hann(), rate);
+        let (loudest, level) = out
+            .iter()
+            .enumerate()
+            .fold((0, FLOOR_DB), |best, (i, &v)| if v > best.1 { (i, v) } else { best });
+        assert!(level.abs() < 1.0, "{level}");
+        let step = (HIGHEST_HZ / LOWEST_HZ).powf(1.0 / BAND_COUNT as f32);
+        let centre = LOWEST_HZ * step.powf(loudest as f32 + 0.5);
+        assert!((centre / freq).log2().abs() < 0.25, "{centre}");
+    }
+}
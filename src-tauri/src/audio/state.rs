@@ -9,6 +9,8 @@ pub enum PlaybackState {
     Playing,
     Paused,
     Stopped,
+    /// The output device went away; playback resumes once one is back.
+    Reconnecting,
     Error(String),
 }
 
@@ -21,6 +23,7 @@ impl std::fmt::Display for PlaybackState {
             Self::Playing => write!(f, "playing"),
             Self::Paused => write!(f, "paused"),
             Self::Stopped => write!(f, "stopped"),
+            Self::Reconnecting => write!(f, "reconnecting"),
             Self::Error(msg) => write!(f, "error: {msg}"),
         }
     }
@@ -0,0 +1,241 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+/// Bauer crossfeed as tuned by bs2b's default preset: below the cut the
+/// opposite channel is fed in, `CROSSFEED_DB` down.
+const CROSSFEED_CUT_HZ: f64 = 700.0;
+const CROSSFEED_DB: f64 = 4.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct StereoSettings {
+    /// -1 is left only, 1 right only.
+    pub balance: f32,
+    pub mono: bool,
+    pub swap: bool,
+    pub crossfeed: bool,
+}
+
+impl StereoSettings {
+    fn is_neutral(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Filter coefficients of the crossfeed: a low pass for the signal fed
+/// across, and a high shelf that keeps the direct side's level.
+struct Crossfeed {
+    a0_lo: f64,
+    b1_lo: f64,
+    a0_hi: f64,
+    a1_hi: f64,
+    b1_hi: f64,
+    /// Brings centred lows back to unity after the two paths add up.
+    gain: f64,
+}
+
+impl Crossfeed {
+    fn new(sample_rate: u32) -> Self {
+        let gb_lo = CROSSFEED_DB * -5.0 / 6.0 - 3.0;
+        let gb_hi = CROSSFEED_DB / 6.0 - 3.0;
+        let g_lo = 10f64.powf(gb_lo / 20.0);
+        let g_hi = 1.0 - 10f64.powf(gb_hi / 20.0);
+        let fc_hi = CROSSFEED_CUT_HZ * 2f64.powf((gb_lo - 20.0 * g_hi.log10()) / 12.0);
+        let sr = sample_rate as f64;
+        let x_lo = (-2.0 * std::f64::consts::PI * CROSSFEED_CUT_HZ / sr).exp();
+        let x_hi = (-2.0 * std::f64::consts::PI * fc_hi / sr).exp();
+        Self {
+            a0_lo: g_lo * (1.0 - x_lo),
+            b1_lo: x_lo,
+            a0_hi: 1.0 - g_hi * (1.0 - x_hi),
+            a1_hi: -x_hi,
+            b1_hi: x_hi,
+            gain: 1.0 / (1.0 - g_hi + g_lo),
+        }
+    }
+}
+
+/// Balance, mono downmix, channel swap and crossfeed for stereo sources.
+/// Anything that isn't two channels passes through.
+pub struct StereoSource<S: Source<Item = f32>> {
+    inner: S,
+    settings: Arc<RwLock<StereoSettings>>,
+    crossfeed: Crossfeed,
+    /// Per channel: low-passed, high-shelved and previous input.
+    lo: [f64; 2],
+    hi: [f64; 2],
+    prev: [f64; 2],
+    /// Right sample of the frame being played, once the left has gone.
+    pending: Option<f32>,
+    /// Set when the left sample went out untouched, so the next one is its
+    /// right and passes too; settings only change between frames.
+    passing: bool,
+}
+
+impl<S: Source<Item = f32>> StereoSource<S> {
+    pub fn new(inner: S, settings: Arc<RwLock<StereoSettings>>) -> Self {
+        let crossfeed = Crossfeed::new(inner.sample_rate());
+        Self {
+            inner,
+            settings,
+            crossfeed,
+            lo: [0.0; 2],
+            hi: [0.0; 2],
+            prev: [0.0; 2],
+            pending: None,
+            passing: false,
+        }
+    }
+
+    fn process(&mut self, s: &StereoSettings, mut l: f32, mut r: f32) -> (f32, f32) {
+        if s.swap {
+            std::mem::swap(&mut l, &mut r);
+        }
+        if s.crossfeed {
+            let c = &self.crossfeed;
+            let input = [l as f64, r as f64];
+            for (ch, &x) in input.iter().enumerate() {
+                self.lo[ch] = c.a0_lo * x + c.b1_lo * self.lo[ch];
+                self.hi[ch] = c.a0_hi * x + c.a1_hi * self.prev[ch] + c.b1_hi * self.hi[ch];
+            }
+            self.prev = input;
+            l = ((self.hi[0] + self.lo[1]) * c.gain) as f32;
+            r = ((self.hi[1] + self.lo[0]) * c.gain) as f32;
+        }
+        if s.mono {
+            let m = (l + r) * 0.5;
+            (l, r) = (m, m);
+        }
+        let balance = s.balance.clamp(-1.0, 1.0);
+        (l * (1.0 - balance).min(1.0), r * (1.0 + balance).min(1.0))
+    }
+
+    fn reset(&mut self) {
+        self.lo = [0.0; 2];
+        self.hi = [0.0; 2];
+        self.prev = [0.0; 2];
+        self.pending = None;
+        self.passing = false;
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for StereoSource<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if let Some(r) = self.pending.take() {
+            return Some(r);
+        }
+        let l = self.inner.next()?;
+        if self.inner.channels() != 2 {
+            return Some(l);
+        }
+        if self.passing {
+            self.passing = false;
+            return Some(l);
+        }
+        let s = *self.settings.read().unwrap();
+        if s.is_neutral() {
+            // Keep the crossfeed from starting on stale memory.
+            self.reset();
+            self.passing = true;
+            return Some(l);
+        }
+        let Some(r) = self.inner.next() else {
+            return Some(l);
+        };
+        let (l, r) = self.process(&s, l, r);
+        self.pending = Some(r);
+        Some(l)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for StereoSource<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.reset();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn run(settings: StereoSettings, frames: &[(f32, f32)]) -> Vec<(f32, f32)> {
+        let samples = frames.iter().flat_map(|&(l, r)| [l, r]).collect::<Vec<_>>();
+        let out: Vec<f32> = StereoSource::new(
+            SamplesBuffer::new(2, 44_100, samples),
+            Arc::new(RwLock::new(settings)),
+        )
+        .collect();
+        out.chunks(2).map(|f| (f[0], f[1])).collect()
+    }
+
+    #[test]
+    fn swap_mono_and_balance() {
+        let frame = [(0.8, 0.2)];
+        assert_eq!(run(StereoSettings::default(), &frame), [(0.8, 0.2)]);
+        assert_eq!(run(StereoSettings { swap: true, ..Default::default() }, &frame), [(0.2, 0.8)]);
+        assert_eq!(run(StereoSettings { mono: true, ..Default::default() }, &frame), [(0.5, 0.5)]);
+        let right = run(StereoSettings { balance: 0.5, ..Default::default() }, &frame);
+        assert_eq!(right, [(0.4, 0.2)]);
+    }
+
+    #[test]
+    fn settings_change_between_frames() {
+        let settings = Arc::new(RwLock::new(StereoSettings::default()));
+        let samples = vec![0.8, 0.2, 0.8, 0.2];
+        let mut stereo = StereoSource::new(SamplesBuffer::new(2, 44_100, samples), settings.clone());
+        assert_eq!(stereo.next(), Some(0.8));
+        settings.write().unwrap().swap = true;
+        assert_eq!(stereo.collect::<Vec<_>>(), [0.2, 0.2, 0.8]);
+
+        let settings = Arc::new(RwLock::new(StereoSettings { swap: true, ..Default::default() }));
+        let samples = vec![0.8, 0.2, 0.8, 0.2];
+        let mut stereo = StereoSource::new(SamplesBuffer::new(2, 44_100, samples), settings.clone());
+        assert_eq!(stereo.next(), Some(0.2));
+        *settings.write().unwrap() = StereoSettings::default();
+        assert_eq!(stereo.collect::<Vec<_>>(), [0.8, 0.8, 0.2]);
+    }
+
+    #[test]
+    fn crossfeed_leaks_lows_across_and_keeps_the_centre() {
+        let settings = StereoSettings { crossfeed: true, ..Default::default() };
+        // Hard-left 100 Hz tone.
+        let tone = |i: usize| (2.0 * std::f32::consts::PI * 100.0 * i as f32 / 44_100.0).sin() * 0.5;
+        let left: Vec<(f32, f32)> = (0..44_100).map(|i| (tone(i), 0.0)).collect();
+        let out = run(settings, &left);
+        let peak = |f: fn(&(f32, f32)) -> f32| out[22_050..].iter().map(f).fold(0.0f32, |m, v| m.max(v.abs()));
+        let (l, r) = (peak(|f| f.0), peak(|f| f.1));
+        // About 4.5 dB between the sides at low frequencies.
+        let diff = 20.0 * (l / r).log10();
+        assert!((diff - 4.5).abs() < 1.5, "{diff}");
+
+        // Centred audio comes out at much the same level.
+        let centre: Vec<(f32, f32)> = (0..44_100).map(|i| (tone(i), tone(i))).collect();
+        let out = run(settings, &centre);
+        let level = out[22_050..].iter().fold(0.0f32, |m, f| m.max(f.0.abs()));
+        assert!((20.0 * (level / 0.5).log10()).abs() < 1.0, "{level}");
+    }
+}
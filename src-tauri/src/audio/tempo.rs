@@ -0,0 +1,348 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use rodio::source::SeekError;
+use rodio::Source;
+
+/// Length of the overlapping segments the stretch is built from.
+const SEGMENT_MS: u32 = 60;
+/// How far a segment may move from its nominal spot to line up with the
+/// audio before it.
+const SEARCH_MS: u32 = 12;
+/// Only every n-th frame is compared when lining segments up.
+const CORRELATION_STRIDE: usize = 4;
+
+/// How a speed other than 1x is reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpeedMode {
+    /// Play samples faster or slower; pitch follows the speed.
+    #[default]
+    Resample,
+    /// Change tempo only; pitch stays put.
+    Stretch,
+}
+
+impl SpeedMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "resample" => Some(Self::Resample),
+            "stretch" => Some(Self::Stretch),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TempoSettings {
+    pub speed: f32,
+    pub mode: SpeedMode,
+    /// Pitch shift on top of whatever the speed does, in semitones.
+    pub semitones: f32,
+}
+
+impl Default for TempoSettings {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            mode: SpeedMode::default(),
+            semitones: 0.0,
+        }
+    }
+}
+
+impl TempoSettings {
+    /// Rate the sink plays samples at. Resampling moves pitch and tempo
+    /// together, so every pitch change is done here.
+    pub fn sink_rate(&self) -> f32 {
+        let pitch = 2f32.powf(self.semitones / 12.0);
+        match self.mode {
+            SpeedMode::Resample => self.speed * pitch,
+            SpeedMode::Stretch => pitch,
+        }
+    }
+
+    /// Tempo change left to the time stretch once the sink has resampled.
+    pub fn stretch(&self) -> f32 {
+        self.speed / self.sink_rate()
+    }
+}
+
+/// WSOLA time stretch: changes tempo without touching pitch by laying
+/// Hann-windowed segments of the input at a fixed hop, each one taken from
+/// near its nominal position where it best continues the previous one.
+pub struct TimeStretch<S: Source<Item = f32>> {
+    inner: S,
+    settings: Arc<RwLock<TempoSettings>>,
+    channels: usize,
+    sample_rate: u32,
+    /// Half a segment, in frames; also the output hop.
+    hop: usize,
+    search: usize,
+    window: Vec<f32>,
+    /// Interleaved input not yet consumed.
+    input: Vec<f32>,
+    /// Where the analysis would be without alignment, in frames of `input`.
+    nominal: f64,
+    /// Where the previous segment's audio carries on, None before the
+    /// first segment.
+    continuation: Option<usize>,
+    /// Windowed second half of the previous segment, still to be added.
+    tail: Vec<f32>,
+    block: Vec<f32>,
+    block_pos: usize,
+    /// Input frames per output frame.
+    ratio: f32,
+    exhausted: bool,
+}
+
+impl<S: Source<Item = f32>> TimeStretch<S> {
+    pub fn new(inner: S, settings: Arc<RwLock<TempoSettings>>) -> Self {
+        let channels = inner.channels().max(1) as usize;
+        let sample_rate = inner.sample_rate();
+        let hop = (sample_rate * SEGMENT_MS / 2000).max(1) as usize;
+        // Periodic Hann: halves a hop apart sum to exactly one.
+        let window = (0..2 * hop)
+            .map(|i| 0.5 - 0.5 * (std::f32::consts::PI * i as f32 / hop as f32).cos())
+            .collect();
+        let ratio = settings.read().unwrap().stretch();
+        Self {
+            inner,
+            settings,
+            channels,
+            sample_rate,
+            hop,
+            search: (sample_rate * SEARCH_MS / 1000) as usize,
+            window,
+            input: Vec::new(),
+            nominal: 0.0,
+            continuation: None,
+            tail: vec![0.0; hop * channels],
+            block: Vec::new(),
+            block_pos: 0,
+            ratio,
+            exhausted: false,
+        }
+    }
+
+    fn frames(&self) -> usize {
+        self.input.len() / self.channels
+    }
+
+    /// Whether samples pass straight through. Only decided between
+    /// segments with nothing buffered, so switching never drops audio.
+    fn bypassed(&self) -> bool {
+        self.input.is_empty() && self.continuation.is_none() && (self.ratio - 1.0).abs() < 1e-3
+    }
+
+    /// Buffer input up to `frames` frames, or as much as is left.
+    fn fill(&mut self, frames: usize) {
+        while !self.exhausted && self.frames() < frames {
+            for _ in 0..self.channels {
+                match self.inner.next() {
+                    Some(s) => self.input.push(s),
+                    None => {
+                        self.exhausted = true;
+                        break;
+                    }
+                }
+            }
+        }
+        // A partial last frame would skew every channel after it.
+        let whole = self.frames() * self.channels;
+        self.input.truncate(whole);
+    }
+
+    /// Sum of the channels at `frame`, zero past the end of the input.
+    fn mono(&self, frame: usize) -> f32 {
+        let at = frame * self.channels;
+        self.input.get(at..at + self.channels).map_or(0.0, |f| f.iter().sum())
+    }
+
+    /// Segment start near `nominal` whose opening best matches what
+    /// naturally followed the previous segment.
+    fn best_start(&self, continuation: usize) -> usize {
+        let centre = self.nominal.round() as usize;
+        let lo = centre.saturating_sub(self.search);
+        let hi = centre + self.search;
+        let mut best = (centre, f32::MIN);
+        for start in lo..=hi {
+            let (mut corr, mut energy) = (0.0, 0.0);
+            for i in (0..self.hop).step_by(CORRELATION_STRIDE) {
+                let x = self.mono(start + i);
+                corr += x * self.mono(continuation + i);
+                energy += x * x;
+            }
+            let score = corr / (energy + 1e-9).sqrt();
+            if score > best.1 {
+                best = (start, score);
+            }
+        }
+        best.0
+    }
+
+    /// Produce the next hop of output. False once the input is used up.
+    fn next_block(&mut self) -> bool {
+        let (hop, ch) = (self.hop, self.channels);
+        let start = match self.continuation {
+            None => self.nominal as usize,
+            // At 1x the natural continuation rebuilds the input exactly.
+            Some(next) if (self.ratio - 1.0).abs() < 1e-3 => next,
+            Some(next) => {
+                self.fill(self.nominal as usize + self.search + 2 * hop);
+                self.best_start(next)
+            }
+        };
+        self.fill(start + 2 * hop);
+        if self.exhausted && start >= self.frames() {
+            return false;
+        }
+
+        self.block.clear();
+        for i in 0..hop {
+            for c in 0..ch {
+                let x = self.input.get((start + i) * ch + c).copied().unwrap_or(0.0);
+                let out = match self.continuation {
+                    // Nothing to overlap with yet: start at full level.
+                    None => x,
+                    Some(_) => self.tail[i * ch + c] + x * self.window[i],
+                };
+                self.block.push(out);
+            }
+        }
+        for i in 0..hop {
+            for c in 0..ch {
+                let x = self.input.get((start + hop + i) * ch + c).copied().unwrap_or(0.0);
+                self.tail[i * ch + c] = x * self.window[hop + i];
+            }
+        }
+        self.block_pos = 0;
+
+        let next = start + hop;
+        self.nominal = if (self.ratio - 1.0).abs() < 1e-3 {
+            next as f64
+        } else {
+            self.nominal + hop as f64 * self.ratio as f64
+        };
+
+        // Drop input that no later segment can reach.
+        let drop = next.min((self.nominal as usize).saturating_sub(self.search)).min(self.frames());
+        self.input.drain(..drop * ch);
+        self.nominal -= drop as f64;
+        self.continuation = Some(next - drop);
+        true
+    }
+
+    fn reset(&mut self) {
+        self.input.clear();
+        self.nominal = 0.0;
+        self.continuation = None;
+        self.tail.fill(0.0);
+        self.block.clear();
+        self.block_pos = 0;
+        self.exhausted = false;
+    }
+}
+
+impl<S: Source<Item = f32>> Iterator for TimeStretch<S> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.block_pos >= self.block.len() {
+            self.ratio = self.settings.read().unwrap().stretch();
+            if self.bypassed() {
+                // A frame at a time, so a switch never lands mid-frame.
+                self.block.clear();
+                self.block.extend(self.inner.by_ref().take(self.channels));
+                self.block_pos = 0;
+                if self.block.is_empty() {
+                    return None;
+                }
+            } else if !self.next_block() {
+                return None;
+            }
+        }
+        let sample = self.block[self.block_pos];
+        self.block_pos += 1;
+        Some(sample)
+    }
+}
+
+impl<S: Source<Item = f32>> Source for TimeStretch<S> {
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.bypassed() {
+            self.inner.current_frame_len()
+        } else {
+            None
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.reset();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    fn sine(rate: u32, secs: f32) -> SamplesBuffer<f32> {
+        let samples = (0..(rate as f32 * secs) as usize)
+            .flat_map(|i| {
+                let v = (2.0 * std::f32::consts::PI * 440.0 * i as f32 / rate as f32).sin() * 0.5;
+                [v, v]
+            })
+            .collect::<Vec<_>>();
+        SamplesBuffer::new(2, rate, samples)
+    }
+
+    fn stretched(settings: TempoSettings, secs: f32) -> Vec<f32> {
+        TimeStretch::new(sine(44_100, secs), Arc::new(RwLock::new(settings))).collect()
+    }
+
+    #[test]
+    fn pitch_is_resampled_and_tempo_stretched() {
+        let up = TempoSettings { speed: 1.5, mode: SpeedMode::Stretch, semitones: 12.0 };
+        assert!((up.sink_rate() - 2.0).abs() < 1e-6);
+        assert!((up.stretch() - 0.75).abs() < 1e-6);
+        let resample = TempoSettings { speed: 1.5, mode: SpeedMode::Resample, semitones: 0.0 };
+        assert!((resample.sink_rate() - 1.5).abs() < 1e-6);
+        assert!((resample.stretch() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stretch_changes_length_but_not_pitch() {
+        let input = 2.0 * 44_100.0 * 2.0;
+        for speed in [0.5, 2.0] {
+            let out = stretched(TempoSettings { speed, mode: SpeedMode::Stretch, semitones: 0.0 }, 2.0);
+            let expected = input / speed;
+            assert!((out.len() as f32 - expected).abs() < expected * 0.05, "{speed}: {}", out.len());
+            assert_eq!(out.len() % 2, 0);
+            // Pitch holds: a 440 Hz tone still crosses zero 880 times a second.
+            let left = out.iter().step_by(2).collect::<Vec<_>>();
+            let crossings = left.windows(2).filter(|w| (*w[0] < 0.0) != (*w[1] < 0.0)).count();
+            let per_sec = crossings as f32 / (left.len() as f32 / 44_100.0);
+            assert!((per_sec - 880.0).abs() < 880.0 * 0.05, "{speed}: {per_sec}");
+        }
+    }
+
+    #[test]
+    fn unity_tempo_passes_samples_through() {
+        let out = stretched(TempoSettings::default(), 0.5);
+        assert!(out.iter().eq(sine(44_100, 0.5).collect::<Vec<_>>().iter()));
+    }
+}
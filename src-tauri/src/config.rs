@@ -2,18 +2,72 @@ use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::sync::RwLock;
 
+use crate::audio::equalizer::EqBand;
+use crate::db::SearchCache;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct AppConfig {
     pub volume: f64,
     pub eq_enabled: bool,
+    pub eq_bands: Vec<EqBand>,
+    pub eq_preamp_db: f64,
+    pub eq_auto_preamp: bool,
+    /// Name of the preset last applied; empty once the bands are edited.
+    pub eq_preset: String,
+    /// Gains of the old fixed ten-band EQ; read once, then kept as `eq_bands`.
+    #[serde(skip_serializing)]
     pub eq_gains: Vec<f64>,
     pub notifications_enabled: bool,
     pub discord_rpc_enabled: bool,
+    /// Queue the frontend used to keep here; read once, then moved into the
+    /// database.
+    #[serde(skip_serializing)]
     pub saved_queue: Vec<String>,
+    #[serde(skip_serializing)]
     pub saved_queue_index: i64,
     pub repeat_mode: String,
     pub playback_speed: f64,
+    /// "resample" or "stretch".
+    pub speed_mode: String,
+    pub pitch_semitones: f64,
+    pub crossfade_secs: f64,
+    pub crossfade_curve: String,
+    /// "off", "track" or "album".
+    pub normalization_mode: String,
+    pub normalization_target_lufs: f64,
+    /// Gain applied to tracks that have not been analysed yet.
+    pub normalization_fallback_db: f64,
+    pub limiter_enabled: bool,
+    pub limiter_ceiling_db: f64,
+    /// Compress quiet and loud passages together for low-volume listening.
+    pub night_mode: bool,
+    /// -1 (left only) to 1 (right only).
+    pub balance: f64,
+    pub mono: bool,
+    pub swap_channels: bool,
+    pub crossfeed: bool,
+    /// Skip silence at the start and end of tracks.
+    pub trim_silence: bool,
+    /// Peak level below which audio counts as silence, in dBFS.
+    pub silence_threshold_db: f64,
+    /// Tracks at least this long reopen where they were left; 0 turns it off.
+    pub resume_min_minutes: f64,
+    /// Skip segments of the categories below while playing.
+    pub sponsorblock_enabled: bool,
+    /// SponsorBlock categories to skip, e.g. "music_offtopic" or "sponsor".
+    pub sponsorblock_categories: Vec<String>,
+    /// Base URL of the SponsorBlock-compatible server to ask for segments.
+    pub sponsorblock_api: String,
+    /// Cut the categories out of offline downloads (yt-dlp needs ffmpeg).
+    pub sponsorblock_cut_downloads: bool,
+    /// Seconds the sleep timer fades out over before it stops playback.
+    pub sleep_fade_secs: f64,
+    /// Output device name; empty for the system default.
+    pub output_device: String,
+    /// Where audio goes: "device", "null", "wav:<path>" or "fifo:<path>".
+    /// `SUNDER_OUTPUT` overrides it.
+    pub output_backend: String,
 }
 
 impl Default for AppConfig {
@@ -21,13 +75,41 @@ impl Default for AppConfig {
         Self {
             volume: 0.8,
             eq_enabled: false,
-            eq_gains: vec![0.0; 10],
+            eq_bands: EqBand::graphic(&[]),
+            eq_preamp_db: 0.0,
+            eq_auto_preamp: false,
+            eq_preset: "Flat".into(),
+            eq_gains: Vec::new(),
             notifications_enabled: true,
             discord_rpc_enabled: false,
             saved_queue: Vec::new(),
             saved_queue_index: -1,
             repeat_mode: "off".into(),
             playback_speed: 1.0,
+            speed_mode: "resample".into(),
+            pitch_semitones: 0.0,
+            crossfade_secs: 0.0,
+            crossfade_curve: "equal_power".into(),
+            normalization_mode: "off".into(),
+            normalization_target_lufs: -14.0,
+            normalization_fallback_db: -6.0,
+            limiter_enabled: true,
+            limiter_ceiling_db: -1.0,
+            night_mode: false,
+            balance: 0.0,
+            mono: false,
+            swap_channels: false,
+            crossfeed: false,
+            trim_silence: false,
+            silence_threshold_db: -50.0,
+            resume_min_minutes: 20.0,
+            sponsorblock_enabled: false,
+            sponsorblock_categories: vec!["music_offtopic".into()],
+            sponsorblock_api: "https://sponsor.ajay.app".into(),
+            sponsorblock_cut_downloads: false,
+            sleep_fade_secs: 30.0,
+            output_device: String::new(),
+            output_backend: "device".into(),
         }
     }
 }
@@ -40,7 +122,7 @@ pub struct ConfigManager {
 impl ConfigManager {
     pub fn new(data_dir: &std::path::Path) -> Self {
         let path = data_dir.join("config.json");
-        let config = if path.exists() {
+        let mut config: AppConfig = if path.exists() {
             match std::fs::read_to_string(&path) {
                 Ok(s) => serde_json::from_str(&s).unwrap_or_default(),
                 Err(_) => AppConfig::default(),
@@ -48,6 +130,10 @@ impl ConfigManager {
         } else {
             AppConfig::default()
         };
+        if !config.eq_gains.is_empty() {
+            let gains: Vec<f32> = config.eq_gains.drain(..).map(|g| g as f32).collect();
+            config.eq_bands = EqBand::graphic(&gains);
+        }
 
         Self {
             config: RwLock::new(config),
@@ -55,6 +141,37 @@ impl ConfigManager {
         }
     }
 
+    /// Move a queue saved in the config by an older version into `cache`,
+    /// keeping the tracks it still knows.
+    pub fn migrate_saved_queue(&self, cache: &SearchCache) {
+        let (ids, index) = {
+            let config = self.config.read().unwrap();
+            if config.saved_queue.is_empty() {
+                return;
+            }
+            (config.saved_queue.clone(), config.saved_queue_index)
+        };
+        let mut tracks = Vec::new();
+        let mut current = None;
+        for (i, id) in ids.iter().enumerate() {
+            if let Ok(Some(track)) = cache.get_track_by_id(id) {
+                if usize::try_from(index) == Ok(i) {
+                    current = Some(tracks.len());
+                }
+                tracks.push(track);
+            }
+        }
+        if let Err(e) = cache.save_queue(&tracks, current) {
+            eprintln!("[sunder] failed to move the saved queue: {e}");
+            return;
+        }
+        let mut config = self.config.write().unwrap();
+        config.saved_queue.clear();
+        config.saved_queue_index = -1;
+        drop(config);
+        self.save();
+    }
+
     pub fn get(&self) -> AppConfig {
         self.config.read().unwrap().clone()
     }
@@ -3,8 +3,11 @@ use std::sync::Mutex;
 
 use rusqlite::{params, Connection};
 
+use crate::audio::eq_presets::{self, EqPreset};
+use crate::audio::loudness::Loudness;
+use crate::audio::silence::AudibleSpan;
 use crate::error::AppError;
-use crate::models::{Playlist, Track};
+use crate::models::{Alarm, Bookmark, Chapter, CuePoints, LocalTime, Playlist, ResumePoint, Segment, Track};
 
 #[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct CachedLyrics {
@@ -92,6 +95,12 @@ impl SearchCache {
              CREATE TABLE IF NOT EXISTS lyric_offsets (
                  track_id  TEXT PRIMARY KEY,
                  offset_ms INTEGER NOT NULL DEFAULT 0
+             );
+
+             CREATE TABLE IF NOT EXISTS cue_points (
+                 track_id TEXT PRIMARY KEY,
+                 start_ms INTEGER,
+                 end_ms   INTEGER
              );",
         )?;
 
@@ -111,9 +120,91 @@ impl SearchCache {
                  size       INTEGER NOT NULL DEFAULT 0,
                  downloaded TEXT NOT NULL DEFAULT (datetime('now'))
              );
-             CREATE INDEX IF NOT EXISTS idx_downloads_time ON downloads(downloaded DESC);",
+             CREATE INDEX IF NOT EXISTS idx_downloads_time ON downloads(downloaded DESC);
+
+             CREATE TABLE IF NOT EXISTS loudness (
+                 track_id        TEXT PRIMARY KEY REFERENCES tracks(id) ON DELETE CASCADE,
+                 integrated_lufs REAL NOT NULL,
+                 true_peak_dbtp  REAL NOT NULL,
+                 analyzed        TEXT NOT NULL DEFAULT (datetime('now'))
+             );
+
+             CREATE TABLE IF NOT EXISTS silence (
+                 track_id     TEXT PRIMARY KEY REFERENCES tracks(id) ON DELETE CASCADE,
+                 threshold_db REAL NOT NULL,
+                 start_ms     INTEGER NOT NULL,
+                 end_ms       INTEGER NOT NULL,
+                 analyzed     TEXT NOT NULL DEFAULT (datetime('now'))
+             );
+
+             CREATE TABLE IF NOT EXISTS resume_points (
+                 track_id    TEXT PRIMARY KEY REFERENCES tracks(id) ON DELETE CASCADE,
+                 position_ms INTEGER NOT NULL,
+                 speed       REAL NOT NULL DEFAULT 1,
+                 saved       TEXT NOT NULL DEFAULT (datetime('now'))
+             );
+
+             CREATE TABLE IF NOT EXISTS bookmarks (
+                 id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                 track_id    TEXT NOT NULL REFERENCES tracks(id) ON DELETE CASCADE,
+                 name        TEXT NOT NULL,
+                 position_ms INTEGER NOT NULL,
+                 created     TEXT NOT NULL DEFAULT (datetime('now'))
+             );
+             CREATE INDEX IF NOT EXISTS idx_bookmarks_track ON bookmarks(track_id, position_ms);
+
+             CREATE TABLE IF NOT EXISTS chapters (
+                 track_id TEXT PRIMARY KEY REFERENCES tracks(id) ON DELETE CASCADE,
+                 chapters TEXT NOT NULL,
+                 fetched  TEXT NOT NULL DEFAULT (datetime('now'))
+             );
+
+             CREATE TABLE IF NOT EXISTS segments (
+                 track_id TEXT PRIMARY KEY REFERENCES tracks(id) ON DELETE CASCADE,
+                 segments TEXT NOT NULL,
+                 fetched  TEXT NOT NULL DEFAULT (datetime('now')),
+                 failed   INTEGER NOT NULL DEFAULT 0
+             );
+
+             CREATE TABLE IF NOT EXISTS alarms (
+                 id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                 label       TEXT NOT NULL DEFAULT '',
+                 playlist_id INTEGER NOT NULL REFERENCES playlists(id) ON DELETE CASCADE,
+                 hour        INTEGER NOT NULL,
+                 minute      INTEGER NOT NULL,
+                 weekdays    INTEGER NOT NULL DEFAULT 0,
+                 ramp_secs   INTEGER NOT NULL DEFAULT 0,
+                 volume      REAL NOT NULL DEFAULT 0.8,
+                 enabled     INTEGER NOT NULL DEFAULT 1,
+                 last_fired  TEXT
+             );
+
+             CREATE TABLE IF NOT EXISTS play_queue (
+                 position  INTEGER PRIMARY KEY,
+                 track_id  TEXT NOT NULL,
+                 title     TEXT NOT NULL,
+                 artist    TEXT NOT NULL,
+                 thumbnail TEXT NOT NULL DEFAULT '',
+                 duration  REAL NOT NULL DEFAULT 0,
+                 album     TEXT NOT NULL DEFAULT '',
+                 current   INTEGER NOT NULL DEFAULT 0
+             );
+
+             CREATE TABLE IF NOT EXISTS eq_presets (
+                 name      TEXT PRIMARY KEY,
+                 preamp_db REAL NOT NULL DEFAULT 0,
+                 bands     TEXT NOT NULL,
+                 builtin   INTEGER NOT NULL DEFAULT 0
+             );",
         )?;
 
+        for preset in eq_presets::builtin_presets() {
+            conn.execute(
+                "INSERT OR IGNORE INTO eq_presets (name, preamp_db, bands, builtin) VALUES (?1, ?2, ?3, 1)",
+                params![preset.name, preset.preamp_db, serde_json::to_string(&preset.bands).unwrap_or_default()],
+            )?;
+        }
+
         // Migration: add thumbnail to playlists if missing
         if let Err(e) = conn.execute("ALTER TABLE playlists ADD COLUMN thumbnail TEXT NOT NULL DEFAULT ''", []) {
             let msg = e.to_string();
@@ -130,26 +221,70 @@ impl SearchCache {
             }
         }
 
+        // Migration: add album to tracks if missing
+        if let Err(e) = conn.execute("ALTER TABLE tracks ADD COLUMN album TEXT NOT NULL DEFAULT ''", []) {
+            let msg = e.to_string();
+            if !msg.contains("duplicate column name") {
+                eprintln!("[sunder] tracks album migration failed: {e}");
+            }
+        }
+
+        // Migration: mark durations measured from the decoded audio, which
+        // metadata refreshes must not overwrite
+        if let Err(e) = conn.execute("ALTER TABLE tracks ADD COLUMN duration_decoded INTEGER NOT NULL DEFAULT 0", []) {
+            let msg = e.to_string();
+            if !msg.contains("duplicate column name") {
+                eprintln!("[sunder] tracks duration_decoded migration failed: {e}");
+            }
+        }
+
+        // Migration: add codec, bitrate (kbps) and whether SponsorBlock
+        // segments were cut out to downloads if missing
+        for column in [
+            "codec TEXT NOT NULL DEFAULT ''",
+            "bitrate INTEGER NOT NULL DEFAULT 0",
+            "segments_cut INTEGER NOT NULL DEFAULT 0",
+        ] {
+            if let Err(e) = conn.execute(&format!("ALTER TABLE downloads ADD COLUMN {column}"), []) {
+                let msg = e.to_string();
+                if !msg.contains("duplicate column name") {
+                    eprintln!("[sunder] downloads column migration failed: {e}");
+                }
+            }
+        }
+
         Ok(Self { conn: Mutex::new(conn) })
     }
 
     pub fn upsert_tracks(&self, tracks: &[Track]) -> Result<(), AppError> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare_cached(
-            "INSERT INTO tracks (id, title, artist, thumbnail, duration)
-             VALUES (?1, ?2, ?3, ?4, ?5)
+            "INSERT INTO tracks (id, title, artist, thumbnail, duration, album)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
              ON CONFLICT(id) DO UPDATE SET
                  title = excluded.title,
                  artist = excluded.artist,
                  thumbnail = excluded.thumbnail,
-                 duration = excluded.duration",
+                 duration = CASE WHEN tracks.duration_decoded THEN tracks.duration ELSE excluded.duration END,
+                 album = CASE WHEN excluded.album <> '' THEN excluded.album ELSE tracks.album END",
         )?;
         for t in tracks {
-            stmt.execute(params![t.id, t.title, t.artist, t.thumbnail, t.duration_secs])?;
+            stmt.execute(params![t.id, t.title, t.artist, t.thumbnail, t.duration_secs, t.album])?;
         }
         Ok(())
     }
 
+    /// Store the length found by decoding a track, in place of the one its
+    /// metadata gave.
+    pub fn set_track_duration(&self, track_id: &str, duration_secs: f64) -> Result<(), AppError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE tracks SET duration = ?2, duration_decoded = 1 WHERE id = ?1",
+            params![track_id, duration_secs],
+        )?;
+        Ok(())
+    }
+
     pub fn get_lyrics(&self, track_id: &str) -> Result<Option<CachedLyrics>, AppError> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare_cached(
@@ -215,10 +350,305 @@ impl SearchCache {
         Ok(())
     }
 
+    pub fn get_cue_points(&self, track_id: &str) -> Result<CuePoints, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached("SELECT start_ms, end_ms FROM cue_points WHERE track_id = ?1")?;
+        let mut rows = stmt.query(params![track_id])?;
+        if let Some(row) = rows.next()? {
+            Ok(CuePoints {
+                start_ms: row.get::<_, Option<i64>>(0)?.map(|ms| ms as u64),
+                end_ms: row.get::<_, Option<i64>>(1)?.map(|ms| ms as u64),
+            })
+        } else {
+            Ok(CuePoints::default())
+        }
+    }
+
+    /// Store a track's cue points; with both ends open they are forgotten.
+    pub fn set_cue_points(&self, track_id: &str, cues: &CuePoints) -> Result<(), AppError> {
+        let conn = self.conn.lock().unwrap();
+        if *cues == CuePoints::default() {
+            conn.execute("DELETE FROM cue_points WHERE track_id = ?1", params![track_id])?;
+            return Ok(());
+        }
+        conn.execute(
+            "INSERT INTO cue_points (track_id, start_ms, end_ms) VALUES (?1, ?2, ?3)
+             ON CONFLICT(track_id) DO UPDATE SET
+                 start_ms = excluded.start_ms,
+                 end_ms = excluded.end_ms",
+            params![track_id, cues.start_ms.map(|ms| ms as i64), cues.end_ms.map(|ms| ms as i64)],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_resume_point(&self, track_id: &str) -> Result<Option<ResumePoint>, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached("SELECT position_ms, speed FROM resume_points WHERE track_id = ?1")?;
+        let mut rows = stmt.query_map(params![track_id], |row| {
+            Ok(ResumePoint {
+                position_ms: row.get::<_, i64>(0)? as u64,
+                speed: row.get::<_, f64>(1)? as f32,
+            })
+        })?;
+        Ok(rows.next().and_then(|r| r.ok()))
+    }
+
+    pub fn set_resume_point(&self, track_id: &str, point: &ResumePoint) -> Result<(), AppError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO resume_points (track_id, position_ms, speed) VALUES (?1, ?2, ?3)
+             ON CONFLICT(track_id) DO UPDATE SET
+                 position_ms = excluded.position_ms,
+                 speed = excluded.speed,
+                 saved = datetime('now')",
+            params![track_id, point.position_ms as i64, point.speed as f64],
+        )?;
+        Ok(())
+    }
+
+    pub fn clear_resume_point(&self, track_id: &str) -> Result<(), AppError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM resume_points WHERE track_id = ?1", params![track_id])?;
+        Ok(())
+    }
+
+    /// A track's bookmarks in playback order.
+    pub fn list_bookmarks(&self, track_id: &str) -> Result<Vec<Bookmark>, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, track_id, name, position_ms FROM bookmarks
+             WHERE track_id = ?1 ORDER BY position_ms, id",
+        )?;
+        let bookmarks = stmt
+            .query_map(params![track_id], |row| {
+                Ok(Bookmark {
+                    id: row.get(0)?,
+                    track_id: row.get(1)?,
+                    name: row.get(2)?,
+                    position_ms: row.get::<_, i64>(3)? as u64,
+                })
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(bookmarks)
+    }
+
+    pub fn add_bookmark(&self, track_id: &str, name: &str, position_ms: u64) -> Result<Bookmark, AppError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO bookmarks (track_id, name, position_ms) VALUES (?1, ?2, ?3)",
+            params![track_id, name, position_ms as i64],
+        )?;
+        Ok(Bookmark {
+            id: conn.last_insert_rowid(),
+            track_id: track_id.to_string(),
+            name: name.to_string(),
+            position_ms,
+        })
+    }
+
+    pub fn delete_bookmark(&self, id: i64) -> Result<(), AppError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM bookmarks WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    pub fn list_alarms(&self) -> Result<Vec<Alarm>, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, label, playlist_id, hour, minute, weekdays, ramp_secs, volume, enabled
+             FROM alarms ORDER BY hour, minute, id",
+        )?;
+        let alarms = stmt
+            .query_map([], |row| {
+                Ok(Alarm {
+                    id: row.get(0)?,
+                    label: row.get(1)?,
+                    playlist_id: row.get(2)?,
+                    hour: row.get(3)?,
+                    minute: row.get(4)?,
+                    weekdays: row.get(5)?,
+                    ramp_secs: row.get(6)?,
+                    volume: row.get::<_, f64>(7)? as f32,
+                    enabled: row.get(8)?,
+                })
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(alarms)
+    }
+
+    /// Insert `alarm` when its id is 0, otherwise update it. Editing an alarm
+    /// lets it go off again within the same minute.
+    pub fn save_alarm(&self, alarm: &Alarm) -> Result<Alarm, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut saved = alarm.clone();
+        if alarm.id == 0 {
+            conn.execute(
+                "INSERT INTO alarms (label, playlist_id, hour, minute, weekdays, ramp_secs, volume, enabled)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    alarm.label,
+                    alarm.playlist_id,
+                    alarm.hour,
+                    alarm.minute,
+                    alarm.weekdays,
+                    alarm.ramp_secs,
+                    alarm.volume as f64,
+                    alarm.enabled,
+                ],
+            )?;
+            saved.id = conn.last_insert_rowid();
+        } else {
+            let changed = conn.execute(
+                "UPDATE alarms SET label = ?2, playlist_id = ?3, hour = ?4, minute = ?5, weekdays = ?6,
+                 ramp_secs = ?7, volume = ?8, enabled = ?9, last_fired = NULL
+                 WHERE id = ?1",
+                params![
+                    alarm.id,
+                    alarm.label,
+                    alarm.playlist_id,
+                    alarm.hour,
+                    alarm.minute,
+                    alarm.weekdays,
+                    alarm.ramp_secs,
+                    alarm.volume as f64,
+                    alarm.enabled,
+                ],
+            )?;
+            if changed == 0 {
+                return Err(AppError::Database(rusqlite::Error::QueryReturnedNoRows));
+            }
+        }
+        Ok(saved)
+    }
+
+    pub fn delete_alarm(&self, id: i64) -> Result<(), AppError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM alarms WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Mark alarm `id` as gone off at `now`, disabling it if it doesn't
+    /// repeat. False when it already went off that minute.
+    pub fn claim_alarm(&self, id: i64, now: &LocalTime) -> Result<bool, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let changed = conn.execute(
+            "UPDATE alarms SET last_fired = ?2, enabled = enabled AND weekdays != 0
+             WHERE id = ?1 AND last_fired IS NOT ?2",
+            params![id, now.stamp],
+        )?;
+        Ok(changed == 1)
+    }
+
+    /// The local date and time, as SQLite reads the system clock and zone.
+    pub fn local_now(&self) -> Result<LocalTime, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let time = conn.query_row(
+            "SELECT strftime('%Y-%m-%d %H:%M', 'now', 'localtime'),
+                    CAST(strftime('%w', 'now', 'localtime') AS INTEGER),
+                    CAST(strftime('%H', 'now', 'localtime') AS INTEGER),
+                    CAST(strftime('%M', 'now', 'localtime') AS INTEGER)",
+            [],
+            |row| {
+                Ok(LocalTime {
+                    stamp: row.get(0)?,
+                    weekday: row.get(1)?,
+                    hour: row.get(2)?,
+                    minute: row.get(3)?,
+                })
+            },
+        )?;
+        Ok(time)
+    }
+
+    /// A track's chapters, empty when it has none; None if never looked up.
+    pub fn get_chapters(&self, track_id: &str) -> Result<Option<Vec<Chapter>>, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached("SELECT chapters FROM chapters WHERE track_id = ?1")?;
+        let mut rows = stmt.query_map(params![track_id], |row| row.get::<_, String>(0))?;
+        Ok(rows
+            .next()
+            .and_then(|r| r.ok())
+            .map(|json| serde_json::from_str(&json).unwrap_or_default()))
+    }
+
+    pub fn set_chapters(&self, track_id: &str, chapters: &[Chapter]) -> Result<(), AppError> {
+        let json = serde_json::to_string(chapters).map_err(|e| AppError::Extraction(e.to_string()))?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO chapters (track_id, chapters) VALUES (?1, ?2)
+             ON CONFLICT(track_id) DO UPDATE SET
+                 chapters = excluded.chapters,
+                 fetched = datetime('now')",
+            params![track_id, json],
+        )?;
+        Ok(())
+    }
+
+    /// A track's SponsorBlock segments, unless never looked up or looked up
+    /// so long ago that they may have changed since: a month for a list, a
+    /// day when there were none yet, an hour after a failed lookup (which
+    /// keeps whatever was known before).
+    pub fn get_segments(&self, track_id: &str) -> Result<Option<Vec<Segment>>, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT segments FROM segments
+             WHERE track_id = ?1 AND fetched > datetime('now', CASE
+                 WHEN failed THEN '-1 hours'
+                 WHEN segments = '[]' THEN '-1 days'
+                 ELSE '-30 days'
+             END)",
+        )?;
+        let mut rows = stmt.query_map(params![track_id], |row| row.get::<_, String>(0))?;
+        Ok(rows
+            .next()
+            .and_then(|r| r.ok())
+            .map(|json| serde_json::from_str(&json).unwrap_or_default()))
+    }
+
+    pub fn set_segments(&self, track_id: &str, segments: &[Segment]) -> Result<(), AppError> {
+        let json = serde_json::to_string(segments).map_err(|e| AppError::Extraction(e.to_string()))?;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO segments (track_id, segments) VALUES (?1, ?2)
+             ON CONFLICT(track_id) DO UPDATE SET
+                 segments = excluded.segments,
+                 fetched = datetime('now'),
+                 failed = 0",
+            params![track_id, json],
+        )?;
+        Ok(())
+    }
+
+    /// Note a failed segment lookup so it isn't repeated for a while.
+    pub fn set_segments_failed(&self, track_id: &str) -> Result<(), AppError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO segments (track_id, segments, failed) VALUES (?1, '[]', 1)
+             ON CONFLICT(track_id) DO UPDATE SET
+                 fetched = datetime('now'),
+                 failed = 1",
+            params![track_id],
+        )?;
+        Ok(())
+    }
+
+    /// Uploads known to be split into chapters.
+    pub fn chaptered_track_ids(&self) -> Result<Vec<String>, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached("SELECT track_id FROM chapters WHERE chapters != '[]'")?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(ids)
+    }
+
     pub fn get_track_by_id(&self, id: &str) -> Result<Option<Track>, AppError> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare_cached(
-            "SELECT id, title, artist, thumbnail, duration FROM tracks WHERE id = ?1",
+            "SELECT id, title, artist, thumbnail, duration, album FROM tracks WHERE id = ?1",
         )?;
         let track = stmt
             .query_row(params![id], |row| {
@@ -228,6 +658,7 @@ impl SearchCache {
                     artist: row.get(2)?,
                     thumbnail: row.get(3)?,
                     duration_secs: row.get(4)?,
+                    album: row.get(5)?,
                     stream_url: None,
                 })
             })
@@ -242,7 +673,7 @@ impl SearchCache {
         let conn = self.conn.lock().unwrap();
         let placeholders: String = std::iter::repeat_n("?", ids.len()).collect::<Vec<_>>().join(",");
         let sql = format!(
-            "SELECT id, title, artist, thumbnail, duration FROM tracks WHERE id IN ({placeholders})"
+            "SELECT id, title, artist, thumbnail, duration, album FROM tracks WHERE id IN ({placeholders})"
         );
         let mut stmt = conn.prepare(&sql)?;
         let rows: Vec<Track> = stmt
@@ -253,6 +684,7 @@ impl SearchCache {
                     artist: row.get(2)?,
                     thumbnail: row.get(3)?,
                     duration_secs: row.get(4)?,
+                    album: row.get(5)?,
                     stream_url: None,
                 })
             })?
@@ -278,7 +710,7 @@ impl SearchCache {
             .join(" ");
 
         let mut stmt = conn.prepare_cached(
-            "SELECT t.id, t.title, t.artist, t.thumbnail, t.duration
+            "SELECT t.id, t.title, t.artist, t.thumbnail, t.duration, t.album
              FROM tracks_fts f
              JOIN tracks t ON t.rowid = f.rowid
              WHERE tracks_fts MATCH ?1
@@ -294,6 +726,7 @@ impl SearchCache {
                     artist: row.get(2)?,
                     thumbnail: row.get(3)?,
                     duration_secs: row.get(4)?,
+                    album: row.get(5)?,
                     stream_url: None,
                 })
             })?
@@ -416,6 +849,47 @@ impl SearchCache {
         Ok(())
     }
 
+    /// Remember the play queue, with `current` marking the current track.
+    pub fn save_queue(&self, tracks: &[Track], current: Option<usize>) -> Result<(), AppError> {
+        let conn = self.conn.lock().unwrap();
+        let tx = conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM play_queue", [])?;
+        for (i, t) in tracks.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO play_queue (position, track_id, title, artist, thumbnail, duration, album, current)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![i as i64, t.id, t.title, t.artist, t.thumbnail, t.duration_secs, t.album, current == Some(i)],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// The play queue as last saved, and which of its tracks is current.
+    pub fn load_queue(&self) -> Result<(Vec<Track>, Option<usize>), AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT track_id, title, artist, thumbnail, duration, album, current
+             FROM play_queue ORDER BY position",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                let track = Track {
+                    id: row.get(0)?,
+                    title: row.get(1)?,
+                    artist: row.get(2)?,
+                    thumbnail: row.get(3)?,
+                    duration_secs: row.get(4)?,
+                    album: row.get(5)?,
+                    stream_url: None,
+                };
+                Ok((track, row.get::<_, bool>(6)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let current = rows.iter().position(|(_, current)| *current);
+        Ok((rows.into_iter().map(|(t, _)| t).collect(), current))
+    }
+
     pub fn reorder_playlist_tracks(&self, playlist_id: i64, track_ids: &[String]) -> Result<(), AppError> {
         let conn = self.conn.lock().unwrap();
         let tx = conn.unchecked_transaction()?;
@@ -444,7 +918,7 @@ impl SearchCache {
     pub fn get_playlist_tracks(&self, playlist_id: i64) -> Result<Vec<Track>, AppError> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare_cached(
-            "SELECT t.id, t.title, t.artist, t.thumbnail, t.duration
+            "SELECT t.id, t.title, t.artist, t.thumbnail, t.duration, t.album
              FROM playlist_tracks pt
              JOIN tracks t ON t.id = pt.track_id
              WHERE pt.playlist_id = ?1
@@ -458,6 +932,7 @@ impl SearchCache {
                     artist: row.get(2)?,
                     thumbnail: row.get(3)?,
                     duration_secs: row.get(4)?,
+                    album: row.get(5)?,
                     stream_url: None,
                 })
             })?
@@ -475,26 +950,62 @@ impl SearchCache {
         Ok(())
     }
 
-    /// Record a track as persistently downloaded for offline playback.
-    pub fn mark_downloaded(&self, track_id: &str, path: &str, size: u64) -> Result<(), AppError> {
+    /// Record a track as persistently downloaded for offline playback, with
+    /// the codec and average bitrate (kbps) of the file.
+    pub fn mark_downloaded(
+        &self,
+        track_id: &str,
+        path: &str,
+        size: u64,
+        codec: &str,
+        bitrate: u32,
+    ) -> Result<(), AppError> {
         let conn = self.conn.lock().unwrap();
         conn.execute(
-            "INSERT INTO downloads (track_id, path, size) VALUES (?1, ?2, ?3)
+            "INSERT INTO downloads (track_id, path, size, codec, bitrate) VALUES (?1, ?2, ?3, ?4, ?5)
              ON CONFLICT(track_id) DO UPDATE SET
                  path = excluded.path,
                  size = excluded.size,
+                 codec = excluded.codec,
+                 bitrate = excluded.bitrate,
                  downloaded = datetime('now')",
-            params![track_id, path, size as i64],
+            params![track_id, path, size as i64, codec, bitrate],
         )?;
         Ok(())
     }
 
-    pub fn remove_download(&self, track_id: &str) -> Result<(), AppError> {
+    pub fn set_download_cut(&self, track_id: &str, cut: bool) -> Result<(), AppError> {
         let conn = self.conn.lock().unwrap();
-        conn.execute("DELETE FROM downloads WHERE track_id = ?1", params![track_id])?;
+        conn.execute(
+            "UPDATE downloads SET segments_cut = ?2 WHERE track_id = ?1",
+            params![track_id, cut],
+        )?;
         Ok(())
     }
 
+    /// Whether the download at `path` had segments cut out of it, which
+    /// leaves nothing to skip and every other position shifted.
+    pub fn is_cut_download(&self, path: &str) -> Result<bool, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached("SELECT 1 FROM downloads WHERE path = ?1 AND segments_cut = 1")?;
+        Ok(stmt.exists(params![path])?)
+    }
+
+    /// Forget the download of `track_id`. Returns how many other downloads
+    /// still use its file, as chapter tracks share their upload's.
+    pub fn remove_download(&self, track_id: &str) -> Result<usize, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let path: Option<String> = conn
+            .query_row("SELECT path FROM downloads WHERE track_id = ?1", params![track_id], |row| row.get(0))
+            .ok();
+        conn.execute("DELETE FROM downloads WHERE track_id = ?1", params![track_id])?;
+        let Some(path) = path else {
+            return Ok(0);
+        };
+        let sharing: i64 = conn.query_row("SELECT COUNT(*) FROM downloads WHERE path = ?1", params![path], |row| row.get(0))?;
+        Ok(sharing as usize)
+    }
+
     pub fn is_downloaded(&self, track_id: &str) -> Result<bool, AppError> {
         let conn = self.conn.lock().unwrap();
         let exists: bool = conn
@@ -522,7 +1033,7 @@ impl SearchCache {
     pub fn downloaded_tracks(&self) -> Result<Vec<Track>, AppError> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare_cached(
-            "SELECT t.id, t.title, t.artist, t.thumbnail, t.duration
+            "SELECT t.id, t.title, t.artist, t.thumbnail, t.duration, t.album
              FROM downloads d
              JOIN tracks t ON t.id = d.track_id
              ORDER BY d.downloaded DESC",
@@ -535,6 +1046,7 @@ impl SearchCache {
                     artist: row.get(2)?,
                     thumbnail: row.get(3)?,
                     duration_secs: row.get(4)?,
+                    album: row.get(5)?,
                     stream_url: None,
                 })
             })?
@@ -543,11 +1055,11 @@ impl SearchCache {
         Ok(tracks)
     }
 
-    /// Total size in bytes of all downloaded files.
+    /// Total size in bytes of all downloaded files, each shared file once.
     pub fn downloads_size(&self) -> Result<i64, AppError> {
         let conn = self.conn.lock().unwrap();
         let total: i64 = conn
-            .prepare_cached("SELECT COALESCE(SUM(size), 0) FROM downloads")?
+            .prepare_cached("SELECT COALESCE(SUM(size), 0) FROM (SELECT MAX(size) AS size FROM downloads GROUP BY path)")?
             .query_row([], |row| row.get(0))?;
         Ok(total)
     }
@@ -564,6 +1076,134 @@ impl SearchCache {
         Ok(rows)
     }
 
+    /// Stored EBU R128 measurement for a track, if it has been analysed.
+    pub fn get_loudness(&self, track_id: &str) -> Result<Option<Loudness>, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT integrated_lufs, true_peak_dbtp FROM loudness WHERE track_id = ?1",
+        )?;
+        let mut rows = stmt.query_map(params![track_id], |row| {
+            Ok(Loudness {
+                integrated_lufs: row.get(0)?,
+                true_peak_dbtp: row.get(1)?,
+            })
+        })?;
+        Ok(rows.next().and_then(|r| r.ok()))
+    }
+
+    pub fn set_loudness(&self, track_id: &str, loudness: &Loudness) -> Result<(), AppError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO loudness (track_id, integrated_lufs, true_peak_dbtp) VALUES (?1, ?2, ?3)
+             ON CONFLICT(track_id) DO UPDATE SET
+                 integrated_lufs = excluded.integrated_lufs,
+                 true_peak_dbtp = excluded.true_peak_dbtp,
+                 analyzed = datetime('now')",
+            params![track_id, loudness.integrated_lufs, loudness.true_peak_dbtp],
+        )?;
+        Ok(())
+    }
+
+    /// Measurements of every analysed track on the same album as `track_id`.
+    /// Empty when the track has no album.
+    pub fn album_loudness(&self, track_id: &str) -> Result<Vec<Loudness>, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT l.integrated_lufs, l.true_peak_dbtp
+             FROM tracks cur
+             JOIN tracks t ON t.album = cur.album COLLATE NOCASE
+             JOIN loudness l ON l.track_id = t.id
+             WHERE cur.id = ?1 AND TRIM(cur.album) <> ''",
+        )?;
+        let rows = stmt
+            .query_map(params![track_id], |row| {
+                Ok(Loudness {
+                    integrated_lufs: row.get(0)?,
+                    true_peak_dbtp: row.get(1)?,
+                })
+            })?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(rows)
+    }
+
+    /// Audible span of a track, if it was found with this threshold.
+    pub fn get_audible_span(&self, track_id: &str, threshold_db: f64) -> Result<Option<AudibleSpan>, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT start_ms, end_ms FROM silence
+             WHERE track_id = ?1 AND ABS(threshold_db - ?2) < 0.01",
+        )?;
+        let mut rows = stmt.query_map(params![track_id, threshold_db], |row| {
+            Ok(AudibleSpan {
+                start_ms: row.get::<_, i64>(0)? as u64,
+                end_ms: row.get::<_, i64>(1)? as u64,
+            })
+        })?;
+        Ok(rows.next().and_then(|r| r.ok()))
+    }
+
+    pub fn set_audible_span(&self, track_id: &str, threshold_db: f64, span: &AudibleSpan) -> Result<(), AppError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO silence (track_id, threshold_db, start_ms, end_ms) VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(track_id) DO UPDATE SET
+                 threshold_db = excluded.threshold_db,
+                 start_ms = excluded.start_ms,
+                 end_ms = excluded.end_ms,
+                 analyzed = datetime('now')",
+            params![track_id, threshold_db, span.start_ms as i64, span.end_ms as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Built-ins first, then the user's presets by name.
+    pub fn list_eq_presets(&self) -> Result<Vec<EqPreset>, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT name, preamp_db, bands, builtin FROM eq_presets
+             ORDER BY builtin DESC, rowid",
+        )?;
+        let rows = stmt
+            .query_map([], eq_preset_from_row)?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(rows)
+    }
+
+    pub fn get_eq_preset(&self, name: &str) -> Result<Option<EqPreset>, AppError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare_cached(
+            "SELECT name, preamp_db, bands, builtin FROM eq_presets WHERE name = ?1",
+        )?;
+        let mut rows = stmt.query_map(params![name], eq_preset_from_row)?;
+        Ok(rows.next().and_then(|r| r.ok()))
+    }
+
+    /// Create or replace a user preset. Built-ins can't be overwritten.
+    pub fn save_eq_preset(&self, preset: &EqPreset) -> Result<(), AppError> {
+        let bands = serde_json::to_string(&preset.bands).map_err(|e| AppError::Audio(e.to_string()))?;
+        let conn = self.conn.lock().unwrap();
+        let changed = conn.execute(
+            "INSERT INTO eq_presets (name, preamp_db, bands, builtin) VALUES (?1, ?2, ?3, 0)
+             ON CONFLICT(name) DO UPDATE SET
+                 preamp_db = excluded.preamp_db,
+                 bands = excluded.bands
+             WHERE builtin = 0",
+            params![preset.name, preset.preamp_db, bands],
+        )?;
+        if changed == 0 {
+            return Err(AppError::Audio(format!("\"{}\" is a built-in preset", preset.name)));
+        }
+        Ok(())
+    }
+
+    pub fn delete_eq_preset(&self, name: &str) -> Result<(), AppError> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM eq_presets WHERE name = ?1 AND builtin = 0", params![name])?;
+        Ok(())
+    }
+
     pub fn artist_affinities(&self, limit: usize) -> Result<Vec<(String, i64, i64)>, AppError> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare_cached(
@@ -593,7 +1233,7 @@ impl SearchCache {
     pub fn recently_played(&self, limit: usize) -> Result<Vec<Track>, AppError> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare_cached(
-            "SELECT t.id, t.title, t.artist, t.thumbnail, t.duration
+            "SELECT t.id, t.title, t.artist, t.thumbnail, t.duration, t.album
              FROM listen_history h
              JOIN tracks t ON t.id = h.track_id
              GROUP BY t.id
@@ -607,6 +1247,7 @@ impl SearchCache {
                     artist: row.get(2)?,
                     thumbnail: row.get(3)?,
                     duration_secs: row.get(4)?,
+                    album: row.get(5)?,
                     stream_url: None,
                 })
             })?
@@ -680,6 +1321,16 @@ impl SearchCache {
     }
 }
 
+fn eq_preset_from_row(row: &rusqlite::Row) -> rusqlite::Result<EqPreset> {
+    let bands: String = row.get(2)?;
+    Ok(EqPreset {
+        name: row.get(0)?,
+        preamp_db: row.get(1)?,
+        bands: serde_json::from_str(&bands).unwrap_or_default(),
+        builtin: row.get(3)?,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -701,6 +1352,7 @@ mod tests {
             artist: "Test Artist".into(),
             thumbnail: String::new(),
             duration_secs: 210.0,
+            album: String::new(),
             stream_url: None,
         }
     }
@@ -723,6 +1375,160 @@ mod tests {
         assert!((found.duration_secs - 210.0).abs() < 0.01);
     }
 
+    #[test]
+    fn cue_points_round_trip_and_clear() {
+        let db = temp_cache();
+        assert_eq!(db.get_cue_points("t1").unwrap(), CuePoints::default());
+
+        let cues = CuePoints { start_ms: Some(12_500), end_ms: None };
+        db.set_cue_points("t1", &cues).unwrap();
+        assert_eq!(db.get_cue_points("t1").unwrap(), cues);
+
+        db.set_cue_points("t1", &CuePoints::default()).unwrap();
+        assert_eq!(db.get_cue_points("t1").unwrap(), CuePoints::default());
+    }
+
+    #[test]
+    fn resume_points_and_bookmarks_follow_the_track() {
+        let db = temp_cache();
+        db.upsert_tracks(&[sample_track("mix")]).unwrap();
+        assert!(db.get_resume_point("mix").unwrap().is_none());
+
+        let point = ResumePoint { position_ms: 1_843_000, speed: 1.25 };
+        db.set_resume_point("mix", &point).unwrap();
+        assert_eq!(db.get_resume_point("mix").unwrap(), Some(point));
+        db.clear_resume_point("mix").unwrap();
+        assert!(db.get_resume_point("mix").unwrap().is_none());
+
+        let later = db.add_bookmark("mix", "Second set", 2_400_000).unwrap();
+        let earlier = db.add_bookmark("mix", "Opener", 60_000).unwrap();
+        assert_eq!(db.list_bookmarks("mix").unwrap(), vec![earlier.clone(), later]);
+        db.delete_bookmark(earlier.id).unwrap();
+        assert_eq!(db.list_bookmarks("mix").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn chapters_tell_unknown_from_none() {
+        let db = temp_cache();
+        db.upsert_tracks(&[sample_track("album"), sample_track("single")]).unwrap();
+        assert_eq!(db.get_chapters("album").unwrap(), None);
+
+        let chapters = vec![
+            Chapter { title: "Intro".into(), start_ms: 0, end_ms: 95_000 },
+            Chapter { title: "Second".into(), start_ms: 95_000, end_ms: 301_500 },
+        ];
+        db.set_chapters("album", &chapters).unwrap();
+        db.set_chapters("single", &[]).unwrap();
+        assert_eq!(db.get_chapters("album").unwrap(), Some(chapters));
+        assert_eq!(db.get_chapters("single").unwrap(), Some(vec![]));
+        assert_eq!(db.chaptered_track_ids().unwrap(), vec!["album".to_string()]);
+    }
+
+    #[test]
+    fn segments_expire_and_cut_downloads_are_known() {
+        let db = temp_cache();
+        db.upsert_tracks(&[sample_track("vid")]).unwrap();
+        assert_eq!(db.get_segments("vid").unwrap(), None);
+
+        let segments = vec![Segment { category: "music_offtopic".into(), start_ms: 0, end_ms: 14_200 }];
+        db.set_segments("vid", &segments).unwrap();
+        assert_eq!(db.get_segments("vid").unwrap(), Some(segments.clone()));
+        db.conn
+            .lock()
+            .unwrap()
+            .execute("UPDATE segments SET fetched = datetime('now', '-31 days')", [])
+            .unwrap();
+        assert_eq!(db.get_segments("vid").unwrap(), None);
+
+        // A failed lookup keeps what was known, for an hour.
+        db.set_segments_failed("vid").unwrap();
+        assert_eq!(db.get_segments("vid").unwrap(), Some(segments));
+        let age = |db: &SearchCache, offset: &str| {
+            db.conn
+                .lock()
+                .unwrap()
+                .execute("UPDATE segments SET fetched = datetime('now', ?1)", [offset])
+                .unwrap();
+        };
+        age(&db, "-2 hours");
+        assert_eq!(db.get_segments("vid").unwrap(), None);
+
+        // None found is asked again the next day.
+        db.set_segments("vid", &[]).unwrap();
+        age(&db, "-2 hours");
+        assert_eq!(db.get_segments("vid").unwrap(), Some(vec![]));
+        age(&db, "-2 days");
+        assert_eq!(db.get_segments("vid").unwrap(), None);
+
+        db.mark_downloaded("vid", "/music/vid.webm", 1, "opus", 128).unwrap();
+        assert!(!db.is_cut_download("/music/vid.webm").unwrap());
+        db.set_download_cut("vid", true).unwrap();
+        assert!(db.is_cut_download("/music/vid.webm").unwrap());
+    }
+
+    #[test]
+    fn chapter_downloads_share_their_uploads_file() {
+        let db = temp_cache();
+        let ids = ["mix", "mix#0-60000", "mix#60000-120000"];
+        db.upsert_tracks(&ids.map(sample_track)).unwrap();
+        for id in ids {
+            db.mark_downloaded(id, "/music/mix.webm", 1_000, "opus", 128).unwrap();
+        }
+        assert_eq!(db.downloads_size().unwrap(), 1_000);
+
+        assert_eq!(db.remove_download("mix").unwrap(), 2);
+        assert_eq!(db.remove_download("mix#0-60000").unwrap(), 1);
+        assert_eq!(db.remove_download("mix#60000-120000").unwrap(), 0);
+        assert_eq!(db.remove_download("never").unwrap(), 0);
+        assert_eq!(db.downloads_size().unwrap(), 0);
+    }
+
+    #[test]
+    fn alarms_go_off_once_per_minute() {
+        let db = temp_cache();
+        let playlist = db.create_playlist("Morning", "").unwrap();
+        let alarm = db
+            .save_alarm(&Alarm {
+                id: 0,
+                label: "Wake up".into(),
+                playlist_id: playlist.id,
+                hour: 7,
+                minute: 30,
+                weekdays: 0,
+                ramp_secs: 120,
+                volume: 0.6,
+                enabled: true,
+            })
+            .unwrap();
+        assert_eq!(db.list_alarms().unwrap(), vec![alarm.clone()]);
+
+        let now = LocalTime { stamp: "2026-10-17 07:30".into(), weekday: 6, hour: 7, minute: 30 };
+        assert!(db.claim_alarm(alarm.id, &now).unwrap());
+        assert!(!db.claim_alarm(alarm.id, &now).unwrap());
+        // A one-off alarm is done once it went off.
+        assert!(!db.list_alarms().unwrap()[0].enabled);
+
+        // Saving re-arms it; a repeating one stays enabled.
+        db.save_alarm(&Alarm { weekdays: 0b0111110, ..alarm.clone() }).unwrap();
+        assert!(db.claim_alarm(alarm.id, &now).unwrap());
+        assert!(db.list_alarms().unwrap()[0].enabled);
+
+        db.delete_playlist(playlist.id).unwrap();
+        assert!(db.list_alarms().unwrap().is_empty());
+    }
+
+    #[test]
+    fn decoded_duration_survives_metadata_refresh() {
+        let db = temp_cache();
+        let track = sample_track("abc123");
+        db.upsert_tracks(std::slice::from_ref(&track)).unwrap();
+        db.set_track_duration("abc123", 208.46).unwrap();
+        db.upsert_tracks(std::slice::from_ref(&track)).unwrap();
+
+        let found = db.get_track_by_id("abc123").unwrap().unwrap();
+        assert!((found.duration_secs - 208.46).abs() < 0.001);
+    }
+
     #[test]
     fn search_local_does_not_find_by_video_id() {
         let db = temp_cache();
@@ -803,4 +1609,106 @@ mod tests {
         db.delete_playlist(pl.id).unwrap();
         assert!(db.list_playlists().unwrap().is_empty());
     }
+
+    #[test]
+    fn loudness_roundtrip_and_album_lookup() {
+        let db = temp_cache();
+        let mut a = sample_track("a1");
+        a.album = "Record".into();
+        let mut b = sample_track("a2");
+        b.album = "record".into();
+        let single = sample_track("s1");
+        db.upsert_tracks(&[a, b, single]).unwrap();
+
+        assert!(db.get_loudness("a1").unwrap().is_none());
+        let l = Loudness { integrated_lufs: -9.5, true_peak_dbtp: 0.3 };
+        db.set_loudness("a1", &l).unwrap();
+        db.set_loudness("a2", &Loudness { integrated_lufs: -12.0, true_peak_dbtp: -1.0 }).unwrap();
+        db.set_loudness("s1", &l).unwrap();
+
+        assert_eq!(db.get_loudness("a1").unwrap(), Some(l));
+        assert_eq!(db.album_loudness("a2").unwrap().len(), 2);
+        assert!(db.album_loudness("s1").unwrap().is_empty());
+    }
+
+    #[test]
+    fn eq_presets_keep_builtins_safe() {
+        let db = temp_cache();
+        let presets = db.list_eq_presets().unwrap();
+        assert!(presets.iter().any(|p| p.name == "Flat" && p.builtin));
+
+        let mut mine = db.get_eq_preset("Bass Boost").unwrap().unwrap();
+        assert!(db.save_eq_preset(&mine).is_err());
+        db.delete_eq_preset("Bass Boost").unwrap();
+        assert!(db.get_eq_preset("Bass Boost").unwrap().is_some());
+
+        mine.name = "Mine".into();
+        mine.preamp_db = -4.0;
+        db.save_eq_preset(&mine).unwrap();
+        mine.preamp_db = -5.0;
+        db.save_eq_preset(&mine).unwrap();
+        let stored = db.get_eq_preset("Mine").unwrap().unwrap();
+        assert_eq!(stored.preamp_db, -5.0);
+        assert!(!stored.builtin);
+        assert_eq!(stored.bands, mine.bands);
+        assert_eq!(db.list_eq_presets().unwrap().last().unwrap().name, "Mine");
+
+        db.delete_eq_preset("Mine").unwrap();
+        assert!(db.get_eq_preset("Mine").unwrap().is_none());
+    }
+
+    #[test]
+    fn audible_span_is_kept_per_threshold() {
+        let db = temp_cache();
+        db.upsert_tracks(&[sample_track("t1")]).unwrap();
+        let span = AudibleSpan { start_ms: 1_900, end_ms: 180_300 };
+        db.set_audible_span("t1", -50.0, &span).unwrap();
+        assert_eq!(db.get_audible_span("t1", -50.0).unwrap(), Some(span));
+        assert!(db.get_audible_span("t1", -40.0).unwrap().is_none());
+
+        let louder = AudibleSpan { start_ms: 2_000, end_ms: 180_000 };
+        db.set_audible_span("t1", -40.0, &louder).unwrap();
+        assert_eq!(db.get_audible_span("t1", -40.0).unwrap(), Some(louder));
+        assert!(db.get_audible_span("t1", -50.0).unwrap().is_none());
+    }
+
+    #[test]
+    fn queue_is_kept_in_order_with_its_current_track() {
+        let db = temp_cache();
+        let ids = |(tracks, current): (Vec<Track>, Option<usize>)| {
+            (tracks.into_iter().map(|t| t.id).collect::<Vec<_>>(), current)
+        };
+        assert_eq!(ids(db.load_queue().unwrap()), (vec![], None));
+        let tracks = vec![sample_track("b"), sample_track("a#0-1000"), sample_track("c")];
+        db.save_queue(&tracks, Some(1)).unwrap();
+        assert_eq!(ids(db.load_queue().unwrap()), (vec!["b".into(), "a#0-1000".into(), "c".into()], Some(1)));
+        assert_eq!(db.load_queue().unwrap().0[2].title, "Track c");
+
+        db.save_queue(&tracks[..1], None).unwrap();
+        assert_eq!(ids(db.load_queue().unwrap()), (vec!["b".into()], None));
+    }
+
+    #[test]
+    fn queue_saved_in_the_old_config_moves_into_the_database() {
+        use crate::config::ConfigManager;
+
+        let db = temp_cache();
+        db.upsert_tracks(&[sample_track("a"), sample_track("c")]).unwrap();
+        let dir = std::env::temp_dir().join(format!("sunder_test_config_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let old = r#"{"volume": 0.5, "saved_queue": ["a", "gone", "c"], "saved_queue_index": 2}"#;
+        std::fs::write(dir.join("config.json"), old).unwrap();
+
+        let config = ConfigManager::new(&dir);
+        config.migrate_saved_queue(&db);
+        let (tracks, current) = db.load_queue().unwrap();
+        assert_eq!(tracks.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(), ["a", "c"]);
+        assert_eq!(current, Some(1));
+
+        // Moved once: the queue is gone from the config file.
+        let saved = std::fs::read_to_string(dir.join("config.json")).unwrap();
+        assert!(!saved.contains("saved_queue"), "{saved}");
+        assert_eq!(config.get().volume, 0.5);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -3,7 +3,7 @@ use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::sync::{Arc, Mutex};
 
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, Manager};
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
 use tokio::sync::Semaphore;
@@ -34,15 +34,14 @@ impl DownloadManager {
         }
     }
 
-    /// Persistent path the offline MP3 lives at for a given track id.
-    pub fn path_for(&self, track_id: &str) -> PathBuf {
-        self.dir.join(format!("{track_id}.mp3"))
+    /// Persistent path of the offline file for a given track id, if any.
+    pub fn path_for(&self, track_id: &str) -> Option<PathBuf> {
+        find_audio(&self.dir, track_id)
     }
 
     /// Resolve the offline downloads directory for a Tauri app handle. Shared
     /// with the audio engine so downloaded tracks play back without network.
     pub fn dir_for(app: &AppHandle) -> PathBuf {
-        use tauri::Manager;
         let base = app
             .path()
             .app_data_dir()
@@ -63,18 +62,18 @@ impl DownloadManager {
     /// already-downloaded or in-flight tracks resolve immediately.
     pub async fn download(&self, app: &AppHandle, db: &SearchCache, track: &Track) -> Result<(), String> {
         let track_id = track.id.clone();
-        let path = self.path_for(&track_id);
+        // A chapter track keeps the whole upload it is cut from.
+        let file_id = crate::audio::chapters::split_id(&track_id).0.to_string();
 
         // Already downloaded on disk: ensure DB knows and report done.
-        if path.exists() {
-            let size = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        if let Some(path) = self.path_for(&file_id) {
             let _ = db.upsert_tracks(std::slice::from_ref(track));
-            let _ = db.mark_downloaded(&track_id, &path.to_string_lossy(), size);
+            let _ = record(db, track, &path);
             emit(app, &track_id, "done", 100.0);
             return Ok(());
         }
 
-        if !self.try_begin(&track_id) {
+        if !self.try_begin(&file_id) {
             // Another request is already downloading this track.
             return Ok(());
         }
@@ -83,37 +82,77 @@ impl DownloadManager {
         let _ = db.upsert_tracks(std::slice::from_ref(track));
         emit(app, &track_id, "queued", 0.0);
 
+        // SponsorBlock segments can be cut out of the file for good.
+        let config = app.state::<crate::config::ConfigManager>().get();
+        let cut = config.sponsorblock_cut_downloads && !config.sponsorblock_categories.is_empty();
+        let mut extra = Vec::new();
+        if cut {
+            extra = vec![
+                "--sponsorblock-remove".to_string(),
+                config.sponsorblock_categories.join(","),
+                "--sponsorblock-api".to_string(),
+                config.sponsorblock_api,
+            ];
+        }
+
         let permit = self.sem.clone().acquire_owned().await;
-        let result = run_ytdlp(app, &self.dir, &track_id).await;
+        let result = run_ytdlp(app, &self.dir, &file_id, &extra).await;
         drop(permit);
 
-        self.finish(&track_id);
+        self.finish(&file_id);
 
         match result {
             Ok(final_path) => {
-                let size = std::fs::metadata(&final_path).map(|m| m.len()).unwrap_or(0);
-                db.mark_downloaded(&track_id, &final_path.to_string_lossy(), size)
-                    .map_err(|e| e.to_string())?;
+                record(db, track, &final_path).map_err(|e| e.to_string())?;
+                let _ = db.set_download_cut(&track_id, cut);
                 emit(app, &track_id, "done", 100.0);
+                if db.get_loudness(&file_id).ok().flatten().is_none() {
+                    let app = app.clone();
+                    tokio::task::spawn_blocking(move || {
+                        crate::audio::loudness::analyze_and_store(&app, &file_id, &final_path)
+                    });
+                }
                 Ok(())
             }
             Err(e) => {
-                cleanup_partials(&self.dir, &track_id);
+                cleanup_partials(&self.dir, &file_id);
                 emit(app, &track_id, "error", 0.0);
                 Err(e)
             }
         }
     }
 
-    /// Delete a downloaded track from disk and the database.
+    /// Delete a downloaded track from the database, and its file from disk
+    /// once no other track (an upload or its chapters) plays from it.
     pub fn delete(&self, db: &SearchCache, track_id: &str) -> Result<(), String> {
-        let path = self.path_for(track_id);
-        let _ = std::fs::remove_file(&path);
-        db.remove_download(track_id).map_err(|e| e.to_string())?;
+        let sharing = db.remove_download(track_id).map_err(|e| e.to_string())?;
+        if sharing == 0 {
+            cleanup_partials(&self.dir, crate::audio::chapters::split_id(track_id).0);
+        }
         Ok(())
     }
 }
 
+/// Extensions an audio file may have on disk: the containers YouTube serves
+/// natively, plus MP3 from versions that transcoded every download.
+pub const AUDIO_EXTENSIONS: [&str; 3] = ["webm", "m4a", "mp3"];
+
+/// Finished audio file for `track_id` in `dir`, whatever its container.
+pub fn find_audio(dir: &Path, track_id: &str) -> Option<PathBuf> {
+    AUDIO_EXTENSIONS
+        .iter()
+        .map(|ext| dir.join(format!("{track_id}.{ext}")))
+        .find(|p| p.exists())
+}
+
+/// Record a finished download with its size, codec and bitrate.
+fn record(db: &SearchCache, track: &Track, path: &Path) -> Result<(), crate::error::AppError> {
+    let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+    let (codec, bitrate) = crate::audio::decoder::describe(path, track.duration_secs)
+        .unwrap_or_default();
+    db.mark_downloaded(&track.id, &path.to_string_lossy(), size, &codec, bitrate)
+}
+
 #[derive(serde::Serialize, Clone)]
 struct DownloadEvent {
     track_id: String,
@@ -137,21 +176,18 @@ fn ytdlp_bin() -> String {
 }
 
 /// Runs yt-dlp, streaming download progress as `track-download` events.
-/// Returns the path to the finished MP3 on success.
-async fn run_ytdlp(app: &AppHandle, dir: &Path, track_id: &str) -> Result<PathBuf, String> {
+/// The best audio stream is kept in its original container, so the path
+/// returned on success may end in any of [`AUDIO_EXTENSIONS`].
+async fn run_ytdlp(app: &AppHandle, dir: &Path, track_id: &str, extra: &[String]) -> Result<PathBuf, String> {
     let bin = ytdlp_bin();
     let url = format!("https://www.youtube.com/watch?v={track_id}");
     let out_template = dir.join(format!("{track_id}.%(ext)s"));
-    let expected_path = dir.join(format!("{track_id}.mp3"));
 
     let mut child = Command::new(&bin)
         .args([
             url.as_str(),
-            "--extract-audio",
-            "--audio-format",
-            "mp3",
-            "--audio-quality",
-            "2",
+            "-f",
+            crate::audio::engine::AUDIO_FORMAT,
             "-o",
             out_template.to_str().unwrap_or_default(),
             "--no-playlist",
@@ -159,6 +195,7 @@ async fn run_ytdlp(app: &AppHandle, dir: &Path, track_id: &str) -> Result<PathBu
             "--concurrent-fragments",
             "4",
         ])
+        .args(extra)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
@@ -169,16 +206,16 @@ async fn run_ytdlp(app: &AppHandle, dir: &Path, track_id: &str) -> Result<PathBu
         while let Ok(Some(line)) = lines.next_line().await {
             if let Some(pct) = parse_download_pct(&line) {
                 emit(app, track_id, "downloading", pct);
-            } else if line.contains("[ExtractAudio]") {
-                emit(app, track_id, "converting", 100.0);
             }
         }
     }
 
     let status = child.wait().await.map_err(|e| format!("yt-dlp wait: {e}"))?;
 
-    if status.success() && expected_path.exists() {
-        return Ok(expected_path);
+    if status.success() {
+        if let Some(path) = find_audio(dir, track_id) {
+            return Ok(path);
+        }
     }
 
     let stderr = child
@@ -202,7 +239,7 @@ async fn run_ytdlp(app: &AppHandle, dir: &Path, track_id: &str) -> Result<PathBu
 }
 
 fn cleanup_partials(dir: &Path, track_id: &str) {
-    for ext in ["mp3", "webm", "m4a", "opus", "part", "webm.part", "m4a.part"] {
+    for ext in AUDIO_EXTENSIONS.iter().chain(&["opus", "part", "webm.part", "m4a.part"]) {
         let _ = std::fs::remove_file(dir.join(format!("{track_id}.{ext}")));
     }
 }
@@ -1,8 +1,9 @@
 use std::process::Stdio;
+use std::time::Duration;
 use tokio::process::Command;
 
 use crate::error::AppError;
-use crate::models::Track;
+use crate::models::{Chapter, Segment, Track};
 
 pub struct Extractor {
     bin: String,
@@ -49,6 +50,7 @@ impl Extractor {
                         .to_string(),
                     thumbnail: best_thumbnail(&v),
                     duration_secs: v["duration"].as_f64().unwrap_or(0.0),
+                    album: v["album"].as_str().unwrap_or_default().to_string(),
                     stream_url: None,
                 })
             })
@@ -91,6 +93,7 @@ impl Extractor {
                         .to_string(),
                     thumbnail: best_thumbnail(&v),
                     duration_secs: v["duration"].as_f64().unwrap_or(0.0),
+                    album: v["album"].as_str().unwrap_or_default().to_string(),
                     stream_url: None,
                 })
             })
@@ -99,8 +102,8 @@ impl Extractor {
         Ok(tracks)
     }
 
-    /// Fetch metadata for a single video/track.
-    pub async fn metadata(&self, video_id: &str) -> Result<Track, AppError> {
+    /// Fetch metadata for a single video/track, with its chapters.
+    pub async fn metadata(&self, video_id: &str) -> Result<(Track, Vec<Chapter>), AppError> {
         let output = Command::new(&self.bin)
             .args([
                 &format!("https://www.youtube.com/watch?v={video_id}"),
@@ -117,7 +120,7 @@ impl Extractor {
         let v: serde_json::Value = serde_json::from_slice(&output.stdout)
             .map_err(|e| AppError::Extraction(e.to_string()))?;
 
-        Ok(Track {
+        let track = Track {
             id: v["id"].as_str().unwrap_or(video_id).to_string(),
             title: v["title"].as_str().unwrap_or("Unknown").to_string(),
             artist: v["channel"].as_str()
@@ -126,8 +129,39 @@ impl Extractor {
                 .to_string(),
             thumbnail: best_thumbnail(&v),
             duration_secs: v["duration"].as_f64().unwrap_or(0.0),
+            album: v["album"].as_str().unwrap_or_default().to_string(),
             stream_url: None,
+        };
+        let chapters = parse_chapters(&v, track.duration_secs);
+        Ok((track, chapters))
+    }
+
+    /// SponsorBlock segments of `categories`, asked of the server at `api`
+    /// directly. A video nobody submitted any for has none.
+    pub async fn segments(&self, video_id: &str, api: &str, categories: &[String]) -> Result<Vec<Segment>, AppError> {
+        let categories = serde_json::to_string(categories).map_err(|e| AppError::Extraction(e.to_string()))?;
+        let url = format!("{}/api/skipSegments", api.trim_end_matches('/'));
+        let video_id = video_id.to_string();
+        let body = tokio::task::spawn_blocking(move || {
+            let failed = |e: &dyn std::fmt::Display| AppError::Extraction(format!("SponsorBlock lookup failed: {e}"));
+            match ureq::get(&url)
+                .timeout(Duration::from_secs(15))
+                .query("videoID", &video_id)
+                .query("categories", &categories)
+                .call()
+            {
+                Ok(response) => response.into_string().map_err(|e| failed(&e)),
+                // The API's answer for a video without segments.
+                Err(ureq::Error::Status(404, _)) => Ok(String::new()),
+                Err(ureq::Error::Status(status, _)) => {
+                    Err(AppError::Extraction(format!("SponsorBlock lookup failed (HTTP {status})")))
+                }
+                Err(e) => Err(failed(&e)),
+            }
         })
+        .await
+        .map_err(|e| AppError::Extraction(e.to_string()))??;
+        Ok(parse_segments(&body))
     }
 
     pub async fn get_subtitles(&self, video_id: &str, lang: &str) -> Result<String, AppError> {
@@ -232,6 +266,7 @@ impl Extractor {
                         .to_string(),
                     thumbnail: thumb,
                     duration_secs: v["duration"].as_f64().unwrap_or(0.0),
+                    album: v["album"].as_str().unwrap_or_default().to_string(),
                     stream_url: None,
                 }) {
                     tracks.push(track);
@@ -243,6 +278,45 @@ impl Extractor {
     }
 }
 
+/// Chapter markers from the uploader, as yt-dlp lists them. The last one
+/// runs to the end of the video when it has no end of its own.
+fn parse_chapters(v: &serde_json::Value, duration_secs: f64) -> Vec<Chapter> {
+    let Some(list) = v["chapters"].as_array() else {
+        return Vec::new();
+    };
+    let ms = |secs: f64| (secs.max(0.0) * 1000.0).round() as u64;
+    list.iter()
+        .enumerate()
+        .filter_map(|(i, c)| {
+            let start_ms = ms(c["start_time"].as_f64()?);
+            let end_ms = ms(c["end_time"].as_f64().unwrap_or(duration_secs));
+            let title = c["title"]
+                .as_str()
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .map_or_else(|| format!("Chapter {}", i + 1), str::to_string);
+            (start_ms < end_ms).then_some(Chapter { title, start_ms, end_ms })
+        })
+        .collect()
+}
+
+/// Segments from a `skipSegments` response. Points of interest have no
+/// length and are left out.
+fn parse_segments(body: &str) -> Vec<Segment> {
+    let Ok(serde_json::Value::Array(list)) = serde_json::from_str(body.trim()) else {
+        return Vec::new();
+    };
+    let ms = |v: &serde_json::Value| v.as_f64().map(|secs| (secs.max(0.0) * 1000.0).round() as u64);
+    list.iter()
+        .filter_map(|s| {
+            let start_ms = ms(&s["segment"][0])?;
+            let end_ms = ms(&s["segment"][1])?;
+            let category = s["category"].as_str()?.to_string();
+            (start_ms < end_ms).then_some(Segment { category, start_ms, end_ms })
+        })
+        .collect()
+}
+
 fn best_thumbnail(v: &serde_json::Value) -> String {
     if let Some(thumbs) = v["thumbnails"].as_array() {
         // Pick a medium-res thumbnail (~320x180) instead of the largest one.
@@ -267,3 +341,61 @@ fn best_thumbnail(v: &serde_json::Value) -> String {
     }
     base.to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// A SponsorBlock server on localhost answering `requests` lookups:
+    /// segments for "known", 404 for anything else. Hands back the
+    /// request lines it saw.
+    fn stub_server(requests: usize) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let api = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut seen = Vec::new();
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request).unwrap();
+                let response = if request.contains("videoID=known") {
+                    let body = r#"[{"category":"music_offtopic","actionType":"skip","segment":[0,12.5]},
+                                   {"category":"poi_highlight","actionType":"poi","segment":[40,40]}]"#;
+                    format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len())
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\nConnection: close\r\n\r\nNot Found".to_string()
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+                seen.push(request.trim().to_string());
+            }
+            seen
+        });
+        (api, server)
+    }
+
+    #[tokio::test]
+    async fn segments_come_from_the_skip_segments_endpoint() {
+        let (api, server) = stub_server(2);
+        let extractor = Extractor::new();
+        let categories = vec!["music_offtopic".to_string(), "poi_highlight".to_string()];
+
+        let segments = extractor.segments("known", &format!("{api}/"), &categories).await.unwrap();
+        assert_eq!(segments, vec![Segment { category: "music_offtopic".into(), start_ms: 0, end_ms: 12_500 }]);
+        assert_eq!(extractor.segments("unknown", &api, &categories).await.unwrap(), vec![]);
+
+        let requests = server.join().unwrap();
+        assert!(requests[0].starts_with("GET /api/skipSegments?videoID=known&categories="), "{}", requests[0]);
+        assert!(requests[0].contains("%22music_offtopic%22"), "{}", requests[0]);
+    }
+
+    #[tokio::test]
+    async fn unreachable_server_is_an_error() {
+        let api = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+        assert!(Extractor::new().segments("known", &api, &[]).await.is_err());
+    }
+}
@@ -16,12 +16,20 @@ pub fn set_config(config: AppConfig, manager: State<'_, ConfigManager>) {
 }
 
 use crate::audio::AudioHandle;
+use crate::audio::chapters;
 use crate::audio::engine::AudioCommand;
-use crate::audio::equalizer::BAND_COUNT;
+use crate::audio::dynamics::{DynamicsSettings, MIN_CEILING_DB};
+use crate::audio::eq_presets::{self, EqPreset};
+use crate::audio::equalizer::{self, EqBand, MAX_BANDS, MAX_GAIN_DB};
+use crate::audio::output::OutputDevice;
+use crate::audio::queue::{self, PlayQueue, QueueSnapshot, RepeatMode};
+use crate::audio::sleep::{SleepMode, SleepTimer};
+use crate::audio::stereo::StereoSettings;
+use crate::audio::tempo::SpeedMode;
 use crate::db::{CachedLyrics, SearchCache};
 use crate::downloads::DownloadManager;
 use crate::extraction::Extractor;
-use crate::models::{Playlist, SearchResult, SearchSource, Track};
+use crate::models::{Alarm, Bookmark, CuePoints, Playlist, SearchResult, SearchSource, Track};
 
 #[tauri::command]
 pub async fn search(
@@ -98,42 +106,163 @@ pub async fn search(
 #[tauri::command]
 pub async fn play_track(
     track_id: String,
+    app: tauri::AppHandle,
     audio: State<'_, AudioHandle>,
     db: State<'_, SearchCache>,
     extractor: State<'_, Extractor>,
-    discord: State<'_, DiscordPresence>,
 ) -> Result<(), String> {
-    // Look up duration from DB by primary key (instant).
+    // Look up the track from DB by primary key (instant).
     // Only fall back to yt-dlp metadata if the track was never seen before.
-    let (duration_ms, title, artist, thumbnail) = match db.get_track_by_id(&track_id) {
-        Ok(Some(t)) => ((t.duration_secs * 1000.0) as u64, t.title, t.artist, t.thumbnail),
-        _ => {
-            match extractor.metadata(&track_id).await {
-                Ok(t) => {
-                    let _ = db.upsert_tracks(std::slice::from_ref(&t));
-                    ((t.duration_secs * 1000.0) as u64, t.title, t.artist, t.thumbnail)
-                }
-                Err(_) => (0u64, "Unknown".to_string(), "Unknown".to_string(), String::new()),
+    let track = match db.get_track_by_id(&track_id) {
+        Ok(Some(t)) => t,
+        _ => match extractor.metadata(&track_id).await {
+            Ok((t, chapters)) => {
+                let _ = db.upsert_tracks(std::slice::from_ref(&t));
+                let _ = db.set_chapters(&t.id, &chapters);
+                t
             }
-        }
+            Err(_) => Track {
+                id: track_id,
+                title: "Unknown".to_string(),
+                artist: "Unknown".to_string(),
+                thumbnail: String::new(),
+                duration_secs: 0.0,
+                album: String::new(),
+                stream_url: None,
+            },
+        },
     };
 
-    audio.send(AudioCommand::Play { video_id: track_id.clone(), duration_ms });
-    audio.send(AudioCommand::UpdateMetadata { 
-        title: title.clone(), 
-        artist: artist.clone(), 
-        thumbnail: thumbnail.clone(),
-        track_id: track_id.clone(),
-    });
-    discord.send(PresenceCommand::SetActivity {
-        title,
-        artist,
-        thumbnail,
-    });
-    let _ = db.record_listen(&track_id);
+    let mut queue = audio.queue.lock().unwrap();
+    queue.select_track(track);
+    queue::play_current(&app, &queue, &|cmd| audio.send(cmd));
+    Ok(())
+}
+
+/// Apply an edit to the play queue, then resync the engine's preload and
+/// the frontend with it.
+fn edit_queue(app: &tauri::AppHandle, audio: &AudioHandle, edit: impl FnOnce(&mut PlayQueue)) {
+    let mut queue = audio.queue.lock().unwrap();
+    edit(&mut queue);
+    queue::changed(app, &queue, &|cmd| audio.send(cmd));
+}
+
+#[tauri::command]
+pub fn get_queue(audio: State<'_, AudioHandle>) -> QueueSnapshot {
+    audio.queue.lock().unwrap().snapshot()
+}
+
+/// Replace the queue. `index` selects the current track (-1 for none); with
+/// `play` set it starts right away.
+#[tauri::command]
+pub async fn set_queue(
+    tracks: Vec<Track>,
+    index: i64,
+    play: bool,
+    app: tauri::AppHandle,
+    audio: State<'_, AudioHandle>,
+) -> Result<(), String> {
+    let mut queue = audio.queue.lock().unwrap();
+    queue.set_tracks(tracks, usize::try_from(index).ok());
+    if play && queue.current().is_some() {
+        queue::play_current(&app, &queue, &|cmd| audio.send(cmd));
+    } else {
+        queue::changed(&app, &queue, &|cmd| audio.send(cmd));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn queue_append(tracks: Vec<Track>, app: tauri::AppHandle, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    edit_queue(&app, &audio, |q| q.append(tracks));
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn queue_insert_next(tracks: Vec<Track>, app: tauri::AppHandle, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    edit_queue(&app, &audio, |q| q.insert_next(tracks));
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn queue_remove(index: usize, app: tauri::AppHandle, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    edit_queue(&app, &audio, |q| q.remove(index));
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn queue_move(from: usize, to: usize, app: tauri::AppHandle, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    edit_queue(&app, &audio, |q| q.move_track(from, to));
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn queue_shuffle(app: tauri::AppHandle, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    edit_queue(&app, &audio, PlayQueue::shuffle);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn queue_unshuffle(app: tauri::AppHandle, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    edit_queue(&app, &audio, PlayQueue::unshuffle);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn queue_clear(app: tauri::AppHandle, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    edit_queue(&app, &audio, PlayQueue::clear);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn queue_play_index(index: usize, app: tauri::AppHandle, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    let mut queue = audio.queue.lock().unwrap();
+    if queue.select(index).is_none() {
+        return Err(format!("No track at queue index {index}"));
+    }
+    queue::play_current(&app, &queue, &|cmd| audio.send(cmd));
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn next_track(audio: State<'_, AudioHandle>) -> Result<(), String> {
+    audio.send(AudioCommand::Next);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn previous_track(audio: State<'_, AudioHandle>) -> Result<(), String> {
+    audio.send(AudioCommand::Previous);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn next_chapter(audio: State<'_, AudioHandle>) -> Result<(), String> {
+    audio.send(AudioCommand::NextChapter);
     Ok(())
 }
 
+#[tauri::command]
+pub async fn previous_chapter(audio: State<'_, AudioHandle>) -> Result<(), String> {
+    audio.send(AudioCommand::PreviousChapter);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn save_queue_as_playlist(
+    name: String,
+    audio: State<'_, AudioHandle>,
+    db: State<'_, SearchCache>,
+) -> Result<Playlist, String> {
+    let tracks = audio.queue.lock().unwrap().tracks().to_vec();
+    db.upsert_tracks(&tracks).map_err(|e| e.to_string())?;
+    let mut playlist = db.create_playlist(&name, "").map_err(|e| e.to_string())?;
+    let ids: Vec<String> = tracks.into_iter().map(|t| t.id).collect();
+    db.replace_playlist_tracks(playlist.id, &ids).map_err(|e| e.to_string())?;
+    playlist.track_count = ids.len() as i64;
+    Ok(playlist)
+}
+
 #[tauri::command]
 pub async fn pause(audio: State<'_, AudioHandle>, discord: State<'_, DiscordPresence>) -> Result<(), String> {
     audio.send(AudioCommand::Pause);
@@ -167,12 +296,83 @@ pub async fn set_speed(speed: f32, audio: State<'_, AudioHandle>) -> Result<(),
     Ok(())
 }
 
+/// "resample" lets pitch follow the speed; "stretch" keeps it.
+#[tauri::command]
+pub async fn set_speed_mode(mode: String, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    let Some(mode) = SpeedMode::from_name(&mode) else {
+        return Err(format!("Invalid speed mode: {}", mode));
+    };
+    audio.send(AudioCommand::SetSpeedMode(mode));
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_pitch(semitones: f32, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    audio.send(AudioCommand::SetPitch(semitones));
+    Ok(())
+}
+
+/// Start or stop the `audio-spectrum` events that feed visualizers.
+#[tauri::command]
+pub async fn set_spectrum_enabled(enabled: bool, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    audio.effects.spectrum.set_enabled(enabled);
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn seek(position_secs: f64, audio: State<'_, AudioHandle>) -> Result<(), String> {
     audio.send(AudioCommand::Seek(position_secs));
     Ok(())
 }
 
+/// Start the sleep timer in `mode`, or cancel it with none. It fades out
+/// over the configured time before stopping.
+#[tauri::command]
+pub async fn set_sleep_timer(
+    mode: Option<SleepMode>,
+    audio: State<'_, AudioHandle>,
+    config_mgr: State<'_, ConfigManager>,
+) -> Result<(), String> {
+    let fade = std::time::Duration::from_secs_f64(config_mgr.get().sleep_fade_secs.clamp(0.0, 600.0));
+    audio.send(AudioCommand::SetSleepTimer(mode.map(|m| SleepTimer::new(m, fade))));
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_ab_loop(a_ms: u64, b_ms: u64, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    if a_ms >= b_ms {
+        return Err("Loop end must come after its start".into());
+    }
+    audio.send(AudioCommand::SetAbLoop(Some((a_ms, b_ms))));
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn clear_ab_loop(audio: State<'_, AudioHandle>) -> Result<(), String> {
+    audio.send(AudioCommand::SetAbLoop(None));
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn list_output_devices() -> Result<Vec<OutputDevice>, String> {
+    Ok(crate::audio::output::list_devices())
+}
+
+/// Switch playback to another output device and remember it. An empty name
+/// selects the system default.
+#[tauri::command]
+pub async fn set_output_device(
+    name: String,
+    audio: State<'_, AudioHandle>,
+    config_mgr: State<'_, ConfigManager>,
+) -> Result<(), String> {
+    let mut cfg = config_mgr.get();
+    cfg.output_device = name.clone();
+    config_mgr.update(cfg);
+    audio.send(AudioCommand::SetOutputDevice(name));
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn get_playback_state(audio: State<'_, AudioHandle>) -> Result<serde_json::Value, String> {
     let state = audio.state.read().unwrap().clone();
@@ -190,40 +390,230 @@ pub async fn get_playback_state(audio: State<'_, AudioHandle>) -> Result<serde_j
     }))
 }
 
+/// Set the gain of every band, in band order.
 #[tauri::command]
 pub async fn set_eq_gains(gains: Vec<f32>, audio: State<'_, AudioHandle>) -> Result<(), String> {
-    if gains.len() != BAND_COUNT {
-        return Err(format!("expected {BAND_COUNT} gain values"));
+    let mut eq = audio.effects.eq.write().unwrap();
+    if gains.len() != eq.bands.len() {
+        return Err(format!("expected {} gain values", eq.bands.len()));
+    }
+    for (band, &g) in eq.bands.iter_mut().zip(&gains) {
+        band.gain_db = g.clamp(-MAX_GAIN_DB, MAX_GAIN_DB);
     }
-    let mut arr = [0.0_f32; BAND_COUNT];
-    for (i, &g) in gains.iter().enumerate() {
-        arr[i] = g.clamp(-12.0, 12.0);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_eq_bands(bands: Vec<EqBand>, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    if bands.len() > MAX_BANDS {
+        return Err(format!("at most {MAX_BANDS} bands"));
     }
-    audio.eq_settings.write().unwrap().gains = arr;
+    audio.effects.eq.write().unwrap().bands = bands.into_iter().map(EqBand::clamped).collect();
     Ok(())
 }
 
 #[tauri::command]
 pub async fn set_eq_enabled(enabled: bool, audio: State<'_, AudioHandle>) -> Result<(), String> {
-    audio.eq_settings.write().unwrap().enabled = enabled;
+    audio.effects.eq.write().unwrap().enabled = enabled;
     Ok(())
 }
 
 #[tauri::command]
 pub async fn get_eq_settings(audio: State<'_, AudioHandle>) -> Result<serde_json::Value, String> {
-    let s = audio.eq_settings.read().unwrap();
+    let s = audio.effects.eq.read().unwrap();
     Ok(serde_json::json!({
         "enabled": s.enabled,
-        "gains": s.gains.to_vec(),
+        "preamp_db": s.preamp_db,
+        "auto_preamp": s.auto_preamp,
+        "auto_preamp_db": equalizer::auto_preamp_db(&s.bands, 48_000.0),
+        "bands": s.bands,
     }))
 }
 
+#[derive(serde::Serialize)]
+pub struct ResponsePoint {
+    freq: f32,
+    db: f32,
+}
+
+/// The EQ's combined frequency response, for drawing its curve.
+#[tauri::command]
+pub async fn get_eq_response(points: Option<usize>, audio: State<'_, AudioHandle>) -> Result<Vec<ResponsePoint>, String> {
+    let bands = audio.effects.eq.read().unwrap().bands.clone();
+    Ok(equalizer::frequency_response(&bands, 48_000.0, points.unwrap_or(200).min(2000))
+        .into_iter()
+        .map(|(freq, db)| ResponsePoint { freq, db })
+        .collect())
+}
+
+#[tauri::command]
+pub async fn set_eq_preamp(db: f32, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    audio.effects.eq.write().unwrap().preamp_db = db.clamp(-MAX_GAIN_DB, MAX_GAIN_DB);
+    Ok(())
+}
+
+/// Pick the preamp from the bands so the biggest boost just reaches 0 dB.
+#[tauri::command]
+pub async fn set_eq_auto_preamp(enabled: bool, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    audio.effects.eq.write().unwrap().auto_preamp = enabled;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_limiter(
+    enabled: bool,
+    ceiling_db: Option<f32>,
+    audio: State<'_, AudioHandle>,
+) -> Result<(), String> {
+    let mut d = audio.effects.dynamics.write().unwrap();
+    d.limiter = enabled;
+    if let Some(db) = ceiling_db.filter(|db| db.is_finite()) {
+        d.ceiling_db = db.clamp(MIN_CEILING_DB, 0.0);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_night_mode(enabled: bool, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    audio.effects.dynamics.write().unwrap().night_mode = enabled;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_dynamics_settings(audio: State<'_, AudioHandle>) -> Result<DynamicsSettings, String> {
+    Ok(*audio.effects.dynamics.read().unwrap())
+}
+
+/// -1 plays only the left channel, 1 only the right.
+#[tauri::command]
+pub async fn set_balance(balance: f32, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    if !balance.is_finite() {
+        return Err("Invalid balance".into());
+    }
+    audio.effects.stereo.write().unwrap().balance = balance.clamp(-1.0, 1.0);
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_mono(enabled: bool, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    audio.effects.stereo.write().unwrap().mono = enabled;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_swap_channels(enabled: bool, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    audio.effects.stereo.write().unwrap().swap = enabled;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_crossfeed(enabled: bool, audio: State<'_, AudioHandle>) -> Result<(), String> {
+    audio.effects.stereo.write().unwrap().crossfeed = enabled;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_stereo_settings(audio: State<'_, AudioHandle>) -> Result<StereoSettings, String> {
+    Ok(*audio.effects.stereo.read().unwrap())
+}
+
+#[tauri::command]
+pub async fn list_eq_presets(db: State<'_, SearchCache>) -> Result<Vec<EqPreset>, String> {
+    db.list_eq_presets().map_err(|e| e.to_string())
+}
+
+/// Load a stored preset into the EQ and return it.
+#[tauri::command]
+pub async fn apply_eq_preset(
+    name: String,
+    audio: State<'_, AudioHandle>,
+    db: State<'_, SearchCache>,
+) -> Result<EqPreset, String> {
+    let preset = db
+        .get_eq_preset(&name)
+        .map_err(|e| e.to_string())?
+        .ok_or("Preset not found")?;
+    let mut eq = audio.effects.eq.write().unwrap();
+    eq.preamp_db = preset.preamp_db;
+    eq.bands = preset.bands.clone();
+    Ok(preset)
+}
+
+/// Store the EQ's current bands and preamp under `name`.
+#[tauri::command]
+pub async fn save_eq_preset(
+    name: String,
+    audio: State<'_, AudioHandle>,
+    db: State<'_, SearchCache>,
+) -> Result<EqPreset, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Preset name is empty".into());
+    }
+    let preset = {
+        let eq = audio.effects.eq.read().unwrap();
+        EqPreset {
+            name: name.to_string(),
+            preamp_db: eq.preamp_db,
+            bands: eq.bands.clone(),
+            builtin: false,
+        }
+    };
+    db.save_eq_preset(&preset).map_err(|e| e.to_string())?;
+    Ok(preset)
+}
+
+#[tauri::command]
+pub async fn delete_eq_preset(name: String, db: State<'_, SearchCache>) -> Result<(), String> {
+    db.delete_eq_preset(&name).map_err(|e| e.to_string())
+}
+
+/// Store an Equalizer APO config or AutoEQ ParametricEQ.txt as a preset,
+/// named after the file unless `name` is given.
+#[tauri::command]
+pub async fn import_eq_profile(
+    path: String,
+    name: Option<String>,
+    db: State<'_, SearchCache>,
+) -> Result<EqPreset, String> {
+    let text = std::fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let (preamp_db, bands) = eq_presets::parse_profile(&text).map_err(|e| e.to_string())?;
+    let name = name.filter(|n| !n.trim().is_empty()).unwrap_or_else(|| {
+        let stem = std::path::Path::new(&path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // AutoEQ names its files "<Headphone> ParametricEQ.txt".
+        stem.trim_end_matches("ParametricEQ").trim_end_matches([' ', '-', '_']).to_string()
+    });
+    if name.trim().is_empty() {
+        return Err("Preset name is empty".into());
+    }
+    let preset = EqPreset {
+        name: name.trim().to_string(),
+        preamp_db,
+        bands,
+        builtin: false,
+    };
+    db.save_eq_preset(&preset).map_err(|e| e.to_string())?;
+    Ok(preset)
+}
+
+#[tauri::command]
+pub async fn export_eq_preset(name: String, path: String, db: State<'_, SearchCache>) -> Result<(), String> {
+    let preset = db
+        .get_eq_preset(&name)
+        .map_err(|e| e.to_string())?
+        .ok_or("Preset not found")?;
+    std::fs::write(&path, eq_presets::write_profile(&preset)).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn set_repeat_mode(mode: String, audio: State<'_, AudioHandle>) -> Result<(), String> {
-    if !["off", "queue", "track"].contains(&mode.as_str()) {
+    let Some(repeat) = RepeatMode::from_name(&mode) else {
         return Err(format!("Invalid repeat mode: {}", mode));
-    }
-    audio.send(AudioCommand::SetRepeat(mode));
+    };
+    audio.send(AudioCommand::SetRepeat(repeat));
     Ok(())
 }
 
@@ -283,8 +673,7 @@ pub async fn prefetch_track(
 ) -> Result<(), String> {
     let cache_dir = std::env::temp_dir().join("sunder");
     let _ = std::fs::create_dir_all(&cache_dir);
-    let expected_path = cache_dir.join(format!("{track_id}.mp3"));
-    if expected_path.exists() {
+    if crate::downloads::find_audio(&cache_dir, &track_id).is_some() {
         return Ok(());
     }
     let bin = std::env::var("SUNDER_YTDLP_PATH").unwrap_or_else(|_| "yt-dlp".into());
@@ -294,9 +683,7 @@ pub async fn prefetch_track(
         let _ = tokio::process::Command::new(&bin)
             .args([
                 &url,
-                "--extract-audio",
-                "--audio-format", "mp3",
-                "--audio-quality", "2",
+                "-f", crate::audio::engine::AUDIO_FORMAT,
                 "-o", out_template.to_str().unwrap_or_default(),
                 "--no-playlist",
                 "--concurrent-fragments", "4",
@@ -348,6 +735,106 @@ pub fn set_lyric_offset(track_id: String, offset_ms: i64, db: State<'_, SearchCa
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub fn get_cue_points(track_id: String, db: State<'_, SearchCache>) -> CuePoints {
+    db.get_cue_points(&track_id).unwrap_or_default()
+}
+
+/// Takes effect the next time the track is played.
+#[tauri::command]
+pub fn set_cue_points(
+    track_id: String,
+    start_ms: Option<u64>,
+    end_ms: Option<u64>,
+    db: State<'_, SearchCache>,
+) -> Result<(), String> {
+    if let (Some(start), Some(end)) = (start_ms, end_ms) {
+        if start >= end {
+            return Err("End cue must come after the start cue".into());
+        }
+    }
+    db.set_cue_points(&track_id, &CuePoints { start_ms, end_ms })
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn list_bookmarks(track_id: String, db: State<'_, SearchCache>) -> Result<Vec<Bookmark>, String> {
+    db.list_bookmarks(&track_id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn add_bookmark(
+    track_id: String,
+    name: String,
+    position_ms: u64,
+    db: State<'_, SearchCache>,
+) -> Result<Bookmark, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Bookmark name is empty".into());
+    }
+    db.add_bookmark(&track_id, name, position_ms).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_bookmark(id: i64, db: State<'_, SearchCache>) -> Result<(), String> {
+    db.delete_bookmark(id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn list_alarms(db: State<'_, SearchCache>) -> Result<Vec<Alarm>, String> {
+    db.list_alarms().map_err(|e| e.to_string())
+}
+
+/// Create an alarm (id 0) or update one.
+#[tauri::command]
+pub fn save_alarm(alarm: Alarm, db: State<'_, SearchCache>) -> Result<Alarm, String> {
+    if alarm.hour > 23 || alarm.minute > 59 {
+        return Err("Alarm time is out of range".into());
+    }
+    if alarm.weekdays > 0b111_1111 {
+        return Err("Alarm weekdays are out of range".into());
+    }
+    if !(0.0..=1.0).contains(&alarm.volume) {
+        return Err("Alarm volume must be between 0 and 1".into());
+    }
+    let alarm = Alarm { label: alarm.label.trim().to_string(), ..alarm };
+    db.save_alarm(&alarm).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn delete_alarm(id: i64, db: State<'_, SearchCache>) -> Result<(), String> {
+    db.delete_alarm(id).map_err(|e| e.to_string())
+}
+
+/// One track per chapter of an upload, stored so they can be queued and
+/// added to playlists on their own.
+#[tauri::command]
+pub async fn split_chapters(
+    track_id: String,
+    db: State<'_, SearchCache>,
+    extractor: State<'_, Extractor>,
+) -> Result<Vec<Track>, String> {
+    if chapters::split_id(&track_id).1.is_some() {
+        return Err("This track is a chapter already".into());
+    }
+    let (upload, found) = match (db.get_track_by_id(&track_id), db.get_chapters(&track_id)) {
+        (Ok(Some(track)), Ok(Some(found))) => (track, found),
+        _ => {
+            let (track, found) = extractor.metadata(&track_id).await.map_err(|e| e.to_string())?;
+            db.upsert_tracks(std::slice::from_ref(&track)).map_err(|e| e.to_string())?;
+            db.set_chapters(&track.id, &found).map_err(|e| e.to_string())?;
+            (track, found)
+        }
+    };
+    if found.is_empty() {
+        return Err("This track has no chapters".into());
+    }
+    let tracks = chapters::chapter_tracks(&upload, &found);
+    db.upsert_tracks(&tracks).map_err(|e| e.to_string())?;
+    Ok(tracks)
+}
+
 #[tauri::command]
 pub async fn import_yt_playlist(
     url: String,
@@ -416,7 +903,11 @@ async fn resolve_track(
     if let Ok(Some(track)) = db.get_track_by_id(track_id) {
         return Ok(track);
     }
-    extractor.metadata(track_id).await.map_err(|e| e.to_string())
+    extractor
+        .metadata(track_id)
+        .await
+        .map(|(track, _)| track)
+        .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -443,7 +934,7 @@ pub async fn download_tracks(
     let resolved: HashSet<String> = tracks.iter().map(|t| t.id.clone()).collect();
     for id in &track_ids {
         if !resolved.contains(id) {
-            if let Ok(track) = extractor.metadata(id).await {
+            if let Ok((track, _)) = extractor.metadata(id).await {
                 tracks.push(track);
             }
         }
@@ -566,8 +1057,9 @@ pub async fn get_explore(
         })
     ).await;
 
+    let chaptered: HashSet<String> = db.chaptered_track_ids().unwrap_or_default().into_iter().collect();
     for (seed, tracks) in queries.into_iter().zip(results) {
-        let filtered = select_section_tracks(tracks, &mut seen_ids);
+        let filtered = select_section_tracks(tracks, &mut seen_ids, &chaptered);
         if !filtered.is_empty() {
             let _ = db.upsert_tracks(&filtered);
             sections.push(ExploreSection {
@@ -756,12 +1248,19 @@ fn append_unique(tracks: &mut Vec<Track>, seen: &mut HashSet<String>, incoming:
     }
 }
 
-fn select_section_tracks(tracks: Vec<Track>, seen_ids: &mut HashSet<String>) -> Vec<Track> {
+/// Uploads in `chaptered`, and full albums and mixes, are kept however long
+/// they are: they can be split into their songs. They rank last all the same.
+fn select_section_tracks(
+    tracks: Vec<Track>,
+    seen_ids: &mut HashSet<String>,
+    chaptered: &HashSet<String>,
+) -> Vec<Track> {
     let mut candidates: Vec<(i64, usize, Track)> = tracks
         .into_iter()
         .enumerate()
         .filter_map(|(index, track)| {
-            if seen_ids.contains(&track.id) || !looks_like_song(&track) {
+            let keep = looks_like_song(&track) || looks_like_album_or_mix(&track) || chaptered.contains(&track.id);
+            if seen_ids.contains(&track.id) || !keep {
                 return None;
             }
             Some((track_quality(&track, index), index, track))
@@ -866,6 +1365,15 @@ fn looks_like_song(track: &Track) -> bool {
     true
 }
 
+/// Full albums and DJ mixes, which search results don't carry chapters for
+/// but which usually have them.
+fn looks_like_album_or_mix(track: &Track) -> bool {
+    let title = track.title.to_lowercase();
+    ["full album", "complete album", "hour mix", "dj mix", "dj set", "live set"]
+        .iter()
+        .any(|needle| title.contains(needle))
+}
+
 fn clean_track_title(title: &str) -> String {
     let mut cleaned = title.to_string();
     for marker in ["(", "[", " - Official", " | Official"] {
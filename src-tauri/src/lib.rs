@@ -1,3 +1,4 @@
+mod alarms;
 mod audio;
 pub mod config;
 mod db;
@@ -11,6 +12,7 @@ pub mod models;
 use tauri::{Emitter, Manager};
 use crate::config::ConfigManager;
 use audio::AudioHandle;
+use audio::engine::AudioCommand;
 use db::SearchCache;
 use downloads::DownloadManager;
 use extraction::Extractor;
@@ -27,17 +29,23 @@ pub fn run() {
                 .app_data_dir()
                 .unwrap_or_else(|_| std::env::current_dir().unwrap().join("sunder_data"));
 
-            app.manage(SearchCache::new(&data_dir).expect("failed to init database"));
-            app.manage(AudioHandle::new(app.handle().clone()));
-            app.manage(Extractor::new());
-            app.manage(DownloadManager::new(&data_dir));
-
+            // Config first: the audio thread reads the output device from it.
             let config_mgr = ConfigManager::new(&data_dir);
             let drpc = discord::DiscordPresence::new();
             drpc.set_enabled(config_mgr.get().discord_rpc_enabled);
             app.manage(config_mgr);
             app.manage(drpc);
 
+            let cache = SearchCache::new(&data_dir).expect("failed to init database");
+            app.state::<ConfigManager>().migrate_saved_queue(&cache);
+            app.manage(cache);
+            app.manage(audio::queue::QueueSaver::start(app.handle().clone()));
+            app.manage(AudioHandle::new(app.handle().clone()));
+            audio::queue::restore(app.handle());
+            app.manage(Extractor::new());
+            app.manage(DownloadManager::new(&data_dir));
+            alarms::start(app.handle().clone());
+
             // System Tray Setup
             use tauri::menu::{Menu, MenuItem, PredefinedMenuItem};
             use tauri::tray::{TrayIconBuilder, TrayIconEvent, MouseButton};
@@ -70,10 +78,10 @@ pub fn run() {
                             let _ = app.emit("media-toggle", ());
                         }
                         "next" => {
-                            let _ = app.emit("media-next", ());
+                            app.state::<AudioHandle>().send(AudioCommand::Next);
                         }
                         "prev" => {
-                            let _ = app.emit("media-previous", ());
+                            app.state::<AudioHandle>().send(AudioCommand::Previous);
                         }
                         "show" => {
                             if let Some(window) = app.get_webview_window("main") {
@@ -113,17 +121,46 @@ pub fn run() {
             ipc::commands::search,
             ipc::commands::search_local,
             ipc::commands::play_track,
+            ipc::commands::get_queue,
+            ipc::commands::set_queue,
+            ipc::commands::queue_append,
+            ipc::commands::queue_insert_next,
+            ipc::commands::queue_remove,
+            ipc::commands::queue_move,
+            ipc::commands::queue_shuffle,
+            ipc::commands::queue_unshuffle,
+            ipc::commands::queue_clear,
+            ipc::commands::queue_play_index,
+            ipc::commands::next_track,
+            ipc::commands::previous_track,
+            ipc::commands::next_chapter,
+            ipc::commands::previous_chapter,
+            ipc::commands::save_queue_as_playlist,
             ipc::commands::get_subtitles,
             ipc::commands::get_lyrics_cache,
             ipc::commands::save_lyrics_cache,
             ipc::commands::get_lyric_offset,
             ipc::commands::set_lyric_offset,
+            ipc::commands::get_cue_points,
+            ipc::commands::set_cue_points,
+            ipc::commands::list_bookmarks,
+            ipc::commands::add_bookmark,
+            ipc::commands::delete_bookmark,
+            ipc::commands::list_alarms,
+            ipc::commands::save_alarm,
+            ipc::commands::delete_alarm,
+            ipc::commands::split_chapters,
             ipc::commands::import_yt_playlist,
             ipc::commands::pause,
             ipc::commands::resume,
             ipc::commands::stop,
             ipc::commands::set_volume,
             ipc::commands::seek,
+            ipc::commands::set_sleep_timer,
+            ipc::commands::set_ab_loop,
+            ipc::commands::clear_ab_loop,
+            ipc::commands::list_output_devices,
+            ipc::commands::set_output_device,
             ipc::commands::get_playback_state,
             ipc::commands::create_playlist,
             ipc::commands::list_playlists,
@@ -140,10 +177,31 @@ pub fn run() {
             ipc::commands::set_eq_gains,
             ipc::commands::set_eq_enabled,
             ipc::commands::get_eq_settings,
+            ipc::commands::set_eq_bands,
+            ipc::commands::get_eq_response,
+            ipc::commands::set_eq_preamp,
+            ipc::commands::set_eq_auto_preamp,
+            ipc::commands::set_limiter,
+            ipc::commands::set_night_mode,
+            ipc::commands::get_dynamics_settings,
+            ipc::commands::set_balance,
+            ipc::commands::set_mono,
+            ipc::commands::set_swap_channels,
+            ipc::commands::set_crossfeed,
+            ipc::commands::get_stereo_settings,
+            ipc::commands::list_eq_presets,
+            ipc::commands::apply_eq_preset,
+            ipc::commands::save_eq_preset,
+            ipc::commands::delete_eq_preset,
+            ipc::commands::import_eq_profile,
+            ipc::commands::export_eq_preset,
             ipc::commands::get_config,
             ipc::commands::set_config,
             ipc::commands::set_repeat_mode,
             ipc::commands::set_speed,
+            ipc::commands::set_speed_mode,
+            ipc::commands::set_pitch,
+            ipc::commands::set_spectrum_enabled,
             ipc::commands::export_playlist_json,
             ipc::commands::import_playlist_json,
             ipc::commands::set_discord_rpc,
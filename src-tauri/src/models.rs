@@ -7,10 +7,96 @@ pub struct Track {
     pub artist: String,
     pub thumbnail: String,
     pub duration_secs: f64,
+    /// Release the track belongs to, when YouTube Music reports one.
+    #[serde(default)]
+    pub album: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream_url: Option<String>,
 }
 
+/// A bare track titled after its id, for tests.
+#[cfg(test)]
+pub fn track(id: &str) -> Track {
+    Track {
+        id: id.into(),
+        title: id.into(),
+        artist: String::new(),
+        thumbnail: String::new(),
+        duration_secs: 0.0,
+        album: String::new(),
+        stream_url: None,
+    }
+}
+
+/// Part of a track the user wants played, in ms into the file. Either end
+/// may be left open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CuePoints {
+    pub start_ms: Option<u64>,
+    pub end_ms: Option<u64>,
+}
+
+/// Where a long track was left, and the speed it was playing at.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ResumePoint {
+    pub position_ms: u64,
+    pub speed: f32,
+}
+
+/// A named position inside a track.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub id: i64,
+    pub track_id: String,
+    pub name: String,
+    pub position_ms: u64,
+}
+
+/// A playlist set to start at a time of day.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alarm {
+    pub id: i64,
+    pub label: String,
+    pub playlist_id: i64,
+    /// Local time it goes off.
+    pub hour: u8,
+    pub minute: u8,
+    /// Days it repeats on, bit 0 for Sunday through bit 6 for Saturday;
+    /// none goes off once and then disables itself.
+    pub weekdays: u8,
+    /// Seconds the volume takes to rise to `volume`; 0 starts right at it.
+    pub ramp_secs: u32,
+    pub volume: f32,
+    pub enabled: bool,
+}
+
+/// The local wall clock, to the minute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalTime {
+    /// "YYYY-MM-DD HH:MM", telling this minute from every other.
+    pub stamp: String,
+    /// 0 for Sunday through 6 for Saturday.
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+}
+
+/// A titled section of an upload, in ms into the file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Chapter {
+    pub title: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+/// A stretch of a video submitted to SponsorBlock, in ms into the file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Segment {
+    pub category: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResult {
     pub tracks: Vec<Track>,
@@ -2,11 +2,12 @@ import { invoke } from "@tauri-apps/api/core";
 import { listen } from "@tauri-apps/api/event";
 import { getVersion } from "@tauri-apps/api/app";
 import { save, open } from "@tauri-apps/plugin-dialog";
-import type { Track, SearchResult, PlaybackProgress, Playlist, ExploreData, EqSettings, DownloadEvent } from "../types";
+import type { Track, SearchResult, PlaybackProgress, Playlist, ExploreData, EqBand, EqSettings, EqPreset, DownloadEvent, OutputDevice, QueueSnapshot, AudioSpectrum, CuePoints, AbLoop, Bookmark, Chapter, Segment, SleepMode, SleepStatus, Alarm } from "../types";
 import { player } from "../state/player.svelte";
 import { config } from "../state/config.svelte";
 import { lyricsState, parseLrc } from "../state/lyrics.svelte";
 import { downloads } from "../state/downloads.svelte";
+import { toastState } from "../state/toast.svelte";
 
 export async function search(query: string, limit = 20): Promise<SearchResult> {
   return invoke<SearchResult>("search", { query, limit });
@@ -29,17 +30,6 @@ export async function playTrack(track: Track): Promise<void> {
   player.isBuffering = true;
   player.downloadPercent = 0;
   player.downloadStage = "preparing";
-  const idx = player.queue.findIndex((t) => t.id === track.id);
-  if (idx !== -1) {
-    player.queueIndex = idx;
-  } else {
-    const insertAt = player.queueIndex + 1;
-    const updated = [...player.queue];
-    updated.splice(insertAt, 0, track);
-    player.queue = updated;
-    player.queueIndex = insertAt;
-  }
-  player.prefetchAhead(player.queueIndex);
   await invoke("play_track", { trackId: track.id });
   // Lazy lyrics: only fetch if the lyrics panel is already open
   if (lyricsState.visible) {
@@ -47,31 +37,53 @@ export async function playTrack(track: Track): Promise<void> {
   }
 }
 
-let advancing = false;
+export async function playNext(): Promise<void> {
+  await invoke("next_track");
+}
 
-export async function playNext(manual = false): Promise<void> {
-  if (advancing) return;
-  advancing = true;
-  try {
-    const next = player.nextTrack(manual);
-    if (next) {
-      await playTrack(next);
-    }
-  } finally {
-    advancing = false;
-  }
+export async function playPrev(): Promise<void> {
+  await invoke("previous_track");
 }
 
-export async function playPrev(manual = false): Promise<void> {
-  if (player.currentTime > 5.0) {
-    await seek(0);
-    player.currentTime = 0;
-    return;
-  }
-  const prev = player.prevTrack(manual);
-  if (prev) {
-    await playTrack(prev);
-  }
+/** Replace the queue with `tracks` and start playing the one at `index`. */
+export async function playQueue(tracks: Track[], index = 0): Promise<void> {
+  await invoke("set_queue", { tracks, index, play: true });
+}
+
+export async function queueAppend(tracks: Track[]): Promise<void> {
+  await invoke("queue_append", { tracks });
+}
+
+export async function queueInsertNext(tracks: Track[]): Promise<void> {
+  await invoke("queue_insert_next", { tracks });
+}
+
+export async function queueRemove(index: number): Promise<void> {
+  await invoke("queue_remove", { index });
+}
+
+export async function queueMove(from: number, to: number): Promise<void> {
+  await invoke("queue_move", { from, to });
+}
+
+export async function queueShuffle(): Promise<void> {
+  await invoke("queue_shuffle");
+}
+
+export async function queueUnshuffle(): Promise<void> {
+  await invoke("queue_unshuffle");
+}
+
+export async function queueClear(): Promise<void> {
+  await invoke("queue_clear");
+}
+
+export async function queuePlayIndex(index: number): Promise<void> {
+  await invoke("queue_play_index", { index });
+}
+
+export async function saveQueueAsPlaylist(name: string): Promise<Playlist> {
+  return invoke<Playlist>("save_queue_as_playlist", { name });
 }
 
 export async function pause(): Promise<void> {
@@ -94,13 +106,58 @@ export async function setVolume(volume: number): Promise<void> {
 
 export async function setSpeed(speed: number): Promise<void> {
   player.speed = speed;
+  player.trackSpeed = null;
   await invoke("set_speed", { speed });
 }
 
+export async function setSpeedMode(mode: "resample" | "stretch"): Promise<void> {
+  await invoke("set_speed_mode", { mode });
+}
+
+export async function setPitch(semitones: number): Promise<void> {
+  await invoke("set_pitch", { semitones });
+}
+
+/** Receive `audio-spectrum` events until the returned function is called. */
+export async function subscribeSpectrum(onFrame: (s: AudioSpectrum) => void): Promise<() => void> {
+  const unlisten = await listen<AudioSpectrum>("audio-spectrum", (event) => onFrame(event.payload));
+  await invoke("set_spectrum_enabled", { enabled: true });
+  return () => {
+    unlisten();
+    invoke("set_spectrum_enabled", { enabled: false }).catch(() => {});
+  };
+}
+
 export async function seek(positionSecs: number): Promise<void> {
   await invoke("seek", { positionSecs });
 }
 
+export async function nextChapter(): Promise<void> {
+  await invoke("next_chapter");
+}
+
+export async function previousChapter(): Promise<void> {
+  await invoke("previous_chapter");
+}
+
+/** Store one track per chapter of an upload and return them. */
+export async function splitChapters(trackId: string): Promise<Track[]> {
+  return invoke<Track[]>("split_chapters", { trackId });
+}
+
+/** Start the sleep timer, or cancel it with null. */
+export async function setSleepTimer(mode: SleepMode | null): Promise<void> {
+  await invoke("set_sleep_timer", { mode });
+}
+
+export async function setAbLoop(aMs: number, bMs: number): Promise<void> {
+  await invoke("set_ab_loop", { aMs, bMs });
+}
+
+export async function clearAbLoop(): Promise<void> {
+  await invoke("clear_ab_loop");
+}
+
 export async function prefetchTrack(trackId: string): Promise<void> {
   await invoke("prefetch_track", { trackId });
 }
@@ -161,6 +218,86 @@ export async function getEqSettings(): Promise<EqSettings> {
   return invoke<EqSettings>("get_eq_settings");
 }
 
+export async function setEqBands(bands: EqBand[]): Promise<void> {
+  await invoke("set_eq_bands", { bands });
+}
+
+/** The EQ's combined response as (Hz, dB) points, 20 Hz to 20 kHz. */
+export async function getEqResponse(points?: number): Promise<{ freq: number; db: number }[]> {
+  return invoke("get_eq_response", { points });
+}
+
+export async function setEqPreamp(db: number): Promise<void> {
+  await invoke("set_eq_preamp", { db });
+}
+
+/** Derive the preamp from the bands so boosts never push past 0 dBFS. */
+export async function setEqAutoPreamp(enabled: boolean): Promise<void> {
+  await invoke("set_eq_auto_preamp", { enabled });
+}
+
+export async function setLimiter(enabled: boolean, ceilingDb?: number): Promise<void> {
+  await invoke("set_limiter", { enabled, ceilingDb });
+}
+
+export async function setNightMode(enabled: boolean): Promise<void> {
+  await invoke("set_night_mode", { enabled });
+}
+
+/** -1 plays only the left channel, 1 only the right. */
+export async function setBalance(balance: number): Promise<void> {
+  await invoke("set_balance", { balance });
+}
+
+export async function setMono(enabled: boolean): Promise<void> {
+  await invoke("set_mono", { enabled });
+}
+
+export async function setSwapChannels(enabled: boolean): Promise<void> {
+  await invoke("set_swap_channels", { enabled });
+}
+
+export async function setCrossfeed(enabled: boolean): Promise<void> {
+  await invoke("set_crossfeed", { enabled });
+}
+
+export async function listEqPresets(): Promise<EqPreset[]> {
+  return invoke<EqPreset[]>("list_eq_presets");
+}
+
+export async function applyEqPreset(name: string): Promise<EqPreset> {
+  return invoke<EqPreset>("apply_eq_preset", { name });
+}
+
+export async function saveEqPreset(name: string): Promise<EqPreset> {
+  return invoke<EqPreset>("save_eq_preset", { name });
+}
+
+export async function deleteEqPreset(name: string): Promise<void> {
+  await invoke("delete_eq_preset", { name });
+}
+
+/** Pick an Equalizer APO config or AutoEQ ParametricEQ.txt and store it as a preset. */
+export async function importEqProfile(): Promise<EqPreset | null> {
+  const path = await open({
+    filters: [{ name: "EQ profile", extensions: ["txt"] }],
+    multiple: false,
+    directory: false,
+  });
+  if (!path) return null;
+  return invoke<EqPreset>("import_eq_profile", { path });
+}
+
+export async function exportEqPreset(name: string): Promise<boolean> {
+  const path = await save({
+    defaultPath: `${name.replace(/[^a-zA-Z0-9_-]/g, "_")} ParametricEQ.txt`,
+    filters: [{ name: "EQ profile", extensions: ["txt"] }],
+  });
+  if (!path) return false;
+  await invoke("export_eq_preset", { name, path });
+  return true;
+}
+
 export async function setRepeatMode(mode: "off" | "queue" | "track"): Promise<void> {
   await invoke("set_repeat_mode", { mode });
 }
@@ -194,6 +331,14 @@ export async function setDiscordRpc(enabled: boolean, track?: Track): Promise<vo
   });
 }
 
+export async function listOutputDevices(): Promise<OutputDevice[]> {
+  return invoke<OutputDevice[]>("list_output_devices");
+}
+
+export async function setOutputDevice(name: string): Promise<void> {
+  await invoke("set_output_device", { name });
+}
+
 export async function getTracksByIds(trackIds: string[]): Promise<Track[]> {
   return invoke<Track[]>("get_tracks_by_ids", { trackIds });
 }
@@ -239,16 +384,9 @@ export async function loadDownloads(): Promise<void> {
   }
 }
 
-export async function restoreQueue(): Promise<void> {
-  const { saved_queue, saved_queue_index } = config.current;
-  if (!saved_queue?.length) return;
-  const tracks = await getTracksByIds(saved_queue);
-  if (tracks.length === 0) return;
-  player.queue = tracks;
-  player.queueIndex = Math.min(Math.max(saved_queue_index ?? -1, -1), tracks.length - 1);
-  if (player.queueIndex >= 0) {
-    player.currentTrack = tracks[player.queueIndex];
-  }
+/** Pick up the queue the backend kept from last session. */
+export async function loadQueue(): Promise<void> {
+  player.applyQueue(await invoke<QueueSnapshot>("get_queue"));
 }
 
 export interface UpdateInfo {
@@ -305,12 +443,16 @@ export async function openUrl(url: string): Promise<void> {
 export function initProgressListener(): () => void {
   let unlistenProgress: (() => void) | undefined;
   let unlistenDownload: (() => void) | undefined;
-  let unlistenFinished: (() => void) | undefined;
+  let unlistenQueue: (() => void) | undefined;
   let unlistenError: (() => void) | undefined;
-  let unlistenNext: (() => void) | undefined;
-  let unlistenPrev: (() => void) | undefined;
   let unlistenToggle: (() => void) | undefined;
   let unlistenTrackDownload: (() => void) | undefined;
+  let unlistenAbLoop: (() => void) | undefined;
+  let unlistenChapters: (() => void) | undefined;
+  let unlistenChapter: (() => void) | undefined;
+  let unlistenSegment: (() => void) | undefined;
+  let unlistenSleepTimer: (() => void) | undefined;
+  let unlistenAlarm: (() => void) | undefined;
 
   listen<PlaybackProgress>("playback-progress", (event) => {
     player.updateFromProgress(event.payload);
@@ -325,9 +467,9 @@ export function initProgressListener(): () => void {
     player.downloadStage = event.payload.stage;
   }).then((fn) => { unlistenDownload = fn; });
 
-  listen("track-finished", () => {
-    playNext().catch((e) => console.error("Failed to play next track after finish:", e));
-  }).then((fn) => { unlistenFinished = fn; });
+  listen<QueueSnapshot>("queue-changed", (event) => {
+    player.applyQueue(event.payload);
+  }).then((fn) => { unlistenQueue = fn; });
 
   listen<{ video_id: string; error: string }>("playback-error", (event) => {
     const failedId = event.payload.video_id;
@@ -346,13 +488,31 @@ export function initProgressListener(): () => void {
     }
   }).then((fn) => { unlistenError = fn; });
 
-  listen("media-next", () => {
-    playNext(true).catch((e) => console.error("Media key next failed:", e));
-  }).then((fn) => { unlistenNext = fn; });
+  listen<AbLoop | null>("ab-loop", (event) => {
+    player.abLoop = event.payload;
+    player.abLoopStart = null;
+  }).then((fn) => { unlistenAbLoop = fn; });
+
+  listen<Chapter[]>("chapters", (event) => {
+    player.chapters = event.payload;
+  }).then((fn) => { unlistenChapters = fn; });
+
+  listen<number | null>("chapter", (event) => {
+    player.chapterIndex = event.payload;
+  }).then((fn) => { unlistenChapter = fn; });
+
+  listen<Segment>("segment-skipped", (event) => {
+    const skipped = Math.round((event.payload.end_ms - event.payload.start_ms) / 1000);
+    toastState.add(`Skipped ${skipped}s of ${event.payload.category.replace(/_/g, " ")}`, "info", 2500);
+  }).then((fn) => { unlistenSegment = fn; });
 
-  listen("media-previous", () => {
-    playPrev(true).catch((e) => console.error("Media key previous failed:", e));
-  }).then((fn) => { unlistenPrev = fn; });
+  listen<SleepStatus | null>("sleep-timer", (event) => {
+    player.sleepTimer = event.payload;
+  }).then((fn) => { unlistenSleepTimer = fn; });
+
+  listen<Alarm>("alarm", (event) => {
+    toastState.add(`Alarm: ${event.payload.label || "playlist started"}`, "info", 5000);
+  }).then((fn) => { unlistenAlarm = fn; });
 
   listen("media-toggle", () => {
     if (player.isPlaying) {
@@ -365,12 +525,16 @@ export function initProgressListener(): () => void {
   return () => {
     unlistenProgress?.();
     unlistenDownload?.();
-    unlistenFinished?.();
+    unlistenQueue?.();
     unlistenError?.();
-    unlistenNext?.();
-    unlistenPrev?.();
     unlistenToggle?.();
     unlistenTrackDownload?.();
+    unlistenAbLoop?.();
+    unlistenChapters?.();
+    unlistenChapter?.();
+    unlistenSegment?.();
+    unlistenSleepTimer?.();
+    unlistenAlarm?.();
   };
 }
 
@@ -467,6 +631,39 @@ export async function setLyricOffset(trackId: string, offsetMs: number): Promise
   await invoke("set_lyric_offset", { trackId, offsetMs });
 }
 
+export async function getCuePoints(trackId: string): Promise<CuePoints> {
+  return invoke<CuePoints>("get_cue_points", { trackId });
+}
+
+export async function setCuePoints(trackId: string, startMs: number | null, endMs: number | null): Promise<void> {
+  await invoke("set_cue_points", { trackId, startMs, endMs });
+}
+
+export async function listBookmarks(trackId: string): Promise<Bookmark[]> {
+  return invoke<Bookmark[]>("list_bookmarks", { trackId });
+}
+
+export async function addBookmark(trackId: string, name: string, positionMs: number): Promise<Bookmark> {
+  return invoke<Bookmark>("add_bookmark", { trackId, name, positionMs });
+}
+
+export async function deleteBookmark(id: number): Promise<void> {
+  await invoke("delete_bookmark", { id });
+}
+
+export async function listAlarms(): Promise<Alarm[]> {
+  return invoke<Alarm[]>("list_alarms");
+}
+
+/** Create an alarm (id 0) or update one. */
+export async function saveAlarm(alarm: Alarm): Promise<Alarm> {
+  return invoke<Alarm>("save_alarm", { alarm });
+}
+
+export async function deleteAlarm(id: number): Promise<void> {
+  await invoke("delete_alarm", { id });
+}
+
 async function tryLrclib(artist: string, title: string, duration?: number): Promise<boolean> {
   try {
     let url = `https://lrclib.net/api/get?artist=${encodeURIComponent(artist)}&track_name=${encodeURIComponent(title)}`;
@@ -1,29 +1,80 @@
 import { invoke } from "@tauri-apps/api/core";
-import { player } from "./player.svelte";
-import { setVolume, setEqEnabled, setEqGains, setSpeed, setRepeatMode } from "../ipc/bridge";
+import { player, graphicBands } from "./player.svelte";
+import type { EqBand } from "../types";
+import { setVolume, setEqEnabled, setEqBands, setEqPreamp, setEqAutoPreamp, setLimiter, setNightMode, setBalance, setMono, setSwapChannels, setCrossfeed, setSpeed, setSpeedMode, setPitch, setRepeatMode } from "../ipc/bridge";
 
 export interface AppConfig {
   volume: number;
   eq_enabled: boolean;
-  eq_gains: number[];
+  eq_bands: EqBand[];
+  eq_preamp_db: number;
+  eq_auto_preamp: boolean;
+  eq_preset: string;
   notifications_enabled: boolean;
   discord_rpc_enabled: boolean;
-  saved_queue: string[];
-  saved_queue_index: number;
   repeat_mode: "off" | "queue" | "track";
   playback_speed: number;
+  speed_mode: "resample" | "stretch";
+  pitch_semitones: number;
+  crossfade_secs: number;
+  crossfade_curve: "equal_power" | "linear";
+  normalization_mode: "off" | "track" | "album";
+  normalization_target_lufs: number;
+  normalization_fallback_db: number;
+  limiter_enabled: boolean;
+  limiter_ceiling_db: number;
+  night_mode: boolean;
+  balance: number;
+  mono: boolean;
+  swap_channels: boolean;
+  crossfeed: boolean;
+  trim_silence: boolean;
+  silence_threshold_db: number;
+  resume_min_minutes: number;
+  sponsorblock_enabled: boolean;
+  sponsorblock_categories: string[];
+  sponsorblock_api: string;
+  sponsorblock_cut_downloads: boolean;
+  sleep_fade_secs: number;
+  output_device: string;
+  output_backend: string;
 }
 
 const defaults: AppConfig = {
   volume: 0.8,
   eq_enabled: false,
-  eq_gains: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+  eq_bands: graphicBands([]),
+  eq_preamp_db: 0,
+  eq_auto_preamp: false,
+  eq_preset: "Flat",
   notifications_enabled: true,
   discord_rpc_enabled: false,
-  saved_queue: [],
-  saved_queue_index: -1,
   repeat_mode: "off",
   playback_speed: 1.0,
+  speed_mode: "resample",
+  pitch_semitones: 0,
+  crossfade_secs: 0,
+  crossfade_curve: "equal_power",
+  normalization_mode: "off",
+  normalization_target_lufs: -14,
+  normalization_fallback_db: -6,
+  limiter_enabled: true,
+  limiter_ceiling_db: -1,
+  night_mode: false,
+  balance: 0,
+  mono: false,
+  swap_channels: false,
+  crossfeed: false,
+  trim_silence: false,
+  silence_threshold_db: -50,
+  resume_min_minutes: 20,
+  sponsorblock_enabled: false,
+  sponsorblock_categories: ["music_offtopic"],
+  sponsorblock_api: "https://sponsor.ajay.app",
+  sponsorblock_cut_downloads: false,
+  sleep_fade_secs: 30,
+  output_device: "",
+  output_backend: "device",
 };
 
 class ConfigState {
@@ -41,14 +92,17 @@ class ConfigState {
     // can't overwrite them via updateFromProgress().
     const savedVolume = this.current.volume;
     const savedEqEnabled = this.current.eq_enabled;
-    const savedEqGains = [...this.current.eq_gains];
+    const savedEqBands = $state.snapshot(this.current.eq_bands);
+    const savedEqPreamp = this.current.eq_preamp_db;
     const savedRepeatMode = this.current.repeat_mode;
     const savedSpeed = this.current.playback_speed;
 
     // Sync into player state
     player.volume = savedVolume;
     player.eqEnabled = savedEqEnabled;
-    player.eqGains = savedEqGains;
+    player.eqBands = savedEqBands;
+    player.eqPreamp = savedEqPreamp;
+    player.eqPreset = this.current.eq_preset || "Custom";
     player.repeatMode = savedRepeatMode;
     player.speed = savedSpeed;
 
@@ -57,10 +111,20 @@ class ConfigState {
     // setVolume/setEq carry the correct speed value.
     try {
       await setSpeed(savedSpeed);
+      await setSpeedMode(this.current.speed_mode);
+      await setPitch(this.current.pitch_semitones);
       await setRepeatMode(savedRepeatMode);
       await setVolume(savedVolume);
       await setEqEnabled(savedEqEnabled);
-      await setEqGains(savedEqGains);
+      await setEqBands(savedEqBands);
+      await setEqPreamp(savedEqPreamp);
+      await setEqAutoPreamp(this.current.eq_auto_preamp);
+      await setLimiter(this.current.limiter_enabled, this.current.limiter_ceiling_db);
+      await setNightMode(this.current.night_mode);
+      await setBalance(this.current.balance);
+      await setMono(this.current.mono);
+      await setSwapChannels(this.current.swap_channels);
+      await setCrossfeed(this.current.crossfeed);
     } catch (e) {
       console.error("Failed to sync config to backend:", e);
     }
@@ -92,15 +156,15 @@ $effect.root(() => {
     if (!config.loaded) return;
     const volume = player.volume;
     const eq_enabled = player.eqEnabled;
-    const eq_gains = $state.snapshot(player.eqGains);
-    const saved_queue = player.queue.map(t => t.id);
-    const saved_queue_index = player.queueIndex;
+    const eq_bands = $state.snapshot(player.eqBands);
+    const eq_preamp_db = player.eqPreamp;
+    const eq_preset = player.eqPreset === "Custom" ? "" : player.eqPreset;
     const repeat_mode = player.repeatMode;
     const playback_speed = player.speed;
 
     clearTimeout(saveTimer);
     saveTimer = setTimeout(() => {
-      config.update({ volume, eq_enabled, eq_gains, saved_queue, saved_queue_index, repeat_mode, playback_speed });
+      config.update({ volume, eq_enabled, eq_bands, eq_preamp_db, eq_preset, repeat_mode, playback_speed });
     }, 300);
   });
 });
@@ -4,6 +4,7 @@ export interface Track {
   artist: string;
   thumbnail: string;
   duration_secs: number;
+  album?: string;
   stream_url?: string;
 }
 
@@ -18,6 +19,8 @@ export interface PlaybackProgress {
   state: string;
   volume: number;
   speed: number;
+  /** Speed the current track resumed at, playing in place of `speed`. */
+  track_speed: number | null;
 }
 
 export interface Playlist {
@@ -36,9 +39,103 @@ export interface ExploreData {
   sections: ExploreSection[];
 }
 
+export type FilterKind = "peaking" | "low_shelf" | "high_shelf" | "low_pass" | "high_pass" | "notch";
+
+export interface EqBand {
+  kind: FilterKind;
+  freq: number;
+  gain_db: number;
+  q: number;
+}
+
 export interface EqSettings {
   enabled: boolean;
-  gains: number[];
+  preamp_db: number;
+  auto_preamp: boolean;
+  /** What the automatic preamp works out to for the current bands. */
+  auto_preamp_db: number;
+  bands: EqBand[];
+}
+
+export interface EqPreset {
+  name: string;
+  preamp_db: number;
+  bands: EqBand[];
+  builtin: boolean;
+}
+
+/** Part of a track to play, in ms into the file; null ends are open. */
+export interface CuePoints {
+  start_ms: number | null;
+  end_ms: number | null;
+}
+
+export interface Bookmark {
+  id: number;
+  track_id: string;
+  name: string;
+  position_ms: number;
+}
+
+export interface Chapter {
+  title: string;
+  start_ms: number;
+  end_ms: number;
+}
+
+export interface Alarm {
+  id: number;
+  label: string;
+  playlist_id: number;
+  hour: number;
+  minute: number;
+  /** Bit 0 for Sunday through bit 6 for Saturday; 0 goes off once. */
+  weekdays: number;
+  ramp_secs: number;
+  volume: number;
+  enabled: boolean;
+}
+
+export type SleepMode =
+  | { mode: "duration"; secs: number }
+  | { mode: "end_of_track" }
+  | { mode: "tracks"; count: number };
+
+export interface SleepStatus {
+  mode: SleepMode;
+  remaining_secs: number | null;
+  tracks_left: number | null;
+  fading: boolean;
+}
+
+export interface Segment {
+  category: string;
+  start_ms: number;
+  end_ms: number;
+}
+
+export interface AbLoop {
+  a_ms: number;
+  b_ms: number;
+}
+
+export interface QueueSnapshot {
+  tracks: Track[];
+  index: number;
+  shuffled: boolean;
+  repeat: "off" | "queue" | "track";
+}
+
+/** One `audio-spectrum` event; every level is in dBFS. */
+export interface AudioSpectrum {
+  bands: number[];
+  rms: number[];
+  peak: number[];
+}
+
+export interface OutputDevice {
+  name: string;
+  is_default: boolean;
 }
 
 export type DownloadStatus = "queued" | "downloading" | "converting" | "done" | "error";